@@ -0,0 +1,169 @@
+//! Measures how long `open_in_helix` takes end to end, including spawning the `kitten @`
+//! processes. A fake `kitten` script is put on the `PATH`, it answers `ls` with a single window
+//! running helix and accepts every other command, so the timings are dominated by the number of
+//! remote control calls. The same is measured with the commands sent over a socket, to a fake kitty
+//! answering the same way.
+//!
+//! The `(before)` rows are the baseline: how files were opened before the keystrokes were batched,
+//! with an `ls`, a `focus-window` and four `send-text` calls for every file.
+//!
+//! Run with `cargo bench`.
+
+use std::{
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use kitty_remote_bindings::command::options::Matcher;
+use serde_json::Value;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixListener,
+};
+
+use felis::{
+    command::open_in_helix,
+    config::Config,
+    fs::{AbsolutePath, FileLocation},
+    kitty_terminal::KittyTerminal,
+    Result,
+};
+
+const ITERATIONS: u32 = 50;
+
+const LS_OUTPUT: &str = r#"[{"id": 1, "is_active": true, "is_focused": true, "tabs": [{
+    "id": 1, "is_active": true, "is_focused": true, "windows": [
+        {"id": 1, "is_active": true, "is_focused": true, "foreground_processes": [
+            {"pid": 2, "cwd": "/bench/project", "cmdline": ["hx"]}
+        ]}
+    ]
+}]}]"#;
+
+fn fake_kitten(dir: &Path) -> std::io::Result<()> {
+    let ls_output = dir.join("ls.json");
+    std::fs::write(&ls_output, LS_OUTPUT)?;
+
+    let script = dir.join("kitten");
+    std::fs::write(
+        &script,
+        format!(
+            "#!/bin/sh\n# kitten @ --to <socket> <command> ...\n[ \"$4\" = ls ] && cat {}\nexit 0\n",
+            ls_output.display()
+        ),
+    )?;
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755))
+}
+
+/// Answers `ls` with a single window running helix, and accepts every other command
+fn fake_kitty(listener: UnixListener) {
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut request = Vec::new();
+            let mut buffer = [0; 4096];
+            while let Ok(read @ 1..) = stream.read(&mut buffer).await {
+                request.extend_from_slice(&buffer[..read]);
+                // Every request ends with `ESC \`
+                let Some(end) = request.windows(2).position(|w| w == b"\x1b\\") else {
+                    continue;
+                };
+                let json = serde_json::from_slice::<Value>(&request[12..end]).expect("JSON");
+                let response = if json["cmd"] == "ls" {
+                    serde_json::json!({"ok": true, "data": LS_OUTPUT})
+                } else {
+                    serde_json::json!({"ok": true})
+                };
+                let response = format!("\x1bP@kitty-cmd{response}\x1b\\");
+                if stream.write_all(response.as_bytes()).await.is_err() {
+                    break;
+                }
+                request.drain(..end + 2);
+            }
+        }
+    });
+}
+
+/// Opens the files the way felis did before batching the keystrokes
+async fn open_one_by_one(
+    kitty: &KittyTerminal,
+    locations: &[FileLocation<AbsolutePath>],
+) -> Result<()> {
+    for location in locations {
+        let windows = kitty.ls().await?;
+        let window = windows.0[0].tabs[0].windows[0].id;
+        kitty.focus_window(Matcher::Id(window)).await?;
+        kitty.send_text(Matcher::Id(window), r"\E").await?;
+        kitty.send_text(Matcher::Id(window), r":").await?;
+        kitty
+            .send_text(
+                Matcher::Id(window),
+                &location.path.as_ref().to_string_lossy(),
+            )
+            .await?;
+        kitty.send_text(Matcher::Id(window), r"\x01open \r").await?;
+    }
+    Ok(())
+}
+
+async fn bench(
+    name: &str,
+    kitty: &KittyTerminal,
+    locations: &[FileLocation<AbsolutePath>],
+) -> Result<()> {
+    let config = Config::default();
+
+    for (before, name) in [
+        (true, format!("{name} (before)")),
+        (false, name.to_string()),
+    ] {
+        let mut total = Duration::ZERO;
+        for _ in 0..ITERATIONS {
+            let start = Instant::now();
+            if before {
+                open_one_by_one(kitty, locations).await?;
+            } else {
+                open_in_helix(locations, None, kitty, None, &config).await?;
+            }
+            total += start.elapsed();
+        }
+
+        println!(
+            "{name:<37} {:>8.2} ms/iter ({ITERATIONS} iterations)",
+            total.as_secs_f64() * 1000.0 / f64::from(ITERATIONS)
+        );
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    fake_kitten(dir.path())?;
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let mut paths = vec![dir.path().to_path_buf()];
+    paths.extend(std::env::split_paths(&path));
+    std::env::set_var("PATH", std::env::join_paths(paths).expect("valid PATH"));
+
+    let location = |location: &str| FileLocation::from(location).try_into();
+    let one_file = [location("/bench/project/src/lib.rs")?];
+    let line_range = [location("/bench/project/src/lib.rs:10-20")?];
+    let three_files = [
+        location("/bench/project/src/lib.rs")?,
+        location("/bench/project/src/fs.rs:3")?,
+        location("/bench/project/README.md")?,
+    ];
+
+    let kitten = KittyTerminal::new("unix:/bench/kitty.sock".to_string());
+    bench("open one file", &kitten, &one_file).await?;
+    bench("open a line range", &kitten, &line_range).await?;
+    bench("open three files", &kitten, &three_files).await?;
+
+    let socket = dir.path().join("kitty.sock");
+    fake_kitty(UnixListener::bind(&socket)?);
+    let socket = KittyTerminal::connect(format!("unix:{}", socket.display()), None).await;
+    bench("open one file (socket)", &socket, &one_file).await?;
+    bench("open a line range (socket)", &socket, &line_range).await?;
+    bench("open three files (socket)", &socket, &three_files).await?;
+
+    Ok(())
+}
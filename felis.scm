@@ -1,11 +1,17 @@
 (require (prefix-in helix. "helix/commands.scm"))
 
 (require "helix/editor.scm")
+(require "helix/misc.scm")
 
-(provide felis-open
+(provide felis-protocol-version
+         felis-open
          felis-file-browser
          felis-file-browser-cwd)
 
+;; The version of felis' protocol this plugin speaks, felis reads it from this file before handing
+;; anything over, see `felis::plugin`
+(define felis-protocol-version 1)
+
 ;; Utils
 
 (define (current-doc-path)
@@ -15,9 +21,60 @@
 
 ;; Commands
 
-(define (felis-open)
-  (let ((path ( ~> (open-input-file "/tmp/felis-open.txt") (read-port-to-string))))
-    (helix.open path)))
+;; JSON objects are read as hash maps
+(define (json-ref object key)
+  (let ([value (hash-try-get object (string->symbol key))])
+    (if value value (hash-try-get object key))))
+
+;; The typed commands felis can run, by name. Anything else is refused, the names in a message
+;; are never evaluated. More commands can be added as `"name" procedure` entries, felis reads the
+;; names from here, see `felis::plugin::commands`.
+(define felis-commands
+  (hash "reload" helix.reload
+        "reload-all" helix.reload-all
+        "write" helix.write
+        "theme" helix.theme
+        "buffer-close" helix.buffer-close))
+
+;; The path followed by the position, the way `:open` understands it. A range is opened at its first
+;; line, felis selects the rest of it once the file is shown.
+(define (open-target request)
+  (let ([path (json-ref request "path")]
+        [line (json-ref request "line")]
+        [column (json-ref request "column")])
+    (cond
+      [(and line column) (string-append path ":" (int->string line) ":" (int->string column))]
+      [line (string-append path ":" (int->string line))]
+      [else path])))
+
+(define (felis-run request)
+  (let ([type (json-ref request "type")])
+    (cond
+      [(equal? type "open")
+       (let ([split (json-ref request "split")]
+             [target (open-target request)])
+         (cond
+           [(equal? split "vertical") (helix.vsplit target)]
+           [(equal? split "horizontal") (helix.hsplit target)]
+           [else (helix.open target)]))]
+      ;; A typed command, e.g. `reload`, with its arguments
+      [(equal? type "command")
+       (let* ([name (json-ref request "name")]
+              [command (hash-try-get felis-commands name)])
+         (if command
+             (apply command (or (json-ref request "args") '()))
+             (set-error! (string-append "felis: unsupported command " name))))]
+      [else (set-error! (string-append "felis: unknown request " type))])))
+
+;; felis writes a message (see `felis::plugin`) in a handoff file of its own, and passes the
+;; handoff's path. The handoff is removed once read, so it's only ever run once.
+(define (felis-open handoff)
+  (when (path-exists? handoff)
+    (let ([message (~> (open-input-file handoff) (read-port-to-string) (string->jsexpr))])
+      (delete-file! handoff)
+      (if (equal? (json-ref message "version") felis-protocol-version)
+          (for-each felis-run (json-ref message "requests"))
+          (set-error! "felis: the message is not in the protocol version of the plugin")))))
 
 (define (felis-file-browser felis-bin browser-bin)
   (helix.run-shell-command felis-bin "open-browser" "-l" "--steel" browser-bin))
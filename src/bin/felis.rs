@@ -1,12 +1,24 @@
 use std::{
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
     path::{Path, PathBuf},
     println,
     process::Stdio,
 };
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use felis::{
-    command, fs::AbsolutePath, kitty_terminal::KittyTerminal, Context, Environment, Result,
+    command,
+    config::{Config, FallbackPolicy, KittyConfig, Strategy},
+    discovery::Discovery,
+    fs::{self, AbsolutePath, Extractors, FileLocation, LocationReader},
+    kitty_terminal::{command::Launch, KittyTerminal},
+    matcher::MatchExpr,
+    picker,
+    plugin::Split,
+    tag::Tags,
+    workspace::WindowTarget,
+    Context, Environment, Result,
 };
 use kitty_remote_bindings::{
     command::options::{Cwd, LaunchType},
@@ -16,101 +28,223 @@ use tokio::io::AsyncReadExt;
 
 #[derive(Parser, Debug)]
 struct Cli {
+    /// Path to the config file, defaults to `$XDG_CONFIG_HOME/felis/config.toml`
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// The address kitty listens on, e.g. `unix:/tmp/kitty.sock-1234`, overrides the config. By
+    /// default the socket is searched for.
+    #[arg(long, global = true)]
+    to: Option<String>,
+    /// Search helix in every reachable kitty instance, e.g. when running one kitty per monitor
+    #[arg(long, global = true, default_value_t = false)]
+    all_instances: bool,
     #[command(subcommand)]
     command: Command,
 }
 
 #[derive(Debug, Subcommand)]
 enum Command {
-    GetActiveFocusedWindow,
-    /// Open the given file in helix
+    /// Print the id of the active focused window. With `--all-instances` it's followed by the
+    /// socket of the window's kitty instance, e.g. `3 unix:/tmp/kitty.sock-1234`.
+    GetActiveFocusedWindow {
+        /// Print the id of the closest window with this role instead, see `felis tag`
+        #[arg(long)]
+        role: Option<String>,
+        /// Print the id of the closest window with this project instead, see `felis tag`
+        #[arg(long)]
+        project: Option<String>,
+    },
+    /// Tag a kitty window with a role and/or a project. Tags take precedence over the heuristics
+    /// that find the helix to open a file in: a window with the `editor` role runs helix, and a
+    /// window tagged with a project is used for the files of that project.
+    Tag {
+        /// The role of the window, e.g. `editor`
+        #[arg(long)]
+        role: Option<String>,
+        /// The project of the window: the name of the project's root directory, or a path
+        #[arg(long)]
+        project: Option<String>,
+        /// Remove the tags that are not given
+        #[arg(long, default_value_t = false)]
+        clear: bool,
+        /// The window to tag, defaults to the window felis is running in
+        #[arg(short, long)]
+        window_id: Option<u32>,
+    },
+    /// Open the given files in helix
     OpenFile {
-        /// Path to the file to open
-        path: PathBuf,
-        /// Open the file in the helix process running in the given window
+        /// Paths to the files to open, optionally followed by a position, e.g. `src/lib.rs:13:3`,
+        /// `src/lib.rs:13` or a line range `src/lib.rs:10-20`. They can also be lines of a
+        /// compiler's or test runner's output, e.g. `--> src/main.rs:10:5`. Multi-line arguments
+        /// (e.g. a selection passed by kitty) are split into lines, unless they are a path wrapped
+        /// across lines. Use `-` to read paths from the standard input, one per line, e.g.
+        /// `rg -l pattern | felis open-file -`, the files are opened as they are read.
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// Open the file in the helix process running in the given window: a window id (with the
+        /// socket of its kitty instance when there are several, e.g. `3@unix:/tmp/kitty-2`), or
+        /// tags of the window, e.g. `role=editor,project=api` (see `felis tag`)
         #[arg(short, long)]
-        window_id: Option<u32>, // TODO: change this to Option<WindowId>
+        window_id: Option<WindowTarget>,
+        /// Open the file in the helix process running in the window matching the kitty match
+        /// expression, e.g. `title:api and cmdline:hx` or `var:felis_role=editor`
+        #[arg(long = "match", conflicts_with = "window_id")]
+        match_expr: Option<MatchExpr>,
         /// The context of how felis is used, this drives how file paths are determined
         #[arg(long, default_value_t = Context::Shell)]
         context: Context,
-        /// Wether to use the steel plugin to open the file
-        #[arg(long, default_value_t = false)]
+        /// How the files are handed to helix, overrides the config
+        #[arg(long)]
+        strategy: Option<Strategy>,
+        /// Use the steel plugin to open the file, the same as `--strategy steel`
+        #[arg(long, default_value_t = false, conflicts_with = "strategy")]
         steel: bool,
+        /// Open the files in new splits instead of the current view, overrides the config
+        #[arg(long)]
+        split: Option<Split>,
+        /// What to do when no helix is running in the workspace of a file, overrides the config
+        #[arg(long)]
+        fallback: Option<FallbackPolicy>,
+        /// Ask which helix to use when a file could be opened in several of them equally well, or
+        /// in none of them
+        #[arg(long, default_value_t = false)]
+        pick: bool,
     },
     /// Run the given file browser / file manager and then open the selected file in helix
     OpenBrowser {
         /// Name or path to the executable to run to select the file to open. The given program
         /// needs to print the path of then file to the standard output, e.g. a propertly configured
-        /// `broot`
+        /// `broot`. When multiple files are selected, the paths should be printed one per line.
         file_browser: String,
         /// Sets the current working directory to the given path when opens the file browser
         cwd: Option<PathBuf>,
         /// Open the file in the helix process running in the given window. If not given felis will
         /// try to determine which helix instance is running in one the parent directories of the
-        /// given file.
+        /// given file. The id can be followed by the socket of its kitty instance, e.g.
+        /// `3@unix:/tmp/kitty-2`. Tags of the window can be given instead of the id, e.g.
+        /// `role=editor,project=api` (see `felis tag`).
         #[arg(short, long)]
-        window_id: Option<u32>, // TODO:  change this to Option<WindowId>
+        window_id: Option<WindowTarget>,
+        /// Open the file in the helix process running in the window matching the kitty match
+        /// expression, e.g. `title:api and cmdline:hx` or `var:felis_role=editor`
+        #[arg(long = "match", conflicts_with = "window_id")]
+        match_expr: Option<MatchExpr>,
         /// When true felis will launch a kitty overlay on top the current window, and run the file
         /// browser there. This is useful when felis is running from an editor.
         #[arg(short, long, default_value_t = false)]
         launch_overlay: bool,
-        /// Wether to use the steel plugin to open the file
-        #[arg(long, default_value_t = false)]
+        /// How the files are handed to helix, overrides the config
+        #[arg(long)]
+        strategy: Option<Strategy>,
+        /// Use the steel plugin to open the file, the same as `--strategy steel`
+        #[arg(long, default_value_t = false, conflicts_with = "strategy")]
         steel: bool,
+        /// What to do when no helix is running in the workspace of a file, overrides the config
+        #[arg(long)]
+        fallback: Option<FallbackPolicy>,
+        /// Ask which helix to use when a file could be opened in several of them equally well, or
+        /// in none of them
+        #[arg(long, default_value_t = false)]
+        pick: bool,
+    },
+    /// Ask which of the given windows to use, this runs in the overlay launched by felis
+    #[command(hide = true)]
+    PickWindow {
+        /// The file to write the id of the chosen window to
+        #[arg(long)]
+        output: PathBuf,
+        /// The windows to choose from, as `<window id>\t<socket>\t<label>`
+        items: Vec<String>,
     },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    let mut config = Config::load(cli.config.as_deref())?;
+
+    // The picker runs in an overlay, and it doesn't talk to kitty
+    if let Command::PickWindow { output, items } = &cli.command {
+        return pick_window(output, items).await;
+    }
 
-    let kitty = KittyTerminal::new(kitty_socket()?);
+    let kitty = kitty_terminal(cli.to.clone(), cli.all_instances, &config.kitty).await?;
 
     match cli.command {
-        Command::GetActiveFocusedWindow => {
-            let window_id = command::get_active_focused_window(&kitty).await?;
-            println!("{}", window_id.0);
+        Command::GetActiveFocusedWindow { role, project } => {
+            let tags = Tags { role, project };
+            let window = if tags.is_empty() {
+                command::get_active_focused_window(&kitty).await?
+            } else {
+                command::get_tagged_window(&kitty, &tags).await?
+            };
+            match window.socket {
+                Some(socket) => println!("{} {socket}", window.id),
+                None => println!("{}", window.id),
+            }
+        }
+
+        Command::Tag {
+            role,
+            project,
+            clear,
+            window_id,
+        } => {
+            // kitty sets KITTY_WINDOW_ID in the windows it launches
+            let window_id = window_id
+                .or_else(|| std::env::var("KITTY_WINDOW_ID").ok()?.parse().ok())
+                .map(WindowId);
+            command::tag_window(&kitty, window_id, &Tags { role, project }, clear).await?;
         }
 
         Command::OpenFile {
-            path,
+            paths,
             window_id,
+            match_expr,
             context,
+            strategy,
             steel,
+            split,
+            fallback,
+            pick,
         } => {
+            if let Some(policy) = fallback {
+                config.fallback.policy = policy;
+            }
+            config.open.split = split.or(config.open.split);
+            config.picker.enabled |= pick;
+            let strategy = strategy.or(steel.then_some(Strategy::Steel));
+            let target = match_expr.map(WindowTarget::Match).or(window_id);
             let env = env(&context, &kitty).await?;
-            let path = AbsolutePath::resolve(&path, &env)?;
-            command::open_in_helix(&path, window_id.map(WindowId), &kitty, steel).await?;
+            open_locations(&paths, target.as_ref(), &kitty, strategy, &config, &env).await?;
         }
 
         Command::OpenBrowser {
             file_browser,
             window_id,
+            match_expr,
             launch_overlay,
+            strategy,
             steel,
             cwd,
+            fallback,
+            pick,
         } => {
+            if let Some(policy) = fallback {
+                config.fallback.policy = policy;
+            }
+            config.picker.enabled |= pick;
+            let strategy = strategy.or(steel.then_some(Strategy::Steel));
+            let target = match_expr.map(WindowTarget::Match).or(window_id);
             if launch_overlay {
-                let executable = std::env::current_exe()?;
-
-                let mut args = vec![
-                    executable.as_os_str().to_str().unwrap().to_string(),
-                    "open-browser".to_string(),
-                    file_browser.as_str().to_string(),
-                ];
-
-                if let Some(tab_id) = window_id {
-                    args.push("--tab-id".to_string());
-                    args.push(tab_id.to_string());
-                };
-
-                if steel {
-                    args.push("--steel".to_string());
-                }
-
-                if let Some(dir) = &cwd {
-                    args.push(dir.to_string_lossy().to_string());
-                }
+                let args = overlay_args(
+                    &file_browser,
+                    target.as_ref(),
+                    strategy,
+                    fallback,
+                    pick,
+                    cwd.as_deref(),
+                )?;
 
                 let working_dir = if let Some(dir) = cwd {
                     Cwd::Path(dir)
@@ -118,51 +252,168 @@ async fn main() -> Result<()> {
                     Cwd::Current
                 };
 
-                // TODO: replace this with a KittyComman
-                kitty.launch(args, LaunchType::Overlay, working_dir).await?;
+                kitty
+                    .launch(
+                        Launch::new(args)
+                            .launch_type(LaunchType::Overlay)
+                            .cwd(working_dir),
+                    )
+                    .await?;
             } else {
-                let mut cmd = tokio::process::Command::new(file_browser);
-
-                if let Some(dir) = cwd {
-                    cmd.current_dir(dir);
-                }
+                let locations = run_file_browser(&file_browser, cwd).await?;
+                command::open_in_helix(&locations, target.as_ref(), &kitty, strategy, &config)
+                    .await?;
+            }
+        }
 
-                let mut child = cmd.stdout(Stdio::piped()).spawn()?;
+        Command::PickWindow { .. } => unreachable!("the picker is run before connecting to kitty"),
+    }
 
-                let mut stdout = child.stdout.take().unwrap();
-                let mut out = String::new();
-                stdout.read_to_string(&mut out).await?;
-                let out = out.trim_end();
-                let path = PathBuf::from(out);
+    Ok(())
+}
 
-                let path = AbsolutePath::try_from(path)?;
+/// Opens the locations of the given paths, `-` reads them from the standard input. The lines of
+/// the standard input are opened in batches as they arrive, see [`LocationReader`].
+async fn open_locations(
+    paths: &[String],
+    target: Option<&WindowTarget>,
+    kitty: &KittyTerminal,
+    strategy: Option<Strategy>,
+    config: &Config,
+    env: &Environment,
+) -> Result<()> {
+    let extractors = Extractors::from_config(config)?;
+    let mut locations = Vec::new();
+    for path in paths {
+        if path == "-" {
+            // Whatever came before is opened first, to keep the order of the paths
+            command::open_in_helix(&locations, target, kitty, strategy, config).await?;
+            locations.clear();
 
-                command::open_in_helix(&path, window_id.map(WindowId), &kitty, steel).await?;
+            let stdin = tokio::io::BufReader::new(tokio::io::stdin());
+            let mut reader = LocationReader::new(stdin, &extractors, env);
+            while let Some(batch) = reader.next_batch().await? {
+                command::open_in_helix(&batch, target, kitty, strategy, config).await?;
             }
+        } else {
+            locations.extend(fs::resolve_selection(path, &extractors, env)?);
         }
-    };
+    }
+
+    command::open_in_helix(&locations, target, kitty, strategy, config).await
+}
+
+/// Runs the file browser, and returns the locations of the selected files
+async fn run_file_browser(
+    file_browser: &str,
+    cwd: Option<PathBuf>,
+) -> Result<Vec<FileLocation<AbsolutePath>>> {
+    let mut cmd = tokio::process::Command::new(file_browser);
+
+    if let Some(dir) = cwd {
+        cmd.current_dir(dir);
+    }
+
+    let mut child = cmd.stdout(Stdio::piped()).spawn()?;
+
+    let mut stdout = child.stdout.take().unwrap();
+    let mut out = String::new();
+    stdout.read_to_string(&mut out).await?;
+    // File browsers with multi-select print one path per line
+    let locations = out
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| FileLocation::<AbsolutePath>::try_from(FileLocation::from(line)))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    Ok(locations)
+}
+
+/// The arguments to run `open-browser` again in the overlay
+fn overlay_args(
+    file_browser: &str,
+    target: Option<&WindowTarget>,
+    strategy: Option<Strategy>,
+    fallback: Option<FallbackPolicy>,
+    pick: bool,
+    cwd: Option<&Path>,
+) -> Result<Vec<String>> {
+    let executable = std::env::current_exe()?;
+
+    let mut args = vec![
+        executable.as_os_str().to_str().unwrap().to_string(),
+        "open-browser".to_string(),
+        file_browser.to_string(),
+    ];
+
+    if let Some(target) = target {
+        let option = match target {
+            WindowTarget::Match(_) => "--match",
+            WindowTarget::Id { .. } | WindowTarget::Tags(_) => "--window-id",
+        };
+        args.push(option.to_string());
+        args.push(target.to_string());
+    }
+
+    if let Some(strategy) = strategy {
+        args.push("--strategy".to_string());
+        args.push(strategy.to_possible_value().unwrap().get_name().to_string());
+    }
+
+    if let Some(policy) = fallback {
+        args.push("--fallback".to_string());
+        args.push(policy.to_possible_value().unwrap().get_name().to_string());
+    }
+
+    if pick {
+        args.push("--pick".to_string());
+    }
+
+    if let Some(dir) = cwd {
+        args.push(dir.to_string_lossy().to_string());
+    }
+
+    Ok(args)
+}
+
+async fn pick_window(output: &Path, items: &[String]) -> Result<()> {
+    let items = items
+        .iter()
+        .map(|item| item.parse())
+        .collect::<Result<Vec<picker::Item>>>()?;
+    let stdin = tokio::io::BufReader::new(tokio::io::stdin());
+    if let Some(item) = picker::prompt(&items, stdin, tokio::io::stdout()).await? {
+        // The file was removed before the picker was launched, anything there now isn't ours
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(output)?;
+        file.write_all(item.to_string().as_bytes())?;
+    }
 
     Ok(())
 }
 
-// When sockets are enabled the KITTY_LISTEN_ON env var is set in shells running in kitty windows.
-// But when felis is executed via `pass_selection_to_program`, then the env var is not set and the
-// kitty program spawned by felis cannot communicate through tty either, so we need this heuristic
-// here when we try to find the socket file based on the parent's p, so we need this heuristic here
-// when we try to find the socket file based on the parent's pid
-fn kitty_socket() -> Result<String> {
-    if let Ok(socket) = std::env::var("KITTY_LISTEN_ON") {
-        Ok(socket)
-    } else {
-        let parent_pid = std::os::unix::process::parent_id();
-        let socket = format!("/tmp/kitty.sock-{parent_pid}");
-        if Path::new(&socket).exists() {
-            Ok(format!("unix:{socket}"))
-        } else {
-            Err(felis::FelisError::UnexpectedError {
-                message: "couldn't determine kitty socket".to_string(),
-            })
+/// Talks to kitty over its socket, or over the terminal when there's no socket but felis runs in a
+/// kitty window. With `all_instances`, the other kitty instances are found and connected to as
+/// well. The options override the config. The remote control password, if any, is used for every
+/// command.
+async fn kitty_terminal(
+    to: Option<String>,
+    all_instances: bool,
+    config: &KittyConfig,
+) -> Result<KittyTerminal> {
+    let all_instances = all_instances || config.all_instances;
+    let password = config.password()?;
+    let discovery = Discovery::new(to.or_else(|| config.socket.clone()));
+    match discovery.kitty_socket() {
+        Ok(socket) if all_instances => {
+            let others = discovery.other_sockets(&socket);
+            Ok(KittyTerminal::connect_all(socket, others, password).await)
         }
+        Ok(socket) => Ok(KittyTerminal::connect(socket, password).await),
+        Err(error) => KittyTerminal::over_tty(password).map_err(|_| error),
     }
 }
 
@@ -1,96 +1,608 @@
-use std::path::Path;
-
-use kitty_remote_bindings::{
-    command::options::Matcher,
-    model::{self, OsWindows, Window, WindowId},
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
 };
 
-use crate::{fs::AbsolutePath, kitty_terminal::KittyTerminal, FelisError, Result};
+use kitty_remote_bindings::command::options::{Cwd, Matcher};
+
+use crate::{
+    config::{Config, FallbackConfig, FallbackPolicy, OpenConfig, Strategy},
+    editor::EditorDetector,
+    fs::{AbsolutePath, FileLocation, Position},
+    kitty_terminal::{
+        command::{Extent, GetText, Launch},
+        KittyTerminal,
+    },
+    model::{self, OsWindows, Process, Window, WindowId, WindowKey},
+    picker::{self, Choices},
+    screen::Screen,
+    strategy::{self, OpenRequest, OpenStrategy},
+    tag::{Tags, PROJECT_VAR, ROLE_VAR},
+    workspace::{WindowTarget, WorkspaceResolver},
+    FelisError, Result,
+};
 
+/// Returns the active focused window, with the socket of its kitty instance when the windows of
+/// several instances are listed
+///
 /// # Errors
 ///
 /// Will return Err if Kitty terminal related operations fail
-pub async fn get_active_focused_window(kitty: &KittyTerminal) -> Result<WindowId> {
+pub async fn get_active_focused_window(kitty: &KittyTerminal) -> Result<WindowKey> {
     let windows = kitty.ls().await?;
     let window = focused_active_window(&windows).ok_or_else(|| FelisError::UnexpectedError {
         message: "Couldn't find active focused window".to_string(),
     })?;
 
-    Ok(window.id)
+    Ok(window.key())
 }
 
+/// Returns the window with the given tags, preferring the ones closer to the active focused window
+///
+/// # Errors
+///
+/// Will return Err if Kitty terminal related operations fail, or there's no window with the tags
+pub async fn get_tagged_window(kitty: &KittyTerminal, tags: &Tags) -> Result<WindowKey> {
+    let windows = kitty.ls().await?;
+    let window = WorkspaceResolver::new(&windows).target(&WindowTarget::Tags(tags.clone()))?;
+
+    Ok(window.key())
+}
+
+/// Tags the given window of kitty's own instance, or the active focused window of any instance.
+/// When `clear` is set, the tags that are not given are removed from the window.
+///
+/// # Errors
+///
+/// Will return Err if Kitty terminal related operations fail
+pub async fn tag_window(
+    kitty: &KittyTerminal,
+    window_id: Option<WindowId>,
+    tags: &Tags,
+    clear: bool,
+) -> Result<WindowKey> {
+    let key = match window_id {
+        Some(window_id) => WindowKey {
+            socket: None,
+            id: window_id.0,
+        },
+        None => get_active_focused_window(kitty).await?,
+    };
+
+    let mut vars = tags.to_user_vars();
+    if clear {
+        // A variable without a value is removed
+        if tags.role.is_none() {
+            vars.push(ROLE_VAR.to_string());
+        }
+        if tags.project.is_none() {
+            vars.push(PROJECT_VAR.to_string());
+        }
+    }
+    kitty
+        .instance(key.socket.as_deref())
+        .set_user_vars(Matcher::Id(key.window_id()), vars)
+        .await?;
+
+    Ok(key)
+}
+
+/// Opens the given locations in helix. Each location is opened in the helix instance running in
+/// its workspace (or in the target window), with a single `:open` command per helix instance. When
+/// there's no helix running in the workspace, the configured fallback policy decides what happens.
+/// Once everything is open, the window of the last location gets focused.
+///
+/// The files are handed to helix with the given strategy, or the one configured for their project,
+/// see [`crate::strategy`].
+///
 /// # Errors
 ///
 /// Will return Err if Kitty terminal related operations fail
 pub async fn open_in_helix(
-    path: &AbsolutePath,
-    kitty_tab_id: Option<WindowId>,
+    locations: &[FileLocation<AbsolutePath>],
+    target: Option<&WindowTarget>,
     kitty: &KittyTerminal,
-    steel: bool,
+    strategy: Option<Strategy>,
+    config: &Config,
 ) -> Result<()> {
-    let windows = kitty.ls().await?;
-    let kitty_window = if let Some(id) = kitty_tab_id {
-        find_window_by_id(&windows, id).ok_or_else(|| FelisError::UnexpectedError {
-            message: format!("Couldn't find window with id {id}"),
-        })?
+    if locations.is_empty() {
+        return Ok(());
+    }
+
+    // Only the target window matters when it is known
+    let windows = match target {
+        Some(WindowTarget::Id { id, .. }) => kitty.ls_matching(Matcher::Id(*id)).await?,
+        _ => kitty.ls().await?,
+    };
+    let resolver = WorkspaceResolver::new(&windows)
+        .root_markers(&config.workspace.root_markers)
+        .detector(EditorDetector::from_config(&config.editor)?);
+
+    let mut choices = match Choices::default_path() {
+        Some(path) if config.picker.remember => Choices::load(path),
+        _ => Choices::default(),
+    };
+
+    let mut batches: Vec<(&Window, Vec<&FileLocation<AbsolutePath>>)> = Vec::new();
+    // Locations without a helix, grouped by the directory where helix should be started
+    let mut fallbacks: Vec<(PathBuf, Vec<&FileLocation<AbsolutePath>>)> = Vec::new();
+    for location in locations {
+        let kitty_window = if let Some(target) = target {
+            resolver.target(target)?
+        } else {
+            match select_window(&resolver, &location.path, kitty, config, &mut choices).await? {
+                Ok(kitty_window) => kitty_window,
+                Err(err) => match config.fallback.policy {
+                    FallbackPolicy::Error => return Err(err),
+                    FallbackPolicy::Recent => resolver.most_recent().ok_or(err)?,
+                    FallbackPolicy::Launch | FallbackPolicy::Shell => {
+                        let dir = resolver.project_dir(&location.path);
+                        match fallbacks.iter_mut().find(|(d, _)| *d == dir) {
+                            Some((_, batch)) => batch.push(location),
+                            None => fallbacks.push((dir, vec![location])),
+                        }
+                        continue;
+                    }
+                },
+            }
+        };
+
+        // Window ids are only unique within a kitty instance
+        match batches
+            .iter_mut()
+            .find(|(w, _)| w.id == kitty_window.id && w.socket == kitty_window.socket)
+        {
+            Some((_, batch)) => batch.push(location),
+            None => batches.push((kitty_window, vec![location])),
+        }
+    }
+
+    choices.save()?;
+
+    let mut focus = batches.last().map(|(kitty_window, _)| {
+        (
+            kitty.instance(kitty_window.socket.as_deref()),
+            kitty_window.id,
+        )
+    });
+
+    for (kitty_window, batch) in &batches {
+        let project = resolver.project_dir(&batch[0].path);
+        let helix = resolver.helix_process(kitty_window);
+        let strategy = strategy::select(strategy, &config.open, &project, helix).await;
+        open_batch(
+            kitty_window,
+            helix,
+            batch,
+            kitty,
+            strategy.as_ref(),
+            &config.open,
+        )
+        .await?;
+    }
+
+    for (dir, batch) in &fallbacks {
+        focus = Some(start_helix(dir, batch, &resolver, kitty, &config.fallback).await?);
+    }
+
+    if let Some((instance, window_id)) = focus {
+        instance.focus_window(Matcher::Id(window_id)).await?;
+    }
+
+    Ok(())
+}
+
+/// Resolves the window of the file's workspace. When the picker is enabled, it asks which window
+/// to use when the best candidate is ambiguous, or when there are no candidates but helix is
+/// running somewhere. Cancelling the picker in the latter case leaves the file to the fallback
+/// policy.
+///
+/// The inner result is the outcome of the resolution, a failed resolution is handled by the
+/// fallback policy. The outer result is an error that should abort opening the files, e.g. when
+/// the picker is cancelled while the file has several candidates.
+async fn select_window<'a>(
+    resolver: &WorkspaceResolver<'a>,
+    path: &AbsolutePath,
+    kitty: &KittyTerminal,
+    config: &Config,
+    choices: &mut Choices,
+) -> Result<Result<&'a Window>> {
+    let workspace = resolver.resolve(path);
+    if !config.picker.enabled {
+        return Ok(workspace);
+    }
+
+    let ambiguous = resolver.ambiguous(path);
+    let options = if !ambiguous.is_empty() {
+        ambiguous
+            .iter()
+            .map(|candidate| (candidate.tab, candidate.window))
+            .collect()
+    } else if workspace.is_err() {
+        resolver.editors()
     } else {
-        find_workspace(&windows, path)?
+        return Ok(workspace);
     };
+    if options.is_empty() {
+        return Ok(workspace);
+    }
 
-    // Once we have the kitty window where helix is running, we can use it to potentially  shorten
-    // the absolute path to a relative path from helix's working directory. This can speed up
-    // "typing" the path into helix. We also remove any new lines from the path (this can happen
-    // when the path is copied from a small terminal window)
-    let rel_path = {
-        let p = path
-            .as_ref()
-            .strip_prefix(window_cwd(kitty_window))?
-            .to_string_lossy()
-            .replace('\n', "");
-        p.trim().to_owned()
+    let root = resolver.project_dir(path);
+    let remembered = choices
+        .get(&root)
+        .filter(|key| options.iter().any(|(_, window)| window.key() == **key))
+        .cloned();
+    let key = if let Some(key) = remembered {
+        key
+    } else {
+        let items = options
+            .iter()
+            .map(|(tab, window)| picker::Item::new(tab, window))
+            .collect::<Vec<_>>();
+        // The overlay is shown on the window felis was invoked from
+        let instance = kitty.instance(
+            resolver
+                .origin_window()
+                .and_then(|window| window.socket.as_deref()),
+        );
+        let Some(key) = picker::pick(instance, &items).await? else {
+            return match workspace {
+                Ok(_) => Err(FelisError::UnexpectedError {
+                    message: format!("No window was picked for {}", path.as_ref().display()),
+                }),
+                Err(err) => Ok(Err(err)),
+            };
+        };
+        choices.insert(root, key.clone());
+        key
     };
 
-    if steel {
-        std::fs::write("/tmp/felis-open.txt", rel_path.as_bytes())?;
+    let window = options
+        .iter()
+        .find(|(_, window)| window.key() == key)
+        .map(|(_, window)| *window)
+        .ok_or_else(|| FelisError::UnexpectedError {
+            message: format!("Couldn't find window with id {}", key.id),
+        })?;
+
+    Ok(Ok(window))
+}
 
-        kitty.focus_window(Matcher::Id(kitty_window.id)).await?;
-        // Go to normal mode by hitting ESC
-        kitty.send_text(Matcher::Id(kitty_window.id), r"\E").await?;
-        // Go to command mode and run felis-open command
-        kitty
-            .send_text(Matcher::Id(kitty_window.id), r":felis-open\r")
+/// Starts a new helix in the given directory that opens the given files, according to the
+/// fallback policy. Returns the kitty instance and the id of the window where helix is running.
+async fn start_helix<'k>(
+    dir: &Path,
+    locations: &[&FileLocation<AbsolutePath>],
+    resolver: &WorkspaceResolver<'_>,
+    kitty: &'k KittyTerminal,
+    config: &FallbackConfig,
+) -> Result<(&'k KittyTerminal, WindowId)> {
+    let mut args = config.command.clone();
+    for location in locations {
+        args.push(location_target(dir, location)?);
+    }
+
+    if config.policy == FallbackPolicy::Shell {
+        let shell = resolver
+            .idle_shell(dir)
+            .ok_or_else(|| FelisError::UnexpectedError {
+                message: format!("Couldn't find an idle shell in {}", dir.display()),
+            })?;
+        let command_line = args
+            .iter()
+            .map(|arg| shell_quote(arg))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let instance = kitty.instance(shell.socket.as_deref());
+        instance
+            .send_text(
+                Matcher::Id(shell.id),
+                &format!(r"{}\r", kitty_escape(&command_line)),
+            )
             .await?;
+
+        Ok((instance, shell.id))
     } else {
-        // Send open command directly to helix
-
-        kitty.focus_window(Matcher::Id(kitty_window.id)).await?;
-        // Go to normal mode by hitting ESC
-        kitty.send_text(Matcher::Id(kitty_window.id), r"\E").await?;
-        // Go to command mode
-        kitty.send_text(Matcher::Id(kitty_window.id), r":").await?;
-        // Paste the path first to avoid autocompletion triggering on the path segment after each
-        // character
-        kitty
-            .send_text(Matcher::Id(kitty_window.id), &rel_path)
+        // The new window belongs to the kitty instance felis was invoked from
+        let instance = kitty.instance(
+            resolver
+                .origin_window()
+                .and_then(|window| window.socket.as_deref()),
+        );
+        let window_id = instance
+            .launch(
+                Launch::new(args)
+                    .launch_type(config.launch_type.into())
+                    .cwd(Cwd::Path(dir.to_path_buf()))
+                    // helix might not be on kitty's PATH, e.g. when it's installed by home-manager
+                    .copy_env(true)
+                    .keep_focus(true),
+            )
             .await?;
-        // Jump at the beginning of the command line, type open, then hit ENTER
-        kitty
-            .send_text(Matcher::Id(kitty_window.id), r"\x01open \r")
+
+        Ok((instance, window_id))
+    }
+}
+
+/// Quotes the argument for a POSIX shell, unless it only contains safe characters
+fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "/._-:+,=@%".contains(c))
+    {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// Opens the locations in helix with the strategy. With `preflight`, the keys going back to normal
+/// mode depend on helix' state. With `verify`, helix' screen is checked afterwards: when the last
+/// file is not shown, the files are opened again after closing whatever helix had open (e.g. a
+/// picker), and if that doesn't work either the open failed.
+async fn open_batch(
+    kitty_window: &Window,
+    helix: Option<&Process>,
+    locations: &[&FileLocation<AbsolutePath>],
+    kitty: &KittyTerminal,
+    strategy: &dyn OpenStrategy,
+    config: &OpenConfig,
+) -> Result<()> {
+    let instance = kitty.instance(kitty_window.socket.as_deref());
+    let reset = if config.preflight {
+        preflight(instance, kitty_window.id).await?
+    } else {
+        ESCAPE
+    };
+    let mut request = OpenRequest {
+        window: kitty_window,
+        helix,
+        locations,
+        reset,
+        split: config.split,
+    };
+    strategy.open(instance, &request).await?;
+
+    let Some(last) = locations.last().filter(|_| config.verify) else {
+        return Ok(());
+    };
+    let path = relative_path(window_cwd(kitty_window), last)?;
+    if wait_for_buffer(instance, kitty_window.id, &path)
+        .await?
+        .is_none()
+    {
+        return Ok(());
+    }
+
+    request.reset = STRONG_RESET;
+    strategy.open(instance, &request).await?;
+    match wait_for_buffer(instance, kitty_window.id, &path).await? {
+        None => Ok(()),
+        Some(screen) => Err(FelisError::OpenNotVerified {
+            path,
+            window_id: kitty_window.id.0,
+            message: screen
+                .message()
+                .or(screen.statusline())
+                .unwrap_or_default()
+                .trim()
+                .to_string(),
+        }),
+    }
+}
+
+/// What helix is doing, as seen on its screen. It decides which keys go back to normal mode before
+/// typing the command opening the files.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EditorState {
+    Normal,
+    Select,
+    /// An escape might only close the completion popup
+    Insert,
+    /// Waiting for the rest of a key sequence, e.g. after `g`
+    Pending,
+    /// The command line is open, e.g. after `:` or `/`
+    Prompt,
+    /// A picker is open, the terminal cursor is in its prompt
+    Picker,
+    /// Not recognised, e.g. helix is not running anymore or its statusline is customised
+    Unknown,
+}
+
+impl EditorState {
+    /// Classifies the screen, read with the cursor (see [`GetText::add_cursor`]). Without the
+    /// cursor a prompt can't be told apart from the mode, so the state is unknown.
+    #[must_use]
+    pub fn classify(screen: &Screen) -> Self {
+        let mode = match screen.mode() {
+            Some("NOR") => EditorState::Normal,
+            Some("SEL") => EditorState::Select,
+            Some("INS") => EditorState::Insert,
+            _ => return EditorState::Unknown,
+        };
+
+        // helix draws its own cursors in the buffers, the terminal cursor is only shown in the
+        // prompts (or in insert mode, depending on the cursor shape)
+        let Some(cursor) = screen.cursor() else {
+            return EditorState::Unknown;
+        };
+        if cursor.visible && mode != EditorState::Insert {
+            return if cursor.row == screen.height() {
+                EditorState::Prompt
+            } else {
+                EditorState::Picker
+            };
+        }
+        if screen.pending_keys().is_some() {
+            return EditorState::Pending;
+        }
+
+        mode
+    }
+
+    /// The keys going back to normal mode, `None` when the state is unknown
+    #[must_use]
+    pub fn reset(self) -> Option<&'static str> {
+        match self {
+            EditorState::Normal | EditorState::Select => Some(ESCAPE),
+            EditorState::Insert
+            | EditorState::Pending
+            | EditorState::Prompt
+            | EditorState::Picker => Some(DOUBLE_ESCAPE),
+            EditorState::Unknown => None,
+        }
+    }
+}
+
+/// Reads helix' screen, and returns the keys going back to normal mode from its state
+async fn preflight(kitty: &KittyTerminal, window_id: WindowId) -> Result<&'static str> {
+    let text = kitty
+        .get_text(
+            GetText::new()
+                .matcher(Matcher::Id(window_id))
+                .extent(Extent::Screen)
+                .add_cursor(true),
+        )
+        .await?;
+    let screen = Screen::new(&text);
+
+    EditorState::classify(&screen)
+        .reset()
+        .ok_or_else(|| FelisError::UnknownEditorState {
+            window_id: window_id.0,
+            statusline: screen.statusline().unwrap_or_default().trim().to_string(),
+        })
+}
+
+/// How many times helix' screen is read before giving up, and how long to wait in between
+const VERIFY_ATTEMPTS: u32 = 5;
+const VERIFY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Waits until helix shows the buffer of the path, helix needs some time to process the keys and
+/// redraw its screen. Returns the last screen when the buffer is not shown.
+async fn wait_for_buffer(
+    kitty: &KittyTerminal,
+    window_id: WindowId,
+    path: &str,
+) -> Result<Option<Screen>> {
+    let mut screen = None;
+    for attempt in 0..VERIFY_ATTEMPTS {
+        if attempt > 0 {
+            tokio::time::sleep(VERIFY_INTERVAL).await;
+        }
+        let text = kitty
+            .get_text(
+                GetText::new()
+                    .matcher(Matcher::Id(window_id))
+                    .extent(Extent::Screen),
+            )
             .await?;
+        let current = Screen::new(&text);
+        if current.shows_buffer(path) {
+            return Ok(None);
+        }
+        screen = Some(current);
     }
 
-    Ok(())
+    Ok(screen)
 }
 
-fn find_window_by_id(windows: &OsWindows, window_id: WindowId) -> Option<&Window> {
-    windows.0.iter().find_map(|os_window| {
-        os_window
-            .tabs
-            .iter()
-            .find_map(|tab| tab.windows.iter().find(|window| window.id == window_id))
+/// Escape as a CSI u encoded key. A bare `\x1b` followed by more keys in the same write would be
+/// read by helix as an Alt chord, e.g. `Alt-:`, instead of an Escape and a `:`.
+const ESCAPE: &str = r"\x1b[27u";
+/// Closes a popup or a prompt, then leaves the mode it was opened in
+const DOUBLE_ESCAPE: &str = r"\x1b[27u\x1b[27u";
+/// Closes whatever helix has open, e.g. a picker with a prompt on top of it, or a pending keymap
+const STRONG_RESET: &str = r"\x1b[27u\x1b[27u\x1b[27u";
+
+pub(crate) fn helix_target(
+    kitty_window: &Window,
+    location: &FileLocation<AbsolutePath>,
+) -> Result<String> {
+    // Once we have the kitty window where helix is running, we can use it to potentially  shorten
+    // the absolute path to a relative path from helix's working directory. This can speed up
+    // "typing" the path into helix.
+    location_target(window_cwd(kitty_window), location)
+}
+
+/// The path of the location relative to the given directory, followed by the position in a format
+/// helix understands. Fails when the path contains control characters, see [`reject_control`].
+fn location_target(dir: &Path, location: &FileLocation<AbsolutePath>) -> Result<String> {
+    let rel_path = relative_path(dir, location)?;
+
+    // helix understands the `path:line:column` format, ranges are selected after the file is open
+    Ok(match location.position {
+        Some(Position::Range { start, .. }) => format!("{rel_path}:{start}"),
+        Some(position) => format!("{rel_path}:{position}"),
+        None => rel_path,
     })
 }
 
+/// The path of the location relative to the given directory, the way helix shows it
+pub(crate) fn relative_path(dir: &Path, location: &FileLocation<AbsolutePath>) -> Result<String> {
+    // The path stays absolute when it's not in the directory, e.g. when helix is running in a
+    // different directory of the same project. Paths wrapped across lines are joined when the
+    // selection is read, see [`crate::fs::resolve_selection`].
+    let rel_path = {
+        let path = location.path.as_ref();
+        path.strip_prefix(dir)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .trim()
+            .to_owned()
+    };
+    reject_control(&rel_path)?;
+
+    Ok(rel_path)
+}
+
+// Text typed into helix goes through two interpreters: kitty's `send-text` processes backslash
+// escapes (`\e`, `\r`, `\x1b`, ...), then helix splits its command line into arguments. A path
+// that is not encoded for both could type arbitrary keys, e.g. `\r:sh ...`. Control characters are
+// rejected, they have no business in a path and they can't be typed safely into helix anyway.
+
+/// Fails when the text contains control characters, e.g. a carriage return or an escape
+fn reject_control(text: &str) -> Result<()> {
+    match text.chars().find(|c| c.is_control()) {
+        Some(c) => Err(FelisError::UnsafeText {
+            text: text.to_string(),
+            reason: format!("it contains the control character {c:?}"),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Escapes the text for `kitty @ send-text`, so kitty sends it as it is
+pub(crate) fn kitty_escape(text: &str) -> String {
+    text.replace('\\', r"\\")
+}
+
+/// Quotes the argument for helix' command line, unless it only contains safe characters. Single
+/// quotes are preferred as nothing is expanded within them (e.g. `%sh{...}`), double quotes are
+/// only used for arguments containing a single quote and nothing that could be expanded or
+/// escaped in them.
+pub(crate) fn helix_quote(arg: &str) -> Result<String> {
+    reject_control(arg)?;
+
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "/._-:+,=@".contains(c))
+    {
+        Ok(arg.to_string())
+    } else if !arg.contains('\'') {
+        Ok(format!("'{arg}'"))
+    } else if !arg.contains(['"', '\\', '%', '`']) {
+        Ok(format!("\"{arg}\""))
+    } else {
+        Err(FelisError::UnsafeText {
+            text: arg.to_string(),
+            reason: "it can't be quoted for helix".to_string(),
+        })
+    }
+}
+
 // TODO: this function should be in a different module probably
 #[must_use]
 pub fn focused_active_window(windows: &OsWindows) -> Option<&model::Window> {
@@ -113,32 +625,6 @@ pub fn window_cwd(window: &Window) -> &Path {
     window.foreground_processes[0].cwd.as_path()
 }
 
-fn find_workspace<'a>(windows: &'a OsWindows, path: &AbsolutePath) -> Result<&'a Window> {
-    let workspace_window = windows.0.iter().find_map(|os_window| {
-        os_window.tabs.iter().find_map(|tab| {
-            tab.windows.iter().find(|w| {
-                w.foreground_processes
-                    .iter()
-                    .any(|process| is_helix_bin(process) && is_in_workspace(process, path))
-            })
-        })
-    });
-
-    workspace_window.ok_or_else(|| FelisError::UnexpectedError {
-        message: format!("Couldn't find workspace for file {:?}", path.as_ref()),
-    })
-}
-
-fn is_in_workspace(process: &model::Process, path: &AbsolutePath) -> bool {
-    path.as_ref()
-        .parent()
-        .map_or(false, |p| p.starts_with(process.cwd.as_path()))
-}
-
-fn is_helix_bin(process: &model::Process) -> bool {
-    process.cmdline.iter().any(|c| c.ends_with("bin/hx"))
-}
-
 #[cfg(test)]
 mod test {
 
@@ -148,23 +634,40 @@ mod test {
     };
 
     use kitty_remote_bindings::{
-        command::{options::Matcher, FocusWindow, Ls, SendText},
+        command::{
+            options::{Cwd, LaunchType, Matcher},
+            FocusWindow, Ls, SendText,
+        },
         model::WindowId,
     };
     use mockall::predicate::*;
     use pretty_assertions::assert_eq;
 
     use crate::{
-        command::{get_active_focused_window, open_in_helix},
-        fs::AbsolutePath,
-        kitty_terminal::{test_fixture, KittyTerminal, MockExecutor},
+        command::{
+            get_active_focused_window, helix_quote, kitty_escape, open_in_helix, tag_window,
+            EditorState,
+        },
+        config::{Config, FallbackConfig, FallbackPolicy, OpenConfig, PickerConfig},
+        fs::FileLocation,
+        kitty_terminal::{
+            command::{Extent, GetText, Launch, SetUserVars},
+            test_fixture, KittyTerminal, MockExecutor,
+        },
+        screen::{self, Screen},
+        workspace::WindowTarget,
+        FelisError,
     };
 
     fn expect_ls_success(executor: &mut MockExecutor) {
+        expect_ls(executor, Ls::new());
+    }
+
+    fn expect_ls(executor: &mut MockExecutor, ls: Ls) {
         executor
             .expect_ls()
             .times(1)
-            .with(eq(Ls::new().to("DummySocket".to_string())))
+            .with(eq(ls.to("DummySocket".to_string())))
             .returning(|_| {
                 Ok(Output {
                     status: ExitStatus::from_raw(0),
@@ -216,7 +719,8 @@ mod test {
         let response = get_active_focused_window(&KittyTerminal::mock(executor))
             .await
             .unwrap();
-        assert_eq!(response, WindowId(2));
+        assert_eq!(response.window_id(), WindowId(2));
+        assert_eq!(response.socket, None);
     }
 
     #[tokio::test]
@@ -224,40 +728,321 @@ mod test {
         let path = "/path/to/felis/src/lib.rs";
 
         let mut executor = MockExecutor::new();
-        expect_ls_success(&mut executor);
+        // Only the target window is listed
+        expect_ls(&mut executor, Ls::new().matcher(Matcher::Id(WindowId(1))));
         expect_focus_window_succes(&mut executor, WindowId(1));
-        expect_send_text_success(&mut executor, r"\E", WindowId(1));
-        expect_send_text_success(&mut executor, r":", WindowId(1));
-        expect_send_text_success(&mut executor, r"src/lib.rs", WindowId(1));
-        expect_send_text_success(&mut executor, r"\x01open \r", WindowId(1));
+        expect_send_text_success(
+            &mut executor,
+            r"\x1b[27u:open \x1b[200~src/lib.rs\x1b[201~\r",
+            WindowId(1),
+        );
 
         open_in_helix(
-            &AbsolutePath::try_from(path).unwrap(),
-            Some(WindowId(1)),
+            &[FileLocation::from(path).try_into().unwrap()],
+            Some(&WindowTarget::Id {
+                id: WindowId(1),
+                socket: None,
+            }),
             &KittyTerminal::mock(executor),
-            false,
+            None,
+            &Config::default(),
         )
         .await
         .unwrap();
     }
 
     #[tokio::test]
-    async fn test_open_in_helix_turns_absolute_path_to_relative() {
+    async fn test_open_in_helix_uses_the_kitty_instance_of_the_window() {
         let path = "/path/to/felis/src/lib.rs";
+        let success = |stdout: &str| Output {
+            status: ExitStatus::from_raw(0),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: Vec::new(),
+        };
+
+        // helix is only running in the other instance, and window 1 exists in both
+        let mut executor = MockExecutor::new();
+        executor
+            .expect_ls()
+            .times(1)
+            .returning(move |_| Ok(success("[]")));
+        let mut other = MockExecutor::new();
+        other
+            .expect_ls()
+            .times(1)
+            .with(eq(Ls::new().to("other.sock".to_string())))
+            .returning(move |_| Ok(success(test_fixture::LS_OUTPUT_JSON)));
+        other
+            .expect_send_text()
+            .times(1)
+            .with(eq(SendText::new(
+                r"\x1b[27u:open \x1b[200~src/lib.rs\x1b[201~\r".to_string(),
+            )
+            .matcher(Matcher::Id(WindowId(1)))
+            .to("other.sock".to_string())))
+            .returning(move |_| Ok(success("")));
+        other
+            .expect_focus_window()
+            .times(1)
+            .with(eq(FocusWindow::new()
+                .matcher(Matcher::Id(WindowId(1)))
+                .to("other.sock".to_string())))
+            .returning(move |_| Ok(success("")));
+
+        open_in_helix(
+            &[FileLocation::from(path).try_into().unwrap()],
+            None,
+            &KittyTerminal::mock(executor).mock_instance("other.sock", other),
+            None,
+            &Config::default(),
+        )
+        .await
+        .unwrap();
+    }
+
+    /// helix' screens, read in this order
+    fn expect_screens(executor: &mut MockExecutor, screens: Vec<&'static str>) {
+        let mut screens = screens.into_iter();
+        executor
+            .expect_get_text()
+            .times(screens.len())
+            .with(eq(GetText::new()
+                .matcher(Matcher::Id(WindowId(1)))
+                .extent(Extent::Screen)
+                .to("DummySocket".to_string())))
+            .returning(move |_| {
+                Ok(Output {
+                    status: ExitStatus::from_raw(0),
+                    stdout: screens.next().unwrap().as_bytes().to_vec(),
+                    stderr: Vec::new(),
+                })
+            });
+    }
+
+    async fn open_and_verify(executor: MockExecutor) -> crate::Result<()> {
+        open_with(
+            executor,
+            OpenConfig {
+                verify: true,
+                ..OpenConfig::default()
+            },
+        )
+        .await
+    }
+
+    async fn open_with(executor: MockExecutor, open: OpenConfig) -> crate::Result<()> {
+        let config = Config {
+            open,
+            ..Config::default()
+        };
+        open_in_helix(
+            &[FileLocation::from("/path/to/felis/src/lib.rs")
+                .try_into()
+                .unwrap()],
+            None,
+            &KittyTerminal::mock(executor),
+            None,
+            &config,
+        )
+        .await
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_open_in_helix_verifies_the_screen() {
+        let mut executor = MockExecutor::new();
+        expect_ls_success(&mut executor);
+        expect_send_text_success(
+            &mut executor,
+            r"\x1b[27u:open \x1b[200~src/lib.rs\x1b[201~\r",
+            WindowId(1),
+        );
+        // helix needs a moment to redraw
+        expect_screens(
+            &mut executor,
+            vec![screen::test_fixture::PICKER, screen::test_fixture::OPENED],
+        );
+        expect_focus_window_succes(&mut executor, WindowId(1));
+
+        open_and_verify(executor).await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_open_in_helix_retries_after_a_stronger_reset() {
+        let mut executor = MockExecutor::new();
+        expect_ls_success(&mut executor);
+        expect_send_text_success(
+            &mut executor,
+            r"\x1b[27u:open \x1b[200~src/lib.rs\x1b[201~\r",
+            WindowId(1),
+        );
+        expect_send_text_success(
+            &mut executor,
+            r"\x1b[27u\x1b[27u\x1b[27u:open \x1b[200~src/lib.rs\x1b[201~\r",
+            WindowId(1),
+        );
+        let mut screens = vec![screen::test_fixture::PICKER; 5];
+        screens.push(screen::test_fixture::OPENED);
+        expect_screens(&mut executor, screens);
+        expect_focus_window_succes(&mut executor, WindowId(1));
+
+        open_and_verify(executor).await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_open_in_helix_fails_when_helix_does_not_show_the_file() {
+        let mut executor = MockExecutor::new();
+        expect_ls_success(&mut executor);
+        executor.expect_send_text().times(2).returning(|_| {
+            Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+            })
+        });
+        expect_screens(&mut executor, vec![screen::test_fixture::ERROR; 10]);
+
+        let result = open_and_verify(executor).await;
+
+        match result {
+            Err(FelisError::OpenNotVerified {
+                path,
+                window_id,
+                message,
+            }) => {
+                assert_eq!(path, "src/lib.rs");
+                assert_eq!(window_id, 1);
+                assert_eq!(
+                    message,
+                    "unable to open \"src/lib.rs\": Permission denied (os error 13)"
+                );
+            }
+            result => panic!("Unexpected result: {result:?}"),
+        }
+    }
+
+    #[test]
+    fn test_editor_state_is_classified_from_the_screen() {
+        let classify = |text| EditorState::classify(&Screen::new(text));
+
+        assert_eq!(classify(screen::test_fixture::NORMAL), EditorState::Normal);
+        assert_eq!(classify(screen::test_fixture::INSERT), EditorState::Insert);
+        assert_eq!(classify(screen::test_fixture::SELECT), EditorState::Select);
+        assert_eq!(
+            classify(screen::test_fixture::PENDING),
+            EditorState::Pending
+        );
+        assert_eq!(classify(screen::test_fixture::PROMPT), EditorState::Prompt);
+        assert_eq!(
+            classify(screen::test_fixture::PICKER_PROMPT),
+            EditorState::Picker
+        );
+        assert_eq!(classify(screen::test_fixture::SHELL), EditorState::Unknown);
+        assert_eq!(EditorState::Unknown.reset(), None);
+    }
+
+    #[test]
+    fn test_editor_state_is_unknown_without_the_cursor() {
+        // The same screen as `NORMAL`, read without `--add-cursor`
+        let screen = Screen::new(
+            screen::test_fixture::NORMAL
+                .strip_suffix("\x1b[?25l\x1b[1;5H\x1b[2 q")
+                .unwrap(),
+        );
+
+        assert_eq!(screen.cursor(), None);
+        assert_eq!(screen.mode(), Some("NOR"));
+        assert_eq!(EditorState::classify(&screen), EditorState::Unknown);
+    }
+
+    fn expect_preflight(executor: &mut MockExecutor, screen: &'static str) {
+        executor
+            .expect_get_text()
+            .times(1)
+            .with(eq(GetText::new()
+                .matcher(Matcher::Id(WindowId(1)))
+                .extent(Extent::Screen)
+                .add_cursor(true)
+                .to("DummySocket".to_string())))
+            .returning(move |_| {
+                Ok(Output {
+                    status: ExitStatus::from_raw(0),
+                    stdout: screen.as_bytes().to_vec(),
+                    stderr: Vec::new(),
+                })
+            });
+    }
+
+    #[tokio::test]
+    async fn test_open_in_helix_closes_the_picker_first() {
+        let mut executor = MockExecutor::new();
+        expect_ls_success(&mut executor);
+        expect_preflight(&mut executor, screen::test_fixture::PICKER_PROMPT);
+        expect_send_text_success(
+            &mut executor,
+            r"\x1b[27u\x1b[27u:open \x1b[200~src/lib.rs\x1b[201~\r",
+            WindowId(1),
+        );
+        expect_focus_window_succes(&mut executor, WindowId(1));
+
+        open_with(
+            executor,
+            OpenConfig {
+                preflight: true,
+                ..OpenConfig::default()
+            },
+        )
+        .await
+        .unwrap();
+    }
 
+    #[tokio::test]
+    async fn test_open_in_helix_refuses_to_type_into_an_unknown_state() {
         let mut executor = MockExecutor::new();
         expect_ls_success(&mut executor);
+        expect_preflight(&mut executor, screen::test_fixture::SHELL);
+        executor.expect_send_text().never();
+
+        let result = open_with(
+            executor,
+            OpenConfig {
+                preflight: true,
+                ..OpenConfig::default()
+            },
+        )
+        .await;
+
+        assert!(
+            matches!(
+                result,
+                Err(FelisError::UnknownEditorState { window_id: 1, .. })
+            ),
+            "Unexpected result: {result:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_open_in_helix_turns_absolute_path_to_relative() {
+        let path = "/path/to/felis/src/lib.rs";
+
+        let mut executor = MockExecutor::new();
+        // Only the target window is listed
+        expect_ls(&mut executor, Ls::new().matcher(Matcher::Id(WindowId(1))));
         expect_focus_window_succes(&mut executor, WindowId(1));
-        expect_send_text_success(&mut executor, r"\E", WindowId(1));
-        expect_send_text_success(&mut executor, r":", WindowId(1));
-        expect_send_text_success(&mut executor, r"src/lib.rs", WindowId(1));
-        expect_send_text_success(&mut executor, r"\x01open \r", WindowId(1));
+        expect_send_text_success(
+            &mut executor,
+            r"\x1b[27u:open \x1b[200~src/lib.rs\x1b[201~\r",
+            WindowId(1),
+        );
 
         open_in_helix(
-            &AbsolutePath::try_from(path).unwrap(),
-            Some(WindowId(1)),
+            &[FileLocation::from(path).try_into().unwrap()],
+            Some(&WindowTarget::Id {
+                id: WindowId(1),
+                socket: None,
+            }),
             &KittyTerminal::mock(executor),
-            false,
+            None,
+            &Config::default(),
         )
         .await
         .unwrap();
@@ -269,17 +1054,19 @@ mod test {
 
         let mut executor = MockExecutor::new();
         expect_ls_success(&mut executor);
-        expect_send_text_success(&mut executor, r"\E", WindowId(1));
-        expect_send_text_success(&mut executor, r":", WindowId(1));
-        expect_send_text_success(&mut executor, r"src/lib.rs", WindowId(1));
-        expect_send_text_success(&mut executor, r"\x01open \r", WindowId(1));
+        expect_send_text_success(
+            &mut executor,
+            r"\x1b[27u:open \x1b[200~src/lib.rs\x1b[201~\r",
+            WindowId(1),
+        );
         expect_focus_window_succes(&mut executor, WindowId(1));
 
         open_in_helix(
-            &AbsolutePath::try_from(path).unwrap(),
+            &[FileLocation::from(path).try_into().unwrap()],
             None,
             &KittyTerminal::mock(executor),
-            false,
+            None,
+            &Config::default(),
         )
         .await
         .unwrap();
@@ -291,19 +1078,416 @@ mod test {
 
         let mut executor = MockExecutor::new();
         expect_ls_success(&mut executor);
-        expect_send_text_success(&mut executor, r"\E", WindowId(1));
-        expect_send_text_success(&mut executor, r":", WindowId(1));
-        expect_send_text_success(&mut executor, r"src/lib.rs", WindowId(1));
-        expect_send_text_success(&mut executor, r"\x01open \r", WindowId(1));
+        expect_send_text_success(
+            &mut executor,
+            r"\x1b[27u:open \x1b[200~src/lib.rs\x1b[201~\r",
+            WindowId(1),
+        );
+        expect_focus_window_succes(&mut executor, WindowId(1));
+
+        open_in_helix(
+            &[FileLocation::from(path).try_into().unwrap()],
+            None,
+            &KittyTerminal::mock(executor),
+            None,
+            &Config::default(),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_open_in_helix_jumps_to_line_and_column() {
+        let path = "/path/to/felis/src/lib.rs:13:3";
+
+        let mut executor = MockExecutor::new();
+        expect_ls_success(&mut executor);
+        expect_focus_window_succes(&mut executor, WindowId(1));
+        expect_send_text_success(
+            &mut executor,
+            r"\x1b[27u:open \x1b[200~src/lib.rs:13:3\x1b[201~\r",
+            WindowId(1),
+        );
+
+        open_in_helix(
+            &[FileLocation::from(path).try_into().unwrap()],
+            None,
+            &KittyTerminal::mock(executor),
+            None,
+            &Config::default(),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_open_in_helix_selects_line_range() {
+        let path = "/path/to/felis/src/lib.rs:10-12";
+
+        let mut executor = MockExecutor::new();
+        expect_ls_success(&mut executor);
+        expect_focus_window_succes(&mut executor, WindowId(1));
+        expect_send_text_success(
+            &mut executor,
+            r"\x1b[27u:open \x1b[200~src/lib.rs:10\x1b[201~\r3x",
+            WindowId(1),
+        );
+
+        open_in_helix(
+            &[FileLocation::from(path).try_into().unwrap()],
+            None,
+            &KittyTerminal::mock(executor),
+            None,
+            &Config::default(),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_open_in_helix_opens_files_of_the_same_workspace_in_one_batch() {
+        let paths = ["/path/to/felis/src/lib.rs", "/path/to/felis/src/fs.rs:3"];
+
+        let mut executor = MockExecutor::new();
+        expect_ls_success(&mut executor);
+        expect_send_text_success(
+            &mut executor,
+            r"\x1b[27u:open \x1b[200~src/lib.rs src/fs.rs:3\x1b[201~\r",
+            WindowId(1),
+        );
+        expect_focus_window_succes(&mut executor, WindowId(1));
+
+        open_in_helix(
+            &paths.map(|path| FileLocation::from(path).try_into().unwrap()),
+            None,
+            &KittyTerminal::mock(executor),
+            None,
+            &Config::default(),
+        )
+        .await
+        .unwrap();
+    }
+
+    fn fallback_config(policy: FallbackPolicy) -> Config {
+        Config {
+            fallback: FallbackConfig {
+                policy,
+                ..FallbackConfig::default()
+            },
+            ..Config::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_open_in_helix_fails_without_workspace_by_default() {
+        let mut executor = MockExecutor::new();
+        expect_ls_success(&mut executor);
+
+        let result = open_in_helix(
+            &[FileLocation::from("/path/to/other-project/main.rs")
+                .try_into()
+                .unwrap()],
+            None,
+            &KittyTerminal::mock(executor),
+            None,
+            &Config::default(),
+        )
+        .await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_open_in_helix_launches_helix_as_fallback() {
+        let mut executor = MockExecutor::new();
+        expect_ls_success(&mut executor);
+        executor
+            .expect_launch()
+            .times(1)
+            .with(eq(Launch::new(vec![
+                "hx".to_string(),
+                "main.rs:3".to_string(),
+            ])
+            .launch_type(LaunchType::Tab)
+            .cwd(Cwd::Path("/path/to/other-project".into()))
+            .copy_env(true)
+            .keep_focus(true)
+            .to("DummySocket".to_string())))
+            .returning(|_| {
+                Ok(Output {
+                    status: ExitStatus::from_raw(0),
+                    stdout: b"5".to_vec(),
+                    stderr: Vec::new(),
+                })
+            });
+        expect_focus_window_succes(&mut executor, WindowId(5));
+
+        open_in_helix(
+            &[FileLocation::from("/path/to/other-project/main.rs:3")
+                .try_into()
+                .unwrap()],
+            None,
+            &KittyTerminal::mock(executor),
+            None,
+            &fallback_config(FallbackPolicy::Launch),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_open_in_helix_runs_helix_in_idle_shell_as_fallback() {
+        let mut executor = MockExecutor::new();
+        expect_ls_success(&mut executor);
+        expect_send_text_success(&mut executor, r"hx main.rs\r", WindowId(3));
+        expect_focus_window_succes(&mut executor, WindowId(3));
+
+        open_in_helix(
+            &[FileLocation::from("/path/to/other-project/main.rs")
+                .try_into()
+                .unwrap()],
+            None,
+            &KittyTerminal::mock(executor),
+            None,
+            &fallback_config(FallbackPolicy::Shell),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_open_in_helix_uses_most_recent_helix_as_fallback() {
+        let mut executor = MockExecutor::new();
+        expect_ls_success(&mut executor);
+        expect_send_text_success(
+            &mut executor,
+            r"\x1b[27u:open \x1b[200~/path/to/other-project/main.rs\x1b[201~\r",
+            WindowId(1),
+        );
         expect_focus_window_succes(&mut executor, WindowId(1));
 
         open_in_helix(
-            &AbsolutePath::try_from(path).unwrap(),
+            &[FileLocation::from("/path/to/other-project/main.rs")
+                .try_into()
+                .unwrap()],
             None,
             &KittyTerminal::mock(executor),
+            None,
+            &fallback_config(FallbackPolicy::Recent),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_open_in_helix_falls_back_when_the_picker_is_cancelled() {
+        let mut executor = MockExecutor::new();
+        expect_ls_success(&mut executor);
+        executor
+            .expect_launch()
+            .times(1)
+            .withf(|launch| {
+                let cmd = std::process::Command::from(launch);
+                let args = cmd.get_args().collect::<Vec<_>>();
+                args.contains(&"--wait-for-child-to-exit".as_ref())
+                    && args.contains(&"pick-window".as_ref())
+                    && args.contains(&"1\t\tkitty @ ls  /path/to/felis".as_ref())
+            })
+            .returning(|_| {
+                // The picker exits without writing a choice
+                Ok(Output {
+                    status: ExitStatus::from_raw(0),
+                    stdout: b"0".to_vec(),
+                    stderr: Vec::new(),
+                })
+            });
+        expect_send_text_success(&mut executor, r"hx main.rs\r", WindowId(3));
+        expect_focus_window_succes(&mut executor, WindowId(3));
+
+        open_in_helix(
+            &[FileLocation::from("/path/to/other-project/main.rs")
+                .try_into()
+                .unwrap()],
+            None,
+            &KittyTerminal::mock(executor),
+            None,
+            &Config {
+                picker: PickerConfig {
+                    enabled: true,
+                    remember: false,
+                },
+                ..fallback_config(FallbackPolicy::Shell)
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_tag_window_sets_user_vars_on_the_active_focused_window() {
+        let mut executor = MockExecutor::new();
+        expect_ls_success(&mut executor);
+        executor
+            .expect_set_user_vars()
+            .times(1)
+            .with(eq(SetUserVars::new(vec![
+                "felis_role=editor".to_string(),
+                "felis_project".to_string(),
+            ])
+            .matcher(Matcher::Id(WindowId(2)))
+            .to("DummySocket".to_string())))
+            .returning(|_| {
+                Ok(Output {
+                    status: ExitStatus::from_raw(0),
+                    stdout: Vec::new(),
+                    stderr: Vec::new(),
+                })
+            });
+
+        let window = tag_window(
+            &KittyTerminal::mock(executor),
+            None,
+            &"role=editor".parse().unwrap(),
+            true,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(window.window_id(), WindowId(2));
+    }
+
+    #[tokio::test]
+    async fn test_tag_window_tags_the_focused_window_in_its_kitty_instance() {
+        let success = |stdout: &str| Output {
+            status: ExitStatus::from_raw(0),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: Vec::new(),
+        };
+
+        // The focused window is in the other instance, window 2 doesn't exist in this one
+        let mut executor = MockExecutor::new();
+        executor
+            .expect_ls()
+            .times(1)
+            .returning(move |_| Ok(success("[]")));
+        let mut other = MockExecutor::new();
+        other
+            .expect_ls()
+            .times(1)
+            .returning(move |_| Ok(success(test_fixture::LS_OUTPUT_JSON)));
+        other
+            .expect_set_user_vars()
+            .times(1)
+            .with(eq(SetUserVars::new(vec!["felis_role=editor".to_string()])
+                .matcher(Matcher::Id(WindowId(2)))
+                .to("other.sock".to_string())))
+            .returning(move |_| Ok(success("")));
+
+        let window = tag_window(
+            &KittyTerminal::mock(executor).mock_instance("other.sock", other),
+            None,
+            &"role=editor".parse().unwrap(),
             false,
         )
         .await
         .unwrap();
+
+        assert_eq!(window.socket.as_deref(), Some("other.sock"));
+    }
+
+    #[test]
+    fn test_helix_quote_only_quotes_when_needed() {
+        assert_eq!(helix_quote("src/lib.rs:3:1").unwrap(), "src/lib.rs:3:1");
+        assert_eq!(helix_quote("src/my file.rs").unwrap(), "'src/my file.rs'");
+        assert_eq!(
+            helix_quote("%sh{touch pwned}").unwrap(),
+            "'%sh{touch pwned}'"
+        );
+        assert_eq!(helix_quote("it's.rs").unwrap(), "\"it's.rs\"");
+        assert_eq!(helix_quote("").unwrap(), "''");
+        assert!(helix_quote("it's %sh{touch pwned}").is_err());
+        assert!(helix_quote("it's \"quoted\"").is_err());
+    }
+
+    #[test]
+    fn test_hostile_text_is_rejected_or_escaped() {
+        for text in ["a\rb", "a\x1b:sh", "\x01open", "a\tb", "a\u{9b}b"] {
+            assert!(
+                matches!(helix_quote(text), Err(FelisError::UnsafeText { .. })),
+                "{text:?}"
+            );
+        }
+
+        assert_eq!(kitty_escape(r"src/\E:sh rm\r"), r"src/\\E:sh rm\\r");
+        assert_eq!(kitty_escape(r"\x1b"), r"\\x1b");
+    }
+
+    #[tokio::test]
+    async fn test_open_in_helix_escapes_hostile_paths() {
+        let mut executor = MockExecutor::new();
+        expect_ls_success(&mut executor);
+        expect_focus_window_succes(&mut executor, WindowId(1));
+        // kitty sends `'src/\E:sh touch pwned\r.rs'`, a single argument for helix
+        expect_send_text_success(
+            &mut executor,
+            r"\x1b[27u:open \x1b[200~'src/\\E:sh touch pwned\\r.rs'\x1b[201~\r",
+            WindowId(1),
+        );
+
+        open_in_helix(
+            &[
+                FileLocation::from(r"/path/to/felis/src/\E:sh touch pwned\r.rs")
+                    .try_into()
+                    .unwrap(),
+            ],
+            None,
+            &KittyTerminal::mock(executor),
+            None,
+            &Config::default(),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_open_in_helix_rejects_paths_with_control_characters() {
+        let mut executor = MockExecutor::new();
+        expect_ls_success(&mut executor);
+
+        let result = open_in_helix(
+            &[
+                FileLocation::from("/path/to/felis/src/\x1b:sh touch pwned\r.rs")
+                    .try_into()
+                    .unwrap(),
+            ],
+            None,
+            &KittyTerminal::mock(executor),
+            None,
+            &Config::default(),
+        )
+        .await;
+
+        assert!(matches!(result, Err(FelisError::UnsafeText { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_shell_fallback_escapes_the_command_line_for_kitty() {
+        let mut executor = MockExecutor::new();
+        expect_ls_success(&mut executor);
+        // The shell gets `hx 'it'\''s.rs'`
+        expect_send_text_success(&mut executor, r"hx 'it'\\''s.rs'\r", WindowId(3));
+        expect_focus_window_succes(&mut executor, WindowId(3));
+
+        open_in_helix(
+            &[FileLocation::from("/path/to/other-project/it's.rs")
+                .try_into()
+                .unwrap()],
+            None,
+            &KittyTerminal::mock(executor),
+            None,
+            &fallback_config(FallbackPolicy::Shell),
+        )
+        .await
+        .unwrap();
     }
 }
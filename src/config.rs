@@ -0,0 +1,437 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use kitty_remote_bindings::command::options::LaunchType;
+use serde::Deserialize;
+
+use crate::{plugin::Split, Result};
+
+/// felis' configuration, loaded from `$XDG_CONFIG_HOME/felis/config.toml` (or
+/// `~/.config/felis/config.toml`) when it exists. Every setting is optional.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// User defined extractors, these are tried before the built-in ones
+    pub extractors: Vec<ExtractorConfig>,
+    pub workspace: WorkspaceConfig,
+    pub editor: EditorConfig,
+    pub fallback: FallbackConfig,
+    pub picker: PickerConfig,
+    pub kitty: KittyConfig,
+    pub open: OpenConfig,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkspaceConfig {
+    /// Files or directories marking the root of a project, e.g. `.git`. A helix running anywhere
+    /// in the same project as a file is considered as a candidate to open the file in. A
+    /// `Cargo.toml` only marks the root when it defines a `[workspace]`.
+    pub root_markers: Vec<String>,
+}
+
+impl Default for WorkspaceConfig {
+    fn default() -> Self {
+        Self {
+            root_markers: [".git", ".helix", "Cargo.toml", "flake.nix"]
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+}
+
+/// Rules to recognise helix among the foreground processes of kitty windows, see
+/// [`crate::editor::EditorDetector`]. When set, the rules replace the built-in ones.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct EditorConfig {
+    /// A process is an editor if it matches any of these rules...
+    pub detect: Option<Vec<ProcessRuleConfig>>,
+    /// ...and none of these
+    pub exclude: Option<Vec<ProcessRuleConfig>>,
+}
+
+/// Regexes matched against a process' command line, e.g.
+///
+/// ```toml
+/// [editor]
+/// detect = [{ argv0 = '(^|/)hx$' }, { argv0 = '(^|/)cargo$', args = '--bin[ =]hx' }]
+/// exclude = [{ args = '--health' }]
+/// ```
+///
+/// `argv0` is matched against the program, `args` against the rest of the arguments joined by
+/// spaces. A rule matches when all of its regexes match.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProcessRuleConfig {
+    pub argv0: Option<String>,
+    pub args: Option<String>,
+}
+
+/// What to do when no helix is running in the workspace of a file
+#[derive(Debug, Default, Clone, Copy, Deserialize, PartialEq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum FallbackPolicy {
+    /// Fail with an error
+    #[default]
+    Error,
+    /// Launch a new helix in the root of the file's project
+    Launch,
+    /// Run helix in an idle shell window that is already in the root of the file's project
+    Shell,
+    /// Open the file in the most recently focused helix anyway
+    Recent,
+}
+
+/// Where the `launch` fallback opens the new helix
+#[derive(Debug, Default, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum WindowType {
+    #[default]
+    Tab,
+    Window,
+    OsWindow,
+}
+
+impl From<WindowType> for LaunchType {
+    fn from(value: WindowType) -> Self {
+        match value {
+            WindowType::Tab => LaunchType::Tab,
+            WindowType::Window => LaunchType::Window,
+            WindowType::OsWindow => LaunchType::OsWindow,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct FallbackConfig {
+    pub policy: FallbackPolicy,
+    pub launch_type: WindowType,
+    /// The command that starts helix, the files to open are appended to it
+    pub command: Vec<String>,
+}
+
+impl Default for FallbackConfig {
+    fn default() -> Self {
+        Self {
+            policy: FallbackPolicy::default(),
+            launch_type: WindowType::default(),
+            command: vec!["hx".to_string()],
+        }
+    }
+}
+
+/// Asking which helix to use when a file could be opened in several of them equally well, or in
+/// none of them, see [`crate::picker`]
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PickerConfig {
+    pub enabled: bool,
+    /// Remember the choices per project root, so the same question is only asked once
+    pub remember: bool,
+}
+
+/// How the files are opened in a running helix
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct OpenConfig {
+    /// Check on helix' screen that the file was opened, see [`crate::screen`]. When it wasn't,
+    /// the keys are sent again after closing whatever helix had open, then felis gives up with an
+    /// error.
+    pub verify: bool,
+    /// Check helix' state on its screen before typing anything, to pick the keys going back to
+    /// normal mode, see [`crate::command::EditorState`]. felis refuses to type into helix when
+    /// its state is not recognised.
+    pub preflight: bool,
+    /// How the files are handed to helix, see [`crate::strategy`]
+    pub strategy: Strategy,
+    /// The strategy of the files in a project, keyed by the project's directory. The longest
+    /// directory containing the file wins over `strategy`.
+    pub projects: HashMap<PathBuf, Strategy>,
+    /// Open the files in new splits instead of the current view
+    pub split: Option<Split>,
+}
+
+impl OpenConfig {
+    /// The strategy of the files in the project, the one of the longest configured directory
+    /// containing it, or the default `strategy`
+    #[must_use]
+    pub fn strategy_for(&self, project: &Path) -> Strategy {
+        self.projects
+            .iter()
+            .filter(|(dir, _)| project.starts_with(dir))
+            .max_by_key(|(dir, _)| dir.components().count())
+            .map_or(self.strategy, |(_, strategy)| *strategy)
+    }
+}
+
+/// How the files are handed to helix, see [`crate::strategy::OpenStrategy`]
+#[derive(Debug, Default, Clone, Copy, Deserialize, PartialEq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// Detect it from the helix build and the installed plugin
+    Auto,
+    /// Type the `:open` command, key by key
+    Plain,
+    /// Type the `:open` command, with the paths in a bracketed paste
+    #[default]
+    BracketedPaste,
+    /// Write the paths in a file, then run the `felis-open` command of the steel plugin
+    Steel,
+}
+
+/// How to reach kitty, see [`crate::discovery`]
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct KittyConfig {
+    /// The address kitty listens on, e.g. `unix:/tmp/kitty.sock` or `tcp:localhost:12345`. When
+    /// set, the socket is not searched for.
+    pub socket: Option<String>,
+    /// Search helix in every reachable kitty instance, not only in the one felis talks to
+    pub all_instances: bool,
+    /// The remote control password, for a kitty configured with `remote_control_password`
+    pub password: Option<String>,
+    /// A file containing the remote control password, trailing whitespace is ignored
+    pub password_file: Option<PathBuf>,
+}
+
+impl KittyConfig {
+    /// The remote control password: `password`, the content of `password_file`, or the
+    /// `KITTY_RC_PASSWORD` environment variable, in this order. `None` when there's no password.
+    pub fn password(&self) -> Result<Option<String>> {
+        if let Some(password) = &self.password {
+            return Ok(Some(password.clone()));
+        }
+        if let Some(path) = &self.password_file {
+            let password = std::fs::read_to_string(path)?;
+            return Ok(Some(password.trim_end().to_string()));
+        }
+
+        Ok(std::env::var("KITTY_RC_PASSWORD")
+            .ok()
+            .filter(|password| !password.is_empty()))
+    }
+}
+
+/// A regex based location extractor, e.g.
+///
+/// ```toml
+/// [[extractors]]
+/// name = "pytest"
+/// pattern = '^(?P<path>[^:\s]+):(?P<line>\d+): '
+/// ```
+///
+/// The pattern must have a `path` named group, and it can have `line`, `column` and `end` (last
+/// line of a range) groups.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ExtractorConfig {
+    pub name: String,
+    pub pattern: String,
+}
+
+impl Config {
+    /// Loads the config from the given path, or from the default location if the path is not
+    /// given. A missing config file at the default location is not an error.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        match path {
+            Some(path) => Self::from_file(path),
+            None => match Self::default_path() {
+                Some(path) if path.exists() => Self::from_file(&path),
+                _ => Ok(Self::default()),
+            },
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    #[must_use]
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .map(|dir| dir.join("felis").join("config.toml"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{
+        Config, ExtractorConfig, FallbackConfig, FallbackPolicy, KittyConfig, PickerConfig, Split,
+        Strategy, WindowType, WorkspaceConfig,
+    };
+
+    #[test]
+    fn test_empty_config_is_the_default() {
+        assert_eq!(toml::from_str::<Config>("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_config_with_extractors() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [[extractors]]
+            name = "pytest"
+            pattern = '^(?P<path>[^:\s]+):(?P<line>\d+): '
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.extractors,
+            vec![ExtractorConfig {
+                name: "pytest".to_string(),
+                pattern: r"^(?P<path>[^:\s]+):(?P<line>\d+): ".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_config_with_root_markers() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [workspace]
+            root_markers = [".git", "package.json"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.workspace,
+            WorkspaceConfig {
+                root_markers: vec![".git".to_string(), "package.json".to_string()]
+            }
+        );
+    }
+
+    #[test]
+    fn test_config_with_fallback() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [fallback]
+            policy = "launch"
+            launch_type = "os-window"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.fallback,
+            FallbackConfig {
+                policy: FallbackPolicy::Launch,
+                launch_type: WindowType::OsWindow,
+                command: vec!["hx".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_config_with_picker() {
+        let config = toml::from_str::<Config>(
+            r"
+            [picker]
+            enabled = true
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.picker,
+            PickerConfig {
+                enabled: true,
+                remember: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_config_with_strategy_per_project() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [open]
+            strategy = "auto"
+            split = "vertical"
+
+            [open.projects]
+            "/home/me/src" = "plain"
+            "/home/me/src/api" = "steel"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.open.strategy, Strategy::Auto);
+        assert_eq!(config.open.split, Some(Split::Vertical));
+        assert_eq!(
+            config.open.strategy_for("/home/me/src/api/server".as_ref()),
+            Strategy::Steel
+        );
+        assert_eq!(
+            config.open.strategy_for("/home/me/src/apiary".as_ref()),
+            Strategy::Plain
+        );
+        assert_eq!(
+            config.open.strategy_for("/home/me/notes".as_ref()),
+            Strategy::Auto
+        );
+    }
+
+    #[test]
+    fn test_config_with_kitty_socket() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [kitty]
+            socket = "unix:@kitty"
+            all_instances = true
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.kitty,
+            KittyConfig {
+                socket: Some("unix:@kitty".to_string()),
+                all_instances: true,
+                ..KittyConfig::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_password_from_the_config_comes_before_the_password_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("kitty-password");
+        std::fs::write(&path, "from file\n").unwrap();
+
+        let config = KittyConfig {
+            password: Some("from config".to_string()),
+            password_file: Some(path.clone()),
+            ..KittyConfig::default()
+        };
+        assert_eq!(config.password().unwrap(), Some("from config".to_string()));
+
+        let config = KittyConfig {
+            password_file: Some(path),
+            ..KittyConfig::default()
+        };
+        assert_eq!(config.password().unwrap(), Some("from file".to_string()));
+    }
+
+    #[test]
+    fn test_missing_password_file_is_an_error() {
+        let config = KittyConfig {
+            password_file: Some("/nonexistent/kitty-password".into()),
+            ..KittyConfig::default()
+        };
+
+        assert!(config.password().is_err());
+    }
+}
@@ -0,0 +1,559 @@
+//! Finding the address kitty listens on for remote control, in kitty's format, e.g.
+//! `unix:/tmp/kitty.sock-1234`, `unix:@kitty` or `tcp:localhost:12345`.
+//!
+//! The places are tried in order:
+//!
+//! 1. the `--to` option, or the `socket` setting of the `[kitty]` config
+//! 2. `KITTY_LISTEN_ON`, kitty sets it in the windows it launches
+//! 3. the environment of the ancestor processes, e.g. when a wrapper shell dropped
+//!    `KITTY_LISTEN_ON`, and the `/tmp/kitty.sock-<pid>` sockets of the ancestors. Without `/proc`
+//!    (e.g. on macOS) only the socket of the parent is tried.
+//! 4. `listen_on` in `kitty.conf`, with the pid of the kitty ancestor
+//! 5. the sockets matching `listen_on`, or `/tmp/kitty.sock-*`, whichever accepts a connection
+//!
+//! Except for the explicit override, every address is probed before it's used.
+
+use std::{
+    fmt::Display,
+    net::{TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{FelisError, Result};
+
+/// How long to wait for a TCP connection when probing an address
+const PROBE_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// How many ancestors are checked, to stop on a cycle in a broken `/proc`
+const MAX_ANCESTORS: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct Discovery {
+    /// The explicit override: the `--to` option or the `socket` config
+    pub to: Option<String>,
+    /// The value of `KITTY_LISTEN_ON`
+    pub listen_on: Option<String>,
+    /// The process to start the walk up the ancestors from, i.e. felis' parent
+    pub parent: u32,
+    /// Where the processes are, usually `/proc`
+    pub proc: PathBuf,
+    /// The path of `kitty.conf`
+    pub kitty_conf: Option<PathBuf>,
+    /// The temporary directory, relative unix socket paths are relative to it
+    pub tmp: PathBuf,
+    /// The directory of the `kitty.sock-<pid>` sockets, `/tmp` regardless of the temporary
+    /// directory
+    pub sockets: PathBuf,
+}
+
+/// A process, as found in `/proc`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Process {
+    pid: u32,
+    name: String,
+    parent: u32,
+}
+
+impl Display for Process {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.pid, self.name)
+    }
+}
+
+impl Discovery {
+    /// The discovery of the current process, with the given override
+    #[must_use]
+    pub fn new(to: Option<String>) -> Self {
+        Self {
+            to,
+            listen_on: std::env::var("KITTY_LISTEN_ON").ok(),
+            parent: std::os::unix::process::parent_id(),
+            proc: PathBuf::from("/proc"),
+            kitty_conf: kitty_conf_path(),
+            tmp: std::env::temp_dir(),
+            sockets: PathBuf::from("/tmp"),
+        }
+    }
+
+    /// Finds the address of kitty, the error lists every place that was tried
+    pub fn kitty_socket(&self) -> Result<String> {
+        let mut attempts = Vec::new();
+
+        if let Some(to) = &self.to {
+            return Ok(to.clone());
+        }
+        attempts.push("--to or the socket config: not given".to_string());
+
+        match &self.listen_on {
+            Some(listen_on) if probe(listen_on) => return Ok(listen_on.clone()),
+            Some(listen_on) => {
+                attempts.push(format!("KITTY_LISTEN_ON: {listen_on} is not reachable"));
+            }
+            None => attempts.push("KITTY_LISTEN_ON: not set".to_string()),
+        }
+
+        let ancestors = self.ancestors();
+        if let Some(address) = ancestors
+            .iter()
+            .find_map(|process| self.ancestor_socket(process))
+        {
+            return Ok(address);
+        }
+        if ancestors.is_empty() {
+            // The ancestors can't be listed, but the parent is known
+            let socket = self.pid_socket(self.parent);
+            if probe(&socket) {
+                return Ok(socket);
+            }
+            attempts.push(format!(
+                "ancestor processes: {} can't be read, {socket} is not reachable",
+                self.proc.display()
+            ));
+        } else {
+            attempts.push(format!(
+                "ancestor processes: none of [{}] has a reachable KITTY_LISTEN_ON or {}",
+                ancestors
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                self.sockets.join("kitty.sock-<pid>").display()
+            ));
+        }
+
+        let kitty_pid = ancestors
+            .iter()
+            .find(|process| process.name == "kitty")
+            .map(|process| process.pid);
+        let listen_on = self.configured_listen_on();
+        let mut pattern = format!("unix:{}", self.sockets.join("kitty.sock-*").display());
+        if let Some(listen_on) = &listen_on {
+            pattern = listen_on.replace("{kitty_pid}", "*");
+        }
+        match (&self.kitty_conf, &listen_on, kitty_pid) {
+            (Some(path), Some(listen_on), Some(pid)) => {
+                let address = listen_on.replace("{kitty_pid}", &pid.to_string());
+                if probe(&address) {
+                    return Ok(address);
+                }
+                attempts.push(format!(
+                    "listen_on in {}: {address} is not reachable",
+                    path.display()
+                ));
+            }
+            (Some(path), Some(listen_on), None) => attempts.push(format!(
+                "listen_on in {}: {listen_on}, but no ancestor is kitty to get its pid",
+                path.display()
+            )),
+            (Some(path), None, _) if path.exists() => {
+                attempts.push(format!("listen_on in {}: not set", path.display()));
+            }
+            (Some(path), None, _) => attempts.push(format!("{}: doesn't exist", path.display())),
+            (None, _, _) => attempts.push("kitty.conf: not found".to_string()),
+        }
+
+        if let Some(address) = glob(&pattern).into_iter().find(|address| probe(address)) {
+            return Ok(address);
+        }
+        attempts.push(format!("{pattern}: no reachable socket"));
+
+        Err(FelisError::SocketNotFound { attempts })
+    }
+
+    /// The sockets of the other kitty instances: the existing sockets matching `listen_on` or
+    /// `/tmp/kitty.sock-*` that accept a connection, except the given one
+    #[must_use]
+    pub fn other_sockets(&self, socket: &str) -> Vec<String> {
+        let mut patterns = vec![format!(
+            "unix:{}",
+            self.sockets.join("kitty.sock-*").display()
+        )];
+        if let Some(listen_on) = self.configured_listen_on() {
+            patterns.push(listen_on.replace("{kitty_pid}", "*"));
+        }
+
+        let mut sockets: Vec<String> = Vec::new();
+        for address in patterns.iter().flat_map(|pattern| glob(pattern)) {
+            if address != socket && !sockets.contains(&address) && probe(&address) {
+                sockets.push(address);
+            }
+        }
+
+        sockets
+    }
+
+    /// The parent, its parent, and so on
+    fn ancestors(&self) -> Vec<Process> {
+        let mut ancestors = Vec::new();
+        let mut pid = self.parent;
+        while pid > 1 && ancestors.len() < MAX_ANCESTORS {
+            let Some(process) = self.process(pid) else {
+                break;
+            };
+            pid = process.parent;
+            ancestors.push(process);
+        }
+
+        ancestors
+    }
+
+    /// Reads the name and the parent of the process from `/proc/<pid>/stat`
+    fn process(&self, pid: u32) -> Option<Process> {
+        let stat = std::fs::read_to_string(self.proc.join(pid.to_string()).join("stat")).ok()?;
+        // The name is in parentheses, and it can contain anything, even parentheses
+        let (open, close) = (stat.find('(')?, stat.rfind(')')?);
+        let parent = stat[close + 1..].split_whitespace().nth(1)?.parse().ok()?;
+
+        Some(Process {
+            pid,
+            name: stat[open + 1..close].to_string(),
+            parent,
+        })
+    }
+
+    fn ancestor_socket(&self, process: &Process) -> Option<String> {
+        let environ = std::fs::read(self.proc.join(process.pid.to_string()).join("environ"));
+        let listen_on = environ.ok().and_then(|environ| {
+            environ
+                .split(|byte| *byte == 0)
+                .filter_map(|variable| variable.strip_prefix(b"KITTY_LISTEN_ON="))
+                .find_map(|value| String::from_utf8(value.to_vec()).ok())
+        });
+
+        listen_on
+            .into_iter()
+            .chain([self.pid_socket(process.pid)])
+            .find(|address| probe(address))
+    }
+
+    /// The socket kitty listens on with `--listen-on unix:/tmp/kitty.sock`, when its pid is `pid`
+    fn pid_socket(&self, pid: u32) -> String {
+        format!(
+            "unix:{}",
+            self.sockets.join(format!("kitty.sock-{pid}")).display()
+        )
+    }
+
+    /// The `listen_on` setting of kitty.conf, with the environment variables expanded and the
+    /// `{kitty_pid}` placeholder added where kitty would add it
+    fn configured_listen_on(&self) -> Option<String> {
+        let conf = std::fs::read_to_string(self.kitty_conf.as_ref()?).ok()?;
+        // The last setting wins
+        let listen_on = conf
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("listen_on"))
+            .rfind(|value| value.starts_with(char::is_whitespace))?
+            .trim();
+        let listen_on = expand_vars(listen_on);
+
+        let Some(path) = listen_on.strip_prefix("unix:") else {
+            return Some(listen_on);
+        };
+        let mut path = if path.starts_with(['/', '@']) {
+            path.to_string()
+        } else if let Some(path) = path.strip_prefix("~/") {
+            let home = std::env::var("HOME").ok()?;
+            format!("{home}/{path}")
+        } else {
+            self.tmp.join(path).display().to_string()
+        };
+        // Unless the placeholder is used, kitty appends its pid to the path
+        if !path.contains("{kitty_pid}") {
+            path.push_str("-{kitty_pid}");
+        }
+
+        Some(format!("unix:{path}"))
+    }
+}
+
+/// `$KITTY_CONFIG_DIRECTORY/kitty.conf`, or `kitty/kitty.conf` in `$XDG_CONFIG_HOME` or
+/// `~/.config`
+fn kitty_conf_path() -> Option<PathBuf> {
+    std::env::var_os("KITTY_CONFIG_DIRECTORY")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+                .map(|dir| dir.join("kitty"))
+        })
+        .map(|dir| dir.join("kitty.conf"))
+}
+
+/// Expands `$NAME` and `${NAME}` with the value of the environment variables, unknown variables
+/// are kept as they are
+fn expand_vars(value: &str) -> String {
+    let variable = regex::Regex::new(r"\$(?:\{(\w+)\}|(\w+))").expect("valid regex");
+
+    variable
+        .replace_all(value, |captures: &regex::Captures| {
+            let name = captures
+                .get(1)
+                .or_else(|| captures.get(2))
+                .map_or("", |name| name.as_str());
+            std::env::var(name).unwrap_or_else(|_| captures[0].to_string())
+        })
+        .into_owned()
+}
+
+/// The unix sockets matching the pattern, a `*` in the file name stands for any characters. The
+/// most recently modified sockets come first.
+fn glob(pattern: &str) -> Vec<String> {
+    let Some(path) = pattern.strip_prefix("unix:") else {
+        return Vec::new();
+    };
+    let path = Path::new(path);
+    let (Some(dir), Some(name)) = (
+        path.parent(),
+        path.file_name().and_then(|name| name.to_str()),
+    ) else {
+        return Vec::new();
+    };
+    let Some((prefix, suffix)) = name.split_once('*') else {
+        return vec![pattern.to_string()];
+    };
+
+    let mut sockets = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(std::result::Result::ok)
+        .filter(|entry| {
+            entry.file_name().to_str().is_some_and(|name| {
+                name.len() >= prefix.len() + suffix.len()
+                    && name.starts_with(prefix)
+                    && name.ends_with(suffix)
+            })
+        })
+        .map(|entry| {
+            let modified = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            (modified, format!("unix:{}", entry.path().display()))
+        })
+        .collect::<Vec<_>>();
+    sockets.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+
+    sockets.into_iter().map(|(_, address)| address).collect()
+}
+
+/// Whether kitty accepts connections on the address
+fn probe(address: &str) -> bool {
+    if let Some(path) = address.strip_prefix("unix:") {
+        if let Some(name) = path.strip_prefix('@') {
+            probe_abstract(name)
+        } else {
+            std::os::unix::net::UnixStream::connect(path).is_ok()
+        }
+    } else if let Some(address) = address.strip_prefix("tcp:") {
+        address
+            .to_socket_addrs()
+            .into_iter()
+            .flatten()
+            .any(|address| TcpStream::connect_timeout(&address, PROBE_TIMEOUT).is_ok())
+    } else {
+        false
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn probe_abstract(name: &str) -> bool {
+    use std::os::linux::net::SocketAddrExt;
+
+    std::os::unix::net::SocketAddr::from_abstract_name(name)
+        .and_then(|address| std::os::unix::net::UnixStream::connect_addr(&address))
+        .is_ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn probe_abstract(_name: &str) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::net::UnixListener, path::Path};
+
+    use pretty_assertions::assert_eq;
+
+    use crate::FelisError;
+
+    use super::Discovery;
+
+    /// A fake `/proc`, the processes are given as `(pid, name, parent, environment)`
+    fn fake_proc(dir: &Path, processes: &[(u32, &str, u32, &str)]) {
+        for (pid, name, parent, environ) in processes {
+            let process = dir.join("proc").join(pid.to_string());
+            std::fs::create_dir_all(&process).unwrap();
+            std::fs::write(
+                process.join("stat"),
+                format!("{pid} ({name}) S {parent} {pid} {pid} 0 -1"),
+            )
+            .unwrap();
+            std::fs::write(process.join("environ"), environ.replace(' ', "\0")).unwrap();
+        }
+    }
+
+    fn discovery(dir: &Path) -> Discovery {
+        Discovery {
+            to: None,
+            listen_on: None,
+            parent: 300,
+            proc: dir.join("proc"),
+            kitty_conf: Some(dir.join("kitty.conf")),
+            tmp: dir.join("tmp"),
+            sockets: dir.join("sockets"),
+        }
+    }
+
+    #[test]
+    fn test_override_and_environment_come_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("kitty.sock");
+        let _kitty = UnixListener::bind(&socket).unwrap();
+
+        let mut discovery = discovery(dir.path());
+        discovery.to = Some("tcp:localhost:1".to_string());
+        discovery.listen_on = Some(format!("unix:{}", socket.display()));
+        assert_eq!(discovery.kitty_socket().unwrap(), "tcp:localhost:1");
+
+        discovery.to = None;
+        assert_eq!(
+            discovery.kitty_socket().unwrap(),
+            format!("unix:{}", socket.display())
+        );
+    }
+
+    #[test]
+    fn test_ancestors_are_searched() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sockets")).unwrap();
+        let socket = dir.path().join("kitty-wrapped.sock");
+        let _kitty = UnixListener::bind(&socket).unwrap();
+        // felis <- sh (wrapper without KITTY_LISTEN_ON) <- zsh <- kitty
+        fake_proc(
+            dir.path(),
+            &[
+                (300, "sh", 200, "PATH=/bin"),
+                (
+                    200,
+                    "zsh",
+                    100,
+                    &format!("PATH=/bin KITTY_LISTEN_ON=unix:{}", socket.display()),
+                ),
+                (100, "kitty", 1, ""),
+            ],
+        );
+
+        assert_eq!(
+            discovery(dir.path()).kitty_socket().unwrap(),
+            format!("unix:{}", socket.display())
+        );
+
+        // The socket named after the kitty ancestor
+        std::fs::remove_file(&socket).unwrap();
+        let socket = dir.path().join("sockets").join("kitty.sock-100");
+        let _kitty = UnixListener::bind(&socket).unwrap();
+
+        assert_eq!(
+            discovery(dir.path()).kitty_socket().unwrap(),
+            format!("unix:{}", socket.display())
+        );
+    }
+
+    #[test]
+    fn test_parent_socket_is_tried_without_proc() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sockets")).unwrap();
+        // Another kitty, it's only used when the parent's socket isn't reachable
+        let _other = UnixListener::bind(dir.path().join("sockets").join("kitty.sock-7")).unwrap();
+        let socket = dir.path().join("sockets").join("kitty.sock-300");
+        let _kitty = UnixListener::bind(&socket).unwrap();
+
+        assert_eq!(
+            discovery(dir.path()).kitty_socket().unwrap(),
+            format!("unix:{}", socket.display())
+        );
+    }
+
+    #[test]
+    fn test_listen_on_is_read_from_kitty_conf() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("tmp")).unwrap();
+        std::fs::write(
+            dir.path().join("kitty.conf"),
+            "# listen_on unix:/nowhere\nlisten_on unix:/nowhere\nlisten_on  unix:mykitty\n",
+        )
+        .unwrap();
+        let socket = dir.path().join("tmp").join("mykitty-100");
+        let _kitty = UnixListener::bind(&socket).unwrap();
+        fake_proc(dir.path(), &[(300, "zsh", 100, ""), (100, "kitty", 1, "")]);
+
+        assert_eq!(
+            discovery(dir.path()).kitty_socket().unwrap(),
+            format!("unix:{}", socket.display())
+        );
+
+        // Without a kitty ancestor, the candidates are probed
+        std::fs::write(dir.path().join("tmp").join("mykitty-7"), "").unwrap();
+        fake_proc(dir.path(), &[(300, "zsh", 1, "")]);
+
+        assert_eq!(
+            discovery(dir.path()).kitty_socket().unwrap(),
+            format!("unix:{}", socket.display())
+        );
+    }
+
+    #[test]
+    fn test_other_instances_are_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_dir = dir.path().join("sockets");
+        std::fs::create_dir(&socket_dir).unwrap();
+        std::fs::write(dir.path().join("kitty.conf"), "listen_on unix:@kitty\n").unwrap();
+        let sockets = ["kitty.sock-100", "kitty.sock-200", "kitty.sock-300"]
+            .map(|name| UnixListener::bind(socket_dir.join(name)).unwrap());
+        // Not a socket anymore, e.g. kitty crashed
+        std::fs::write(socket_dir.join("kitty.sock-400"), "").unwrap();
+        let address = |name: &str| format!("unix:{}", socket_dir.join(name).display());
+
+        let mut others = discovery(dir.path()).other_sockets(&address("kitty.sock-200"));
+        others.sort();
+
+        assert_eq!(
+            others,
+            vec![address("kitty.sock-100"), address("kitty.sock-300")]
+        );
+        drop(sockets);
+    }
+
+    #[test]
+    fn test_error_lists_every_attempt() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut discovery = discovery(dir.path());
+        discovery.listen_on = Some("unix:/nowhere/kitty.sock".to_string());
+        fake_proc(dir.path(), &[(300, "zsh", 100, ""), (100, "kitty", 1, "")]);
+        let sockets = dir.path().join("sockets");
+
+        match discovery.kitty_socket() {
+            Err(FelisError::SocketNotFound { attempts }) => assert_eq!(
+                attempts,
+                vec![
+                    "--to or the socket config: not given".to_string(),
+                    "KITTY_LISTEN_ON: unix:/nowhere/kitty.sock is not reachable".to_string(),
+                    format!(
+                        "ancestor processes: none of [300 (zsh), 100 (kitty)] has a reachable \
+                         KITTY_LISTEN_ON or {}/kitty.sock-<pid>",
+                        sockets.display()
+                    ),
+                    format!("{}/kitty.conf: doesn't exist", dir.path().display()),
+                    format!(
+                        "unix:{}/kitty.sock-*: no reachable socket",
+                        sockets.display()
+                    ),
+                ]
+            ),
+            result => panic!("Unexpected result: {result:?}"),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! Recognising helix among the foreground processes of kitty windows.
+//!
+//! Helix can be launched in many ways: `hx` from a distro package or nix profile, `helix` on some
+//! distros, `.hx-wrapped` behind nix wrappers, a relative `hx`, or a `target/debug/hx` built from
+//! source (e.g. with `cargo run`, which is how the steel branch is usually run). The built-in rules
+//! cover these, and they can be replaced in the config, see [`crate::config::EditorConfig`].
+
+use regex::Regex;
+
+use crate::{
+    config::{EditorConfig, ProcessRuleConfig},
+    model::Process,
+    Result,
+};
+
+/// Built-in detection rules as `(argv0, args)` regex pairs
+const DETECT: &[(Option<&str>, Option<&str>)] = &[
+    // hx, helix, /nix/store/...-helix/bin/hx, .hx-wrapped, target/debug/hx, ...
+    (Some(r"(^|/)\.?(hx|helix)(-wrapped)?$"), None),
+    // cargo run --bin hx / cargo run -p helix-term in the helix repository
+    (
+        Some(r"(^|/)cargo$"),
+        Some(r"^run\b.*(--bin[ =]hx|(-p|--package)[ =]helix-term)\b"),
+    ),
+];
+
+/// Built-in exclusion rules, helix invocations that don't run the editor
+const EXCLUDE: &[(Option<&str>, Option<&str>)] = &[(
+    None,
+    Some(r"(^|\s)(--health|-g|--grammar|-V|--version|-h|--help)(\s|$)"),
+)];
+
+/// Regexes matched against a process' command line: `argv0` against the program, `args` against
+/// the rest of the arguments joined by spaces. A rule matches when all of its regexes match.
+pub struct ProcessRule {
+    argv0: Option<Regex>,
+    args: Option<Regex>,
+}
+
+impl ProcessRule {
+    pub fn new(argv0: Option<&str>, args: Option<&str>) -> Result<Self> {
+        Ok(Self {
+            argv0: argv0.map(Regex::new).transpose()?,
+            args: args.map(Regex::new).transpose()?,
+        })
+    }
+
+    #[must_use]
+    pub fn matches(&self, process: &Process) -> bool {
+        let Some((argv0, args)) = process.cmdline.split_first() else {
+            return false;
+        };
+
+        self.argv0.iter().all(|re| re.is_match(argv0))
+            && self.args.iter().all(|re| re.is_match(&args.join(" ")))
+    }
+}
+
+impl TryFrom<&ProcessRuleConfig> for ProcessRule {
+    type Error = crate::FelisError;
+
+    fn try_from(value: &ProcessRuleConfig) -> Result<Self> {
+        Self::new(value.argv0.as_deref(), value.args.as_deref())
+    }
+}
+
+pub struct EditorDetector {
+    detect: Vec<ProcessRule>,
+    exclude: Vec<ProcessRule>,
+}
+
+impl Default for EditorDetector {
+    fn default() -> Self {
+        let rules = |rules: &[(Option<&str>, Option<&str>)]| {
+            rules
+                .iter()
+                .map(|(argv0, args)| {
+                    ProcessRule::new(*argv0, *args).expect("built-in rules should be valid")
+                })
+                .collect()
+        };
+
+        Self {
+            detect: rules(DETECT),
+            exclude: rules(EXCLUDE),
+        }
+    }
+}
+
+impl EditorDetector {
+    /// Uses the rules from the config, and the built-in rules for anything not configured
+    pub fn from_config(config: &EditorConfig) -> Result<Self> {
+        let rules = |rules: &Vec<ProcessRuleConfig>| {
+            rules
+                .iter()
+                .map(ProcessRule::try_from)
+                .collect::<Result<Vec<_>>>()
+        };
+        let default = Self::default();
+
+        Ok(Self {
+            detect: config.detect.as_ref().map_or(Ok(default.detect), rules)?,
+            exclude: config.exclude.as_ref().map_or(Ok(default.exclude), rules)?,
+        })
+    }
+
+    #[must_use]
+    pub fn is_editor(&self, process: &Process) -> bool {
+        self.detect.iter().any(|rule| rule.matches(process))
+            && !self.exclude.iter().any(|rule| rule.matches(process))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{
+        config::{EditorConfig, ProcessRuleConfig},
+        model::Process,
+    };
+
+    use super::EditorDetector;
+
+    fn process(cmdline: &[&str]) -> Process {
+        Process {
+            pid: 1,
+            cwd: PathBuf::from("/"),
+            cmdline: cmdline.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    #[test]
+    fn test_default_rules_detect_known_helix_launch_forms() {
+        let detector = EditorDetector::default();
+
+        for cmdline in [
+            &["/etc/profiles/per-user/user/bin/hx"][..],
+            &["hx", "src/lib.rs"],
+            &["/usr/bin/helix"],
+            &["/nix/store/xxx-helix-23.10/bin/.hx-wrapped", "."],
+            &["/home/user/src/helix/target/debug/hx"],
+            &["cargo", "run", "--bin", "hx", "--", "src/lib.rs"],
+            &["/home/user/.cargo/bin/cargo", "run", "-p", "helix-term"],
+        ] {
+            assert!(detector.is_editor(&process(cmdline)), "{cmdline:?}");
+        }
+    }
+
+    #[test]
+    fn test_default_rules_ignore_other_processes() {
+        let detector = EditorDetector::default();
+
+        for cmdline in [
+            &["-zsh"][..],
+            &["hx", "--health"],
+            &["/usr/bin/hx", "--grammar", "fetch"],
+            &["cargo", "run"],
+            &["/usr/bin/hxd"],
+            &["rust-analyzer"],
+            &[],
+        ] {
+            assert!(!detector.is_editor(&process(cmdline)), "{cmdline:?}");
+        }
+    }
+
+    #[test]
+    fn test_configured_rules_replace_the_defaults() {
+        let detector = EditorDetector::from_config(&EditorConfig {
+            detect: Some(vec![ProcessRuleConfig {
+                argv0: Some("(^|/)my-helix$".to_string()),
+                args: None,
+            }]),
+            exclude: None,
+        })
+        .unwrap();
+
+        assert!(detector.is_editor(&process(&["/opt/bin/my-helix"])));
+        assert!(!detector.is_editor(&process(&["/opt/bin/my-helix", "--health"])));
+        assert!(!detector.is_editor(&process(&["/usr/bin/hx"])));
+    }
+}
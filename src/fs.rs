@@ -1,7 +1,17 @@
-use std::path::{Path, PathBuf};
+pub mod extractor;
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, Lines};
 
 use crate::{command, Environment};
 
+pub use extractor::{Extractors, LocationExtractor, RegexExtractor};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AbsolutePath {
     buf: PathBuf,
 }
@@ -77,16 +87,252 @@ impl AbsolutePath {
     }
 }
 
+/// A position within a file, e.g. the `13:3` part of `src/lib.rs:13:3`. Lines and columns are
+/// 1-based, the same way compilers and test runners print them, and the way helix expects them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    /// `path:line` or `path:line:column`
+    Line { line: usize, column: Option<usize> },
+    /// `path:start-end`, both ends are inclusive
+    Range { start: usize, end: usize },
+}
+
+impl Position {
+    /// The line the cursor should be placed on when the file is opened
+    #[must_use]
+    pub fn line(&self) -> usize {
+        match self {
+            Position::Line { line, .. } => *line,
+            Position::Range { start, .. } => *start,
+        }
+    }
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Position::Line { line, column: None } => write!(f, "{line}"),
+            Position::Line {
+                line,
+                column: Some(column),
+            } => write!(f, "{line}:{column}"),
+            Position::Range { start, end } => write!(f, "{start}-{end}"),
+        }
+    }
+}
+
+/// A path with an optional position, e.g. `src/lib.rs`, `src/lib.rs:13:3` or `src/lib.rs:10-20`.
+///
+/// The path is a plain [`PathBuf`] until it is resolved against an [`Environment`], after that it
+/// becomes an [`AbsolutePath`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileLocation<P = PathBuf> {
+    pub path: P,
+    pub position: Option<Position>,
+}
+
+impl From<&str> for FileLocation {
+    fn from(value: &str) -> Self {
+        let value = value.trim();
+        // grep like tools might leave a trailing colon after the position
+        let trimmed = value.strip_suffix(':').unwrap_or(value);
+
+        if let Some((rest, last)) = trimmed.rsplit_once(':') {
+            if let Some((path, line)) = rest.rsplit_once(':') {
+                if let (Ok(line), Ok(column)) = (line.parse(), last.parse()) {
+                    return Self {
+                        path: PathBuf::from(path),
+                        position: Some(Position::Line {
+                            line,
+                            column: Some(column),
+                        }),
+                    };
+                }
+            }
+
+            if let Some(position) = parse_line_or_range(last) {
+                return Self {
+                    path: PathBuf::from(rest),
+                    position: Some(position),
+                };
+            }
+        }
+
+        Self {
+            path: PathBuf::from(value),
+            position: None,
+        }
+    }
+}
+
+fn parse_line_or_range(value: &str) -> Option<Position> {
+    if let Some((start, end)) = value.split_once('-') {
+        let start = start.parse().ok()?;
+        let end = end.parse().ok()?;
+        (start <= end).then_some(Position::Range { start, end })
+    } else {
+        value
+            .parse()
+            .ok()
+            .map(|line| Position::Line { line, column: None })
+    }
+}
+
+impl TryFrom<FileLocation> for FileLocation<AbsolutePath> {
+    type Error = String;
+
+    fn try_from(value: FileLocation) -> Result<Self, Self::Error> {
+        Ok(FileLocation {
+            path: AbsolutePath::try_from(value.path)?,
+            position: value.position,
+        })
+    }
+}
+
+impl FileLocation {
+    /// Resolves the path of the location, see [`AbsolutePath::resolve`]. A relative path with a
+    /// git diff prefix (e.g. `a/src/lib.rs` copied from `git diff` output) loses the prefix, but
+    /// only when the prefixed path doesn't exist and the unprefixed one does.
+    ///
+    /// # Errors
+    ///
+    /// Returns error when in a terminal environment we cannot find the active focused window
+    pub fn resolve(&self, env: &Environment) -> crate::Result<FileLocation<AbsolutePath>> {
+        let mut path = AbsolutePath::resolve(&self.path, env)?;
+
+        if self.path.is_relative() && !path.as_ref().exists() {
+            let unprefixed = self
+                .path
+                .strip_prefix("a")
+                .or_else(|_| self.path.strip_prefix("b"));
+            if let Ok(unprefixed) = unprefixed {
+                let candidate = AbsolutePath::resolve(&unprefixed, env)?;
+                if !unprefixed.as_os_str().is_empty() && candidate.as_ref().exists() {
+                    path = candidate;
+                }
+            }
+        }
+
+        Ok(FileLocation {
+            path,
+            position: self.position,
+        })
+    }
+}
+
+/// Extracts and resolves a location from each line of the given text, e.g. from a multi-line
+/// selection. A path wrapped across lines (e.g. copied from a small terminal window) is joined back
+/// together: when the lines are not all existing files, but the joined text is.
+///
+/// # Errors
+///
+/// Returns error when a location cannot be resolved
+pub fn resolve_selection(
+    text: &str,
+    extractors: &Extractors,
+    env: &Environment,
+) -> crate::Result<Vec<FileLocation<AbsolutePath>>> {
+    let locations = extractors
+        .extract_all(text)
+        .iter()
+        .map(|location| location.resolve(env))
+        .collect::<crate::Result<Vec<_>>>()?;
+
+    if locations.len() > 1 && !locations.iter().all(|l| l.path.as_ref().exists()) {
+        let joined = text.lines().map(str::trim).collect::<String>();
+        let location = extractors.extract(&joined).resolve(env)?;
+        if location.path.as_ref().exists() {
+            return Ok(vec![location]);
+        }
+    }
+
+    Ok(locations)
+}
+
+/// Reads locations line by line from the given reader (e.g. stdin when the output of `rg -l` or
+/// `git diff --name-only` is piped into felis), extracting and resolving each line as it arrives.
+///
+/// The locations are returned in batches: a batch ends when the reader is idle for a while, or
+/// reaches the end of its input. This way the files of a slow producer are opened as they are
+/// found, while a burst of lines is still opened with a single command per helix instance.
+pub struct LocationReader<'a, R> {
+    lines: Lines<R>,
+    extractors: &'a Extractors,
+    env: &'a Environment,
+    idle: Duration,
+}
+
+impl<'a, R: AsyncBufRead + Unpin> LocationReader<'a, R> {
+    /// How long the reader waits for the next line before ending a batch
+    pub const IDLE: Duration = Duration::from_millis(50);
+
+    #[must_use]
+    pub fn new(reader: R, extractors: &'a Extractors, env: &'a Environment) -> Self {
+        Self {
+            lines: reader.lines(),
+            extractors,
+            env,
+            idle: Self::IDLE,
+        }
+    }
+
+    #[must_use]
+    pub fn idle(self, idle: Duration) -> Self {
+        Self { idle, ..self }
+    }
+
+    /// Returns the next batch of locations, `None` once the reader reaches the end of its input
+    ///
+    /// # Errors
+    ///
+    /// Returns error when reading fails, or when a location cannot be resolved
+    pub async fn next_batch(&mut self) -> crate::Result<Option<Vec<FileLocation<AbsolutePath>>>> {
+        let mut batch = Vec::new();
+
+        // The first line of a batch is waited for without a timeout
+        while batch.is_empty() {
+            match self.lines.next_line().await? {
+                Some(line) => self.push(&mut batch, &line)?,
+                None => return Ok(None),
+            }
+        }
+
+        // `next_line` is cancel safe, a timed out read doesn't lose a partially read line
+        while let Ok(line) = tokio::time::timeout(self.idle, self.lines.next_line()).await {
+            match line? {
+                Some(line) => self.push(&mut batch, &line)?,
+                None => break,
+            }
+        }
+
+        Ok(Some(batch))
+    }
+
+    fn push(&self, batch: &mut Vec<FileLocation<AbsolutePath>>, line: &str) -> crate::Result<()> {
+        if !line.trim().is_empty() {
+            batch.push(self.extractors.extract(line).resolve(self.env)?);
+        }
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::path::{Path, PathBuf};
+    use std::{
+        path::{Path, PathBuf},
+        time::Duration,
+    };
 
-    use kitty_remote_bindings::model::OsWindows;
+    use crate::model::OsWindows;
     use pretty_assertions::assert_eq;
+    use tokio::io::AsyncWriteExt;
 
     use crate::{kitty_terminal::test_fixture, Environment};
 
-    use super::AbsolutePath;
+    use super::{
+        resolve_selection, AbsolutePath, Extractors, FileLocation, LocationReader, Position,
+    };
 
     #[test]
     fn test_absolute_path_resolve_should_return_path_if_absolute_in_shell_env() {
@@ -138,4 +384,221 @@ mod tests {
             PathBuf::from(Path::new("/path/to/felis/file.txt"))
         );
     }
+
+    #[test]
+    fn test_wrapped_path_is_joined() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/workspace_resolver.rs"), "").unwrap();
+        let env = Environment::Shell(dir.path().to_path_buf());
+
+        let locations = resolve_selection(
+            "src/workspace_re\nsolver.rs:13:3",
+            &Extractors::builtin(),
+            &env,
+        )
+        .unwrap();
+
+        assert_eq!(
+            locations,
+            vec![FileLocation {
+                path: AbsolutePath::try_from(dir.path().join("src/workspace_resolver.rs")).unwrap(),
+                position: Some(Position::Line {
+                    line: 13,
+                    column: Some(3)
+                })
+            }]
+        );
+    }
+
+    #[test]
+    fn test_git_diff_prefix_is_stripped_only_when_the_prefixed_path_is_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("a/src")).unwrap();
+        std::fs::write(dir.path().join("a/src/lib.rs"), "").unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/lib.rs"), "").unwrap();
+        std::fs::write(dir.path().join("src/main.rs"), "").unwrap();
+        let env = Environment::Shell(dir.path().to_path_buf());
+
+        let locations = resolve_selection(
+            "a/src/lib.rs\na/src/main.rs\nb/src/main.rs",
+            &Extractors::builtin(),
+            &env,
+        )
+        .unwrap();
+
+        assert_eq!(
+            locations
+                .iter()
+                .map(|location| location.path.as_ref())
+                .collect::<Vec<_>>(),
+            vec![
+                dir.path().join("a/src/lib.rs").as_path(),
+                dir.path().join("src/main.rs").as_path(),
+                dir.path().join("src/main.rs").as_path(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_selection_of_several_paths_is_split() {
+        let env = Environment::Shell(PathBuf::from("/path/to/felis"));
+
+        let locations =
+            resolve_selection("src/lib.rs\nsrc/main.rs:3", &Extractors::builtin(), &env).unwrap();
+
+        assert_eq!(
+            locations
+                .iter()
+                .map(|location| location.path.as_ref())
+                .collect::<Vec<_>>(),
+            vec![
+                Path::new("/path/to/felis/src/lib.rs"),
+                Path::new("/path/to/felis/src/main.rs")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_file_location_from_plain_path() {
+        assert_eq!(
+            FileLocation::from("src/lib.rs"),
+            FileLocation {
+                path: PathBuf::from("src/lib.rs"),
+                position: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_file_location_from_path_with_line_and_column() {
+        assert_eq!(
+            FileLocation::from("src/lib.rs:13:3"),
+            FileLocation {
+                path: PathBuf::from("src/lib.rs"),
+                position: Some(Position::Line {
+                    line: 13,
+                    column: Some(3)
+                })
+            }
+        );
+        assert_eq!(
+            FileLocation::from("src/lib.rs:13:3:\n"),
+            FileLocation {
+                path: PathBuf::from("src/lib.rs"),
+                position: Some(Position::Line {
+                    line: 13,
+                    column: Some(3)
+                })
+            }
+        );
+    }
+
+    #[test]
+    fn test_file_location_from_path_with_line() {
+        assert_eq!(
+            FileLocation::from("/path/to/file.txt:42"),
+            FileLocation {
+                path: PathBuf::from("/path/to/file.txt"),
+                position: Some(Position::Line {
+                    line: 42,
+                    column: None
+                })
+            }
+        );
+    }
+
+    #[test]
+    fn test_file_location_from_path_with_range() {
+        assert_eq!(
+            FileLocation::from("src/lib.rs:10-20"),
+            FileLocation {
+                path: PathBuf::from("src/lib.rs"),
+                position: Some(Position::Range { start: 10, end: 20 })
+            }
+        );
+    }
+
+    #[test]
+    fn test_file_location_keeps_non_numeric_suffixes_in_path() {
+        assert_eq!(
+            FileLocation::from("dir:name/file.txt"),
+            FileLocation {
+                path: PathBuf::from("dir:name/file.txt"),
+                position: None
+            }
+        );
+        assert_eq!(
+            FileLocation::from("file.txt:20-10"),
+            FileLocation {
+                path: PathBuf::from("file.txt:20-10"),
+                position: None
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_location_reader_resolves_each_line() {
+        let input = "src/lib.rs\n\n  --> src/fs.rs:10:5\n/tmp/file.txt\n";
+        let extractors = Extractors::builtin();
+        let env = Environment::Shell(PathBuf::from("/path/to/felis"));
+        let mut reader = LocationReader::new(input.as_bytes(), &extractors, &env);
+
+        assert_eq!(
+            reader.next_batch().await.unwrap(),
+            Some(vec![
+                FileLocation {
+                    path: AbsolutePath::try_from("/path/to/felis/src/lib.rs").unwrap(),
+                    position: None
+                },
+                FileLocation {
+                    path: AbsolutePath::try_from("/path/to/felis/src/fs.rs").unwrap(),
+                    position: Some(Position::Line {
+                        line: 10,
+                        column: Some(5)
+                    })
+                },
+                FileLocation {
+                    path: AbsolutePath::try_from("/tmp/file.txt").unwrap(),
+                    position: None
+                },
+            ])
+        );
+        assert_eq!(reader.next_batch().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_location_reader_ends_a_batch_when_idle() {
+        let (mut writer, input) = tokio::io::duplex(64);
+        let extractors = Extractors::builtin();
+        let env = Environment::Shell(PathBuf::from("/path/to/felis"));
+        let mut reader = LocationReader::new(tokio::io::BufReader::new(input), &extractors, &env)
+            .idle(Duration::from_millis(10));
+        let paths = |batch: Option<Vec<FileLocation<AbsolutePath>>>| {
+            batch
+                .unwrap()
+                .into_iter()
+                .map(|location| location.path.as_ref().to_path_buf())
+                .collect::<Vec<_>>()
+        };
+
+        writer.write_all(b"src/lib.rs\nsrc/fs.rs\n").await.unwrap();
+        assert_eq!(
+            paths(reader.next_batch().await.unwrap()),
+            vec![
+                PathBuf::from("/path/to/felis/src/lib.rs"),
+                PathBuf::from("/path/to/felis/src/fs.rs")
+            ]
+        );
+
+        writer.write_all(b"src/main.rs\n").await.unwrap();
+        assert_eq!(
+            paths(reader.next_batch().await.unwrap()),
+            vec![PathBuf::from("/path/to/felis/src/main.rs")]
+        );
+
+        drop(writer);
+        assert_eq!(reader.next_batch().await.unwrap(), None);
+    }
 }
@@ -0,0 +1,296 @@
+use std::path::PathBuf;
+
+use regex::{Captures, Regex};
+
+use crate::{config::Config, FelisError, Result};
+
+use super::{FileLocation, Position};
+
+/// Finds a file location in a piece of text, e.g. in a line of a compiler's or a test runner's
+/// output that was selected in the terminal.
+pub trait LocationExtractor {
+    /// The name of the extractor, e.g. `rustc`
+    fn name(&self) -> &str;
+    /// Returns the first location found in the given line, if any
+    fn extract(&self, line: &str) -> Option<FileLocation>;
+}
+
+/// An extractor driven by a regular expression. The regex must have a `path` named group, and it
+/// can have `line`, `column` and `end` groups, where `end` is the last line of a line range.
+pub struct RegexExtractor {
+    name: String,
+    regex: Regex,
+}
+
+impl RegexExtractor {
+    pub fn new(name: &str, pattern: &str) -> Result<Self> {
+        let regex = Regex::new(pattern)?;
+
+        if regex.capture_names().flatten().any(|n| n == "path") {
+            Ok(Self {
+                name: name.to_string(),
+                regex,
+            })
+        } else {
+            Err(FelisError::UnexpectedError {
+                message: format!("extractor {name} doesn't have a `path` named group"),
+            })
+        }
+    }
+}
+
+impl LocationExtractor for RegexExtractor {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn extract(&self, line: &str) -> Option<FileLocation> {
+        let captures = self.regex.captures(line)?;
+        let path = captures.name("path")?.as_str();
+        let number = |name: &str, captures: &Captures| {
+            captures
+                .name(name)
+                .and_then(|m| m.as_str().parse::<usize>().ok())
+        };
+
+        let position = match (
+            number("line", &captures),
+            number("column", &captures),
+            number("end", &captures),
+        ) {
+            (Some(start), _, Some(end)) if start <= end => Some(Position::Range { start, end }),
+            (Some(line), column, _) => Some(Position::Line { line, column }),
+            _ => None,
+        };
+
+        Some(FileLocation {
+            path: PathBuf::from(path),
+            position,
+        })
+    }
+}
+
+/// The built-in extractors as `(name, pattern)` pairs, in the order they are tried
+const BUILTIN_EXTRACTORS: &[(&str, &str)] = &[
+    // rustc diagnostics: `  --> src/main.rs:10:5`
+    (
+        "rustc",
+        r"-->\s+(?P<path>[^:\s]+):(?P<line>\d+):(?P<column>\d+)",
+    ),
+    // rust panics: `thread 'main' panicked at src/main.rs:10:5:`
+    (
+        "rust-panic",
+        r"panicked at (?P<path>[^:\s]+):(?P<line>\d+):(?P<column>\d+)",
+    ),
+    // python tracebacks: `  File "app.py", line 42, in <module>`
+    ("python", r#"File "(?P<path>[^"]+)", line (?P<line>\d+)"#),
+    // java stack traces: `at com.x.Foo.bar(Foo.java:12)`, only the file name is available here
+    (
+        "java",
+        r"\bat\s+[\w$.<>/]+\((?P<path>[\w$-]+\.\w+):(?P<line>\d+)\)",
+    ),
+    // git diff headers: `diff --git a/src/lib.rs b/src/lib.rs`
+    ("git-diff-header", r"^diff --git a/(?P<path>\S+) b/"),
+    // git diff paths: `--- a/src/lib.rs` or `+++ b/src/lib.rs`, a bare `a/src/lib.rs` is left to
+    // `FileLocation::resolve`, as it might be a real path
+    ("git-diff", r"^(?:---|\+\+\+)\s+[ab]/(?P<path>\S+)$"),
+    // grep -n / rg --vimgrep: `src/lib.rs:13:    let x = 1;` or `src/lib.rs:13:3:let x = 1;`
+    (
+        "grep",
+        r"^(?P<path>[^:\s][^:]*):(?P<line>\d+)(?::(?P<column>\d+))?:(?:\D|$)",
+    ),
+];
+
+/// An ordered list of extractors, the first match wins. If none of them matches, the text is
+/// parsed as a plain `path[:line[:column]]`, see [`FileLocation::from`].
+pub struct Extractors(Vec<Box<dyn LocationExtractor + Send + Sync>>);
+
+impl Extractors {
+    #[must_use]
+    pub fn builtin() -> Self {
+        Self(
+            BUILTIN_EXTRACTORS
+                .iter()
+                .map(|(name, pattern)| {
+                    Box::new(
+                        RegexExtractor::new(name, pattern)
+                            .expect("built-in extractors should be valid"),
+                    ) as Box<dyn LocationExtractor + Send + Sync>
+                })
+                .collect(),
+        )
+    }
+
+    /// The extractors defined in the config, followed by the built-in ones
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut extractors = config
+            .extractors
+            .iter()
+            .map(|extractor| {
+                RegexExtractor::new(&extractor.name, &extractor.pattern)
+                    .map(|e| Box::new(e) as Box<dyn LocationExtractor + Send + Sync>)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        extractors.extend(Self::builtin().0);
+
+        Ok(Self(extractors))
+    }
+
+    #[must_use]
+    pub fn extract(&self, text: &str) -> FileLocation {
+        self.0
+            .iter()
+            .find_map(|extractor| extractor.extract(text))
+            .unwrap_or_else(|| FileLocation::from(text))
+    }
+
+    /// Extracts a location from each non-empty line of the given text, e.g. from a multi-line
+    /// selection
+    #[must_use]
+    pub fn extract_all(&self, text: &str) -> Vec<FileLocation> {
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| self.extract(line))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        config::{Config, ExtractorConfig},
+        fs::{FileLocation, Position},
+    };
+
+    use super::Extractors;
+
+    fn location(path: &str, line: usize, column: Option<usize>) -> FileLocation {
+        FileLocation {
+            path: PathBuf::from(path),
+            position: Some(Position::Line { line, column }),
+        }
+    }
+
+    #[test]
+    fn test_builtin_extractors() {
+        let extractors = Extractors::builtin();
+
+        assert_eq!(
+            extractors.extract("  --> src/main.rs:10:5"),
+            location("src/main.rs", 10, Some(5))
+        );
+        assert_eq!(
+            extractors.extract("thread 'main' panicked at src/main.rs:10:5:"),
+            location("src/main.rs", 10, Some(5))
+        );
+        assert_eq!(
+            extractors.extract(r#"  File "app.py", line 42, in <module>"#),
+            location("app.py", 42, None)
+        );
+        assert_eq!(
+            extractors.extract("\tat com.x.Foo.bar(Foo.java:12)"),
+            location("Foo.java", 12, None)
+        );
+        assert_eq!(
+            extractors.extract("src/lib.rs:13:    let x = 1;"),
+            location("src/lib.rs", 13, None)
+        );
+        assert_eq!(
+            extractors.extract("src/lib.rs:13:3:let x = 1;"),
+            location("src/lib.rs", 13, Some(3))
+        );
+    }
+
+    #[test]
+    fn test_git_diff_extractors() {
+        let extractors = Extractors::builtin();
+        let expected = FileLocation {
+            path: PathBuf::from("src/lib.rs"),
+            position: None,
+        };
+
+        assert_eq!(extractors.extract("--- a/src/lib.rs"), expected);
+        assert_eq!(extractors.extract("+++ b/src/lib.rs"), expected);
+        assert_eq!(
+            extractors.extract("a/src/lib.rs"),
+            FileLocation {
+                path: PathBuf::from("a/src/lib.rs"),
+                position: None,
+            }
+        );
+        assert_eq!(
+            extractors.extract("diff --git a/src/lib.rs b/src/lib.rs"),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_plain_locations_fall_back_to_file_location_parsing() {
+        let extractors = Extractors::builtin();
+
+        assert_eq!(
+            extractors.extract("src/lib.rs:13:3"),
+            location("src/lib.rs", 13, Some(3))
+        );
+        assert_eq!(
+            extractors.extract("src/lib.rs:10-20"),
+            FileLocation {
+                path: PathBuf::from("src/lib.rs"),
+                position: Some(Position::Range { start: 10, end: 20 })
+            }
+        );
+    }
+
+    #[test]
+    fn test_extract_all_extracts_a_location_per_line() {
+        let extractors = Extractors::builtin();
+
+        assert_eq!(
+            extractors.extract_all("  --> src/main.rs:10:5\n\nsrc/lib.rs\n"),
+            vec![
+                location("src/main.rs", 10, Some(5)),
+                FileLocation {
+                    path: PathBuf::from("src/lib.rs"),
+                    position: None
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_configured_extractors_take_precedence() {
+        let config = Config {
+            extractors: vec![ExtractorConfig {
+                name: "custom".to_string(),
+                pattern: r"^ERROR (?P<path>\S+) lines (?P<line>\d+)-(?P<end>\d+)".to_string(),
+            }],
+            ..Config::default()
+        };
+        let extractors = Extractors::from_config(&config).unwrap();
+
+        assert_eq!(
+            extractors.extract("ERROR src/lib.rs lines 3-7"),
+            FileLocation {
+                path: PathBuf::from("src/lib.rs"),
+                position: Some(Position::Range { start: 3, end: 7 })
+            }
+        );
+    }
+
+    #[test]
+    fn test_configured_extractors_must_have_a_path_group() {
+        let config = Config {
+            extractors: vec![ExtractorConfig {
+                name: "custom".to_string(),
+                pattern: r"^ERROR (?P<file>\S+)".to_string(),
+            }],
+            ..Config::default()
+        };
+
+        assert!(Extractors::from_config(&config).is_err());
+    }
+}
@@ -0,0 +1,133 @@
+//! The files passed to the steel plugin (see `felis.scm`). Every handoff is a file of its own,
+//! named after the helix process it is meant for and the felis process writing it, in a directory
+//! only the user can access. It's written atomically, so the plugin never reads a partial file, and
+//! the plugin removes it once read.
+
+use std::{
+    fs::{DirBuilder, OpenOptions},
+    io::Write,
+    os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+};
+
+use crate::{FelisError, Result};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Handoff {
+    path: PathBuf,
+}
+
+impl Handoff {
+    /// The handoff of this felis process for the helix process, in [`runtime_dir`]
+    ///
+    /// # Errors
+    ///
+    /// Will return Err if the runtime directory can't be created, or it's accessible to others
+    pub fn new(helix_pid: u32) -> Result<Self> {
+        Ok(Self::in_dir(&runtime_dir()?, helix_pid))
+    }
+
+    #[must_use]
+    pub fn in_dir(dir: &Path, helix_pid: u32) -> Self {
+        Self {
+            path: dir.join(format!("open-{helix_pid}-{}", std::process::id())),
+        }
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the contents to a temporary file only the user can read, then moves it in place
+    ///
+    /// # Errors
+    ///
+    /// Will return Err if the file can't be written
+    pub fn write(&self, contents: &[u8]) -> Result<()> {
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&temporary)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        std::fs::rename(&temporary, &self.path)?;
+
+        Ok(())
+    }
+}
+
+/// `$XDG_RUNTIME_DIR/felis`, or `felis-<uid>` in the temporary directory when there's no runtime
+/// directory (e.g. on macOS). The directory is created when it doesn't exist yet.
+///
+/// # Errors
+///
+/// Will return Err if the directory can't be created, or it's not owned by the user or it's
+/// accessible to others
+pub fn runtime_dir() -> Result<PathBuf> {
+    let uid = rustix::process::getuid().as_raw();
+    let dir = match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("felis"),
+        _ => std::env::temp_dir().join(format!("felis-{uid}")),
+    };
+
+    DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+    // The directory might have been created by someone else, e.g. in a shared temporary directory
+    let metadata = std::fs::metadata(&dir)?;
+    if metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(FelisError::UnexpectedError {
+            message: format!(
+                "{} must be owned by the user and only accessible to them",
+                dir.display()
+            ),
+        });
+    }
+
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use pretty_assertions::assert_eq;
+
+    use super::Handoff;
+
+    #[test]
+    fn test_handoff_is_private_and_complete() {
+        let dir = tempfile::tempdir().unwrap();
+        let handoff = Handoff::in_dir(dir.path(), 38411);
+
+        handoff.write(b"src/lib.rs:3\n").unwrap();
+        handoff.write(b"src/main.rs\n").unwrap();
+
+        assert_eq!(
+            handoff.path().file_name().unwrap().to_string_lossy(),
+            format!("open-38411-{}", std::process::id())
+        );
+        assert_eq!(std::fs::read(handoff.path()).unwrap(), b"src/main.rs\n");
+        let mode = std::fs::metadata(handoff.path())
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // Only the handoff is left, not its temporary file
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_handoffs_are_per_helix() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert_ne!(
+            Handoff::in_dir(dir.path(), 1).path(),
+            Handoff::in_dir(dir.path(), 2).path()
+        );
+    }
+}
@@ -1,16 +1,34 @@
 #![allow(clippy::missing_errors_doc)]
+pub mod command;
+mod protocol;
+mod socket;
+mod tty;
+
+use std::future::Future;
 use std::io;
+use std::path::Path;
 use std::process::Output;
+use std::sync::Arc;
 
-use crate::Result;
+use crate::model::{OsWindows, WindowId};
+use crate::{FelisError, Result};
 use async_trait::async_trait;
-use kitty_remote_bindings::command::options::{Cwd, LaunchType, Matcher};
-use kitty_remote_bindings::command::{CommandOutput, FocusWindow, Launch, Ls, SendText};
-use kitty_remote_bindings::model::OsWindows;
+use command::{
+    CloseWindow, FocusTab, GetText, Kitten, Launch, NewWindow, ResizeWindow, ScrollAmount,
+    ScrollWindow, SetTabTitle, SetUserVars, SetWindowTitle, SignalChild, TabMatcher,
+};
+use kitty_remote_bindings::command::options::Matcher;
+use kitty_remote_bindings::command::{CommandOutput, FocusWindow, Ls, SendText};
+use socket::SocketExecutor;
+use tokio::sync::Mutex;
+use tokio::task::JoinSet;
+use tty::TtyExecutor;
 
 #[cfg(test)]
 use mockall::automock;
 
+type BoxFuture<'a, T> = std::pin::Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
 #[cfg_attr(test, automock)]
 #[async_trait]
 pub(crate) trait Executor {
@@ -18,86 +36,393 @@ pub(crate) trait Executor {
     async fn ls(&self, ls: &Ls) -> io::Result<Output>;
     async fn send_text(&self, send_text: &SendText) -> io::Result<Output>;
     async fn focus_window(&self, focus_window: &FocusWindow) -> io::Result<Output>;
+    async fn set_user_vars(&self, set_user_vars: &SetUserVars) -> io::Result<Output>;
+    async fn get_text(&self, get_text: &GetText) -> io::Result<Output>;
+    async fn set_tab_title(&self, set_tab_title: &SetTabTitle) -> io::Result<Output>;
+    async fn set_window_title(&self, set_window_title: &SetWindowTitle) -> io::Result<Output>;
+    async fn close_window(&self, close_window: &CloseWindow) -> io::Result<Output>;
+    async fn focus_tab(&self, focus_tab: &FocusTab) -> io::Result<Output>;
+    async fn new_window(&self, new_window: &NewWindow) -> io::Result<Output>;
+    async fn resize_window(&self, resize_window: &ResizeWindow) -> io::Result<Output>;
+    async fn signal_child(&self, signal_child: &SignalChild) -> io::Result<Output>;
+    async fn scroll_window(&self, scroll_window: &ScrollWindow) -> io::Result<Output>;
+    async fn kitten(&self, kitten: &Kitten) -> io::Result<Output>;
+}
+
+/// Runs the commands with `kitten @`
+#[derive(Default)]
+struct TokioExecutor {
+    /// The remote control password, passed to `kitten` in its environment, so it doesn't show up
+    /// in the process list
+    password: Option<String>,
+}
+
+impl TokioExecutor {
+    fn command(&self, command: std::process::Command) -> tokio::process::Command {
+        let Some(password) = &self.password else {
+            return tokio::process::Command::from(command);
+        };
+
+        // `kitten @ [options] <command> ...`, the password is one of the options
+        let mut args = command.get_args();
+        let mut with_password = tokio::process::Command::new(command.get_program());
+        with_password
+            .args(args.next())
+            .arg("--password-env")
+            .arg(PASSWORD_VAR)
+            .args(args)
+            .env(PASSWORD_VAR, password);
+        with_password
+    }
 }
 
-struct TokioExecutor;
 #[async_trait]
 impl Executor for TokioExecutor {
     async fn launch(&self, launch: &Launch) -> io::Result<Output> {
-        tokio::process::Command::from(Into::<std::process::Command>::into(launch))
-            .output()
-            .await
+        self.command(launch.into()).output().await
     }
+
     async fn ls(&self, ls: &Ls) -> io::Result<Output> {
-        tokio::process::Command::from(Into::<std::process::Command>::into(ls))
-            .output()
-            .await
+        self.command(ls.into()).output().await
     }
+
     async fn send_text(&self, send_text: &SendText) -> io::Result<Output> {
-        tokio::process::Command::from(Into::<std::process::Command>::into(send_text))
-            .output()
-            .await
+        self.command(send_text.into()).output().await
     }
 
     async fn focus_window(&self, focus_window: &FocusWindow) -> io::Result<Output> {
-        tokio::process::Command::from(Into::<std::process::Command>::into(focus_window))
-            .output()
-            .await
+        self.command(focus_window.into()).output().await
+    }
+
+    async fn set_user_vars(&self, set_user_vars: &SetUserVars) -> io::Result<Output> {
+        self.command(set_user_vars.into()).output().await
+    }
+
+    async fn get_text(&self, get_text: &GetText) -> io::Result<Output> {
+        self.command(get_text.into()).output().await
+    }
+
+    async fn set_tab_title(&self, set_tab_title: &SetTabTitle) -> io::Result<Output> {
+        self.command(set_tab_title.into()).output().await
+    }
+
+    async fn set_window_title(&self, set_window_title: &SetWindowTitle) -> io::Result<Output> {
+        self.command(set_window_title.into()).output().await
+    }
+
+    async fn close_window(&self, close_window: &CloseWindow) -> io::Result<Output> {
+        self.command(close_window.into()).output().await
+    }
+
+    async fn focus_tab(&self, focus_tab: &FocusTab) -> io::Result<Output> {
+        self.command(focus_tab.into()).output().await
+    }
+
+    async fn new_window(&self, new_window: &NewWindow) -> io::Result<Output> {
+        self.command(new_window.into()).output().await
+    }
+
+    async fn resize_window(&self, resize_window: &ResizeWindow) -> io::Result<Output> {
+        self.command(resize_window.into()).output().await
+    }
+
+    async fn signal_child(&self, signal_child: &SignalChild) -> io::Result<Output> {
+        self.command(signal_child.into()).output().await
+    }
+
+    async fn scroll_window(&self, scroll_window: &ScrollWindow) -> io::Result<Output> {
+        self.command(scroll_window.into()).output().await
+    }
+
+    async fn kitten(&self, kitten: &Kitten) -> io::Result<Output> {
+        self.command(kitten.into()).output().await
     }
 }
 
+/// The environment variable `kitten` reads the password from
+const PASSWORD_VAR: &str = "KITTY_RC_PASSWORD";
+
+/// What kitty answers when it refuses a command because of the password, i.e. a missing or a wrong
+/// password, or a command the password doesn't allow
+const PASSWORD_REJECTIONS: [&str; 2] = [
+    "rejected this password",
+    "disallowed by remote_control_password",
+];
+
+/// Reports kitty refusing a command because of the password as a distinct error
+fn check_password(output: &Output) -> Result<()> {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success()
+        && PASSWORD_REJECTIONS
+            .iter()
+            .any(|rejection| stderr.contains(rejection))
+    {
+        return Err(FelisError::PasswordRejected {
+            message: stderr.trim().to_string(),
+        });
+    }
+
+    Ok(())
+}
+
 pub struct KittyTerminal {
-    kitty_socket: String,
+    /// The socket kitty listens on, `None` when the commands are sent over the terminal
+    kitty_socket: Option<String>,
     executor: Box<dyn Executor + Send + Sync + 'static>,
+    /// The other kitty instances whose windows are listed too, see [`KittyTerminal::connect_all`]
+    instances: Vec<Arc<KittyTerminal>>,
+    /// The windows listed by [`KittyTerminal::ls`], kept until a command changes them. The other
+    /// instances share the snapshot of the terminal they belong to, so their commands invalidate
+    /// it too.
+    snapshot: Arc<Mutex<Option<OsWindows>>>,
+    /// Whether this is one of the other instances of a terminal
+    member: bool,
 }
 
 impl KittyTerminal {
     #[must_use]
     pub fn new(kitty_socket: String) -> Self {
+        Self::with_executor(Some(kitty_socket), Box::new(TokioExecutor::default()))
+    }
+
+    fn with_executor(
+        kitty_socket: Option<String>,
+        executor: Box<dyn Executor + Send + Sync + 'static>,
+    ) -> Self {
         Self {
             kitty_socket,
-            executor: Box::new(TokioExecutor),
+            executor,
+            instances: Vec::new(),
+            snapshot: Arc::default(),
+            member: false,
+        }
+    }
+
+    /// Connects to kitty's remote control socket, the commands are sent over this connection
+    /// instead of spawning `kitten @` for each of them. Falls back to `kitten @` when the socket
+    /// can't be connected to, e.g. when kitty listens on a TCP socket.
+    ///
+    /// With a password the commands are always run with `kitten @`: kitty only accepts passwords
+    /// in commands encrypted with its public key, and `kitten` takes care of that.
+    pub async fn connect(kitty_socket: String, password: Option<String>) -> Self {
+        let executor: Box<dyn Executor + Send + Sync + 'static> = if password.is_some() {
+            Box::new(TokioExecutor { password })
+        } else {
+            match SocketExecutor::connect(&kitty_socket).await {
+                Ok(executor) => Box::new(executor),
+                Err(_) => Box::new(TokioExecutor::default()),
+            }
+        };
+
+        Self::with_executor(Some(kitty_socket), executor)
+    }
+
+    /// Connects to several kitty instances: the commands go to the first one, except for the
+    /// commands sent to the windows of the other instances (see [`KittyTerminal::instance`]), and
+    /// [`KittyTerminal::ls`] lists the windows of all of them
+    pub async fn connect_all(
+        kitty_socket: String,
+        others: Vec<String>,
+        password: Option<String>,
+    ) -> Self {
+        let mut terminal = Self::connect(kitty_socket, password.clone()).await;
+        for socket in others {
+            let instance = Self::connect(socket, password.clone()).await;
+            terminal.instances.push(Arc::new(terminal.member(instance)));
+        }
+
+        terminal
+    }
+
+    /// Makes the instance one of the other instances of this terminal
+    fn member(&self, instance: KittyTerminal) -> KittyTerminal {
+        KittyTerminal {
+            snapshot: Arc::clone(&self.snapshot),
+            member: true,
+            ..instance
+        }
+    }
+
+    /// The kitty instance listening on the given socket, see [`crate::model::Window::socket`].
+    /// Windows without a socket, or with an unknown one, belong to this instance.
+    #[must_use]
+    pub fn instance(&self, socket: Option<&str>) -> &KittyTerminal {
+        self.instances
+            .iter()
+            .find(|instance| socket.is_some() && instance.kitty_socket.as_deref() == socket)
+            .map_or(self, AsRef::as_ref)
+    }
+
+    /// Sends the commands over the controlling terminal, when felis runs in a kitty window (i.e.
+    /// `KITTY_WINDOW_ID` is set) and kitty doesn't listen on a socket. With a password the commands
+    /// are run with `kitten @`, it uses the terminal as well.
+    pub fn over_tty(password: Option<String>) -> Result<Self> {
+        if std::env::var_os("KITTY_WINDOW_ID").is_none() {
+            return Err(FelisError::UnexpectedError {
+                message: "not running in a kitty window".to_string(),
+            });
         }
+        let executor: Box<dyn Executor + Send + Sync + 'static> = if password.is_some() {
+            Box::new(TokioExecutor { password })
+        } else {
+            Box::new(TtyExecutor::open(Path::new("/dev/tty"))?)
+        };
+
+        Ok(Self::with_executor(None, executor))
     }
 
     #[cfg(test)]
     pub(crate) fn mock(mock_executor: MockExecutor) -> Self {
-        Self {
-            kitty_socket: "DummySocket".to_string(),
-            executor: Box::new(mock_executor),
+        Self::with_executor(Some("DummySocket".to_string()), Box::new(mock_executor))
+    }
+
+    /// Adds another kitty instance, see [`KittyTerminal::connect_all`]
+    #[cfg(test)]
+    pub(crate) fn mock_instance(mut self, kitty_socket: &str, mock_executor: MockExecutor) -> Self {
+        let instance = Self::with_executor(Some(kitty_socket.to_string()), Box::new(mock_executor));
+        self.instances.push(Arc::new(self.member(instance)));
+        self
+    }
+
+    /// Sets the `--to` option of the command, when kitty listens on a socket
+    fn to<C>(&self, command: C, to: fn(C, String) -> C) -> C {
+        match &self.kitty_socket {
+            Some(kitty_socket) => to(command, kitty_socket.clone()),
+            None => command,
         }
     }
 
-    pub async fn launch(&self, args: Vec<String>, launch_type: LaunchType, cwd: Cwd) -> Result<()> {
-        let output = self
-            .executor
-            .launch(
-                &Launch::new(args)
-                    .to(self.kitty_socket.clone())
-                    .launch_type(launch_type)
-                    .cwd(cwd),
-            )
-            .await?;
-        Launch::result(&output)?;
+    /// Runs a command with the executor, every command goes through here so that kitty refusing the
+    /// password is reported the same way for all of them
+    async fn execute<'a, F>(&'a self, command: F) -> Result<Output>
+    where
+        F: FnOnce(&'a (dyn Executor + Send + Sync)) -> BoxFuture<'a, io::Result<Output>>,
+    {
+        let output = command(self.executor.as_ref()).await?;
+        check_password(&output)?;
 
-        Ok(())
+        Ok(output)
+    }
+
+    /// Launches a new window, returns the id of the new window
+    pub async fn launch(&self, launch: Launch) -> Result<WindowId> {
+        let cmd = self.to(launch, Launch::to);
+        let output = self.execute(|executor| executor.launch(&cmd)).await?;
+        self.invalidate().await;
+        let result = Launch::result(&output)?;
+
+        Ok(result)
     }
 
+    /// Launches a new window and waits until the program running in it exits, returns the exit
+    /// code of the program
+    pub async fn launch_and_wait(&self, launch: Launch) -> Result<i32> {
+        let cmd = self.to(launch.wait_for_child_to_exit(true), Launch::to);
+        let output = self.execute(|executor| executor.launch(&cmd)).await?;
+        self.invalidate().await;
+        if !output.status.success() {
+            return Err(kitty_remote_bindings::Error::ErrorExit(format!(
+                "kitty @ launch: {}",
+                String::from_utf8_lossy(&output.stderr),
+            ))
+            .into());
+        }
+        // kitty prints the exit code where it would print the id of the new window otherwise
+        let result = serde_json::from_slice::<i32>(&output.stdout)
+            .map_err(kitty_remote_bindings::Error::from)?;
+
+        Ok(result)
+    }
+
+    /// Lists the windows, of every instance when there are several. The result is a snapshot
+    /// shared by the following calls, until a command changes the windows (e.g. `launch`) or
+    /// [`KittyTerminal::invalidate`] is called. The other instances only list their own windows.
     pub async fn ls(&self) -> Result<OsWindows> {
-        let output = self
-            .executor
-            .ls(&Ls::new().to(self.kitty_socket.clone()))
-            .await?;
-        let result = Ls::result(&output)?;
+        if self.member {
+            return self.ls_instance(Ls::new()).await;
+        }
+
+        let mut snapshot = self.snapshot.lock().await;
+        if let Some(windows) = snapshot.as_ref() {
+            return Ok(windows.clone());
+        }
+        let windows = self.ls_all().await?;
+        *snapshot = Some(windows.clone());
+
+        Ok(windows)
+    }
+
+    /// Lists the windows matching, when only they matter. Only kitty's own instance is asked, so
+    /// the listing is narrowed when there's a single instance and no snapshot yet, otherwise all
+    /// the windows are listed.
+    pub async fn ls_matching(&self, matcher: Matcher) -> Result<OsWindows> {
+        if self.member || !self.instances.is_empty() || self.snapshot.lock().await.is_some() {
+            return self.ls().await;
+        }
+
+        self.ls_instance(Ls::new().matcher(matcher)).await
+    }
+
+    /// Drops the snapshot of the windows, the next [`KittyTerminal::ls`] asks kitty again. The
+    /// commands changing the windows do it themselves.
+    pub async fn invalidate(&self) {
+        *self.snapshot.lock().await = None;
+    }
+
+    /// The windows of every instance. The windows of the other instances are listed concurrently,
+    /// and they are tagged with their socket. An instance that doesn't answer is skipped, e.g.
+    /// kitty may have been closed since it was found.
+    async fn ls_all(&self) -> Result<OsWindows> {
+        let mut others = JoinSet::new();
+        for (index, instance) in self.instances.iter().enumerate() {
+            let instance = Arc::clone(instance);
+            others.spawn(async move { (index, instance.ls_instance(Ls::new()).await) });
+        }
+
+        let mut windows = self.ls_instance(Ls::new()).await?;
+        if others.is_empty() {
+            return Ok(windows);
+        }
+        windows.tag(self.kitty_socket.as_deref());
+
+        let mut results = Vec::new();
+        while let Some(result) = others.join_next().await {
+            results.push(result.map_err(io::Error::from)?);
+        }
+        results.sort_by_key(|(index, _)| *index);
+        for (index, result) in results {
+            if let Ok(mut instance_windows) = result {
+                instance_windows.tag(self.instances[index].kitty_socket.as_deref());
+                windows.0.extend(instance_windows);
+            }
+        }
+
+        Ok(windows)
+    }
+
+    async fn ls_instance(&self, ls: Ls) -> Result<OsWindows> {
+        let cmd = self.to(ls, Ls::to);
+        let output = self.execute(|executor| executor.ls(&cmd)).await?;
+        // The window tree is decoded into felis' own model, see `crate::model`, but the errors are
+        // reported the same way as the bindings would do it
+        if !output.status.success() {
+            return Err(kitty_remote_bindings::Error::ErrorExit(format!(
+                "kitty @ ls: {}",
+                String::from_utf8_lossy(&output.stderr),
+            ))
+            .into());
+        }
+        let result = serde_json::from_slice::<OsWindows>(&output.stdout)
+            .map_err(kitty_remote_bindings::Error::from)?;
 
         Ok(result)
     }
 
     pub async fn send_text(&self, matcher: Matcher, text: &str) -> Result<()> {
-        let cmd = SendText::new(text.to_string())
-            .to(self.kitty_socket.clone())
+        let cmd = self
+            .to(SendText::new(text.to_string()), SendText::to)
             .matcher(matcher);
-        let output = self.executor.send_text(&cmd).await?;
+        let output = self.execute(|executor| executor.send_text(&cmd)).await?;
 
         SendText::result(&output)?;
 
@@ -105,29 +430,220 @@ impl KittyTerminal {
     }
 
     pub async fn focus_window(&self, matcher: Matcher) -> Result<()> {
-        let cmd = FocusWindow::new()
-            .to(self.kitty_socket.clone())
+        let cmd = self
+            .to(FocusWindow::new(), FocusWindow::to)
             .matcher(matcher);
-        let output = self.executor.focus_window(&cmd).await?;
+        let output = self.execute(|executor| executor.focus_window(&cmd)).await?;
+        self.invalidate().await;
 
         SendText::result(&output)?;
 
         Ok(())
     }
+
+    /// Sets user variables on the matching window, in the `NAME=VALUE` format
+    pub async fn set_user_vars(&self, matcher: Matcher, vars: Vec<String>) -> Result<()> {
+        let cmd = self
+            .to(SetUserVars::new(vars), SetUserVars::to)
+            .matcher(matcher);
+        let output = self
+            .execute(|executor| executor.set_user_vars(&cmd))
+            .await?;
+        self.invalidate().await;
+
+        SetUserVars::result(&output)?;
+
+        Ok(())
+    }
+
+    /// The text of a window, see [`GetText`] for what is included
+    pub async fn get_text(&self, get_text: GetText) -> Result<String> {
+        let cmd = self.to(get_text, GetText::to);
+        let output = self.execute(|executor| executor.get_text(&cmd)).await?;
+        let result = GetText::result(&output)?;
+
+        Ok(result)
+    }
+
+    pub async fn set_tab_title(&self, matcher: TabMatcher, title: &str) -> Result<()> {
+        let cmd = self
+            .to(SetTabTitle::new(title.to_string()), SetTabTitle::to)
+            .matcher(matcher);
+        let output = self
+            .execute(|executor| executor.set_tab_title(&cmd))
+            .await?;
+        self.invalidate().await;
+
+        SetTabTitle::result(&output)?;
+
+        Ok(())
+    }
+
+    /// Sets the title of a window, with `temporary` the program running in the window can change
+    /// it again
+    pub async fn set_window_title(
+        &self,
+        matcher: Matcher,
+        title: &str,
+        temporary: bool,
+    ) -> Result<()> {
+        let cmd = self
+            .to(SetWindowTitle::new(title.to_string()), SetWindowTitle::to)
+            .matcher(matcher)
+            .temporary(temporary);
+        let output = self
+            .execute(|executor| executor.set_window_title(&cmd))
+            .await?;
+        self.invalidate().await;
+
+        SetWindowTitle::result(&output)?;
+
+        Ok(())
+    }
+
+    pub async fn close_window(&self, matcher: Matcher) -> Result<()> {
+        let cmd = self
+            .to(CloseWindow::new(), CloseWindow::to)
+            .matcher(matcher);
+        let output = self.execute(|executor| executor.close_window(&cmd)).await?;
+        self.invalidate().await;
+
+        CloseWindow::result(&output)?;
+
+        Ok(())
+    }
+
+    pub async fn focus_tab(&self, matcher: TabMatcher) -> Result<()> {
+        let cmd = self.to(FocusTab::new(), FocusTab::to).matcher(matcher);
+        let output = self.execute(|executor| executor.focus_tab(&cmd)).await?;
+        self.invalidate().await;
+
+        FocusTab::result(&output)?;
+
+        Ok(())
+    }
+
+    /// Opens a new window, returns the id of the new window
+    pub async fn new_window(&self, new_window: NewWindow) -> Result<WindowId> {
+        let cmd = self.to(new_window, NewWindow::to);
+        let output = self.execute(|executor| executor.new_window(&cmd)).await?;
+        self.invalidate().await;
+        let result = NewWindow::result(&output)?;
+
+        Ok(result)
+    }
+
+    pub async fn resize_window(&self, resize_window: ResizeWindow) -> Result<()> {
+        let cmd = self.to(resize_window, ResizeWindow::to);
+        let output = self
+            .execute(|executor| executor.resize_window(&cmd))
+            .await?;
+        self.invalidate().await;
+
+        ResizeWindow::result(&output)?;
+
+        Ok(())
+    }
+
+    /// Sends signals, e.g. `SIGINT`, to the foreground process of a window
+    pub async fn signal_child(&self, matcher: Matcher, signals: Vec<String>) -> Result<()> {
+        let cmd = self
+            .to(SignalChild::new(signals), SignalChild::to)
+            .matcher(matcher);
+        let output = self.execute(|executor| executor.signal_child(&cmd)).await?;
+        self.invalidate().await;
+
+        SignalChild::result(&output)?;
+
+        Ok(())
+    }
+
+    pub async fn scroll_window(&self, matcher: Matcher, amount: ScrollAmount) -> Result<()> {
+        let cmd = self
+            .to(ScrollWindow::new(amount), ScrollWindow::to)
+            .matcher(matcher);
+        let output = self
+            .execute(|executor| executor.scroll_window(&cmd))
+            .await?;
+
+        ScrollWindow::result(&output)?;
+
+        Ok(())
+    }
+
+    /// Runs a kitten in kitty, returns what the kitten returned
+    pub async fn kitten(&self, kitten: Kitten) -> Result<String> {
+        let cmd = self.to(kitten, Kitten::to);
+        let output = self.execute(|executor| executor.kitten(&cmd)).await?;
+        self.invalidate().await;
+        let result = Kitten::result(&output)?;
+
+        Ok(result)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::process::{ExitStatus, Output};
+    use std::{
+        ffi::OsStr,
+        os::unix::process::ExitStatusExt,
+        process::{ExitStatus, Output},
+    };
 
     use kitty_remote_bindings::{
-        command::{options::Matcher, Ls, SendText},
-        model::WindowId,
+        command::{
+            options::{LaunchType, Matcher},
+            Ls, SendText,
+        },
+        model::{TabId, WindowId},
     };
     use mockall::predicate::eq;
     use pretty_assertions::assert_eq;
 
-    use super::{test_fixture, KittyTerminal, MockExecutor};
+    use crate::FelisError;
+
+    use super::{
+        command::{Extent, FocusTab, GetText, Launch, SetTabTitle, TabMatcher},
+        test_fixture, KittyTerminal, MockExecutor, TokioExecutor,
+    };
+
+    /// The output of the commands that don't print anything
+    fn success() -> Output {
+        Output {
+            status: ExitStatus::default(),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_launch_should_return_the_id_of_the_new_window() {
+        let mut executor = MockExecutor::new();
+
+        executor
+            .expect_launch()
+            .with(eq(Launch::new(vec!["hx".to_string()])
+                .launch_type(LaunchType::Tab)
+                .to("dummy.sock".to_string())))
+            .times(1)
+            .returning(|_| {
+                Ok(Output {
+                    status: ExitStatus::default(),
+                    stdout: b"7\n".to_vec(),
+                    stderr: Vec::new(),
+                })
+            });
+
+        let terminal =
+            KittyTerminal::with_executor(Some("dummy.sock".to_string()), Box::new(executor));
+
+        let result = terminal
+            .launch(Launch::new(vec!["hx".to_string()]).launch_type(LaunchType::Tab))
+            .await
+            .expect("launch() returned an error");
+
+        assert_eq!(result, WindowId(7));
+    }
 
     #[tokio::test]
     async fn test_ls_should_execute_the_ls_remote_command() {
@@ -145,13 +661,139 @@ mod tests {
                 })
             });
 
-        let terminal = KittyTerminal {
-            kitty_socket: "dummy.sock".to_string(),
-            executor: Box::new(executor),
+        let terminal =
+            KittyTerminal::with_executor(Some("dummy.sock".to_string()), Box::new(executor));
+
+        let result = terminal.ls().await.expect("ls() returned an error");
+
+        assert_eq!(result, *test_fixture::LS_OUTPUT);
+    }
+
+    #[tokio::test]
+    async fn test_ls_lists_the_windows_of_every_instance() {
+        let expect_ls = |socket: &str, stdout: &'static str, code: i32| {
+            let mut executor = MockExecutor::new();
+            executor
+                .expect_ls()
+                .with(eq(Ls::new().to(socket.to_string())))
+                .times(1)
+                .returning(move |_| {
+                    Ok(Output {
+                        status: ExitStatus::from_raw(code),
+                        stdout: stdout.as_bytes().to_vec(),
+                        stderr: Vec::new(),
+                    })
+                });
+            executor
         };
 
+        let terminal =
+            KittyTerminal::mock(expect_ls("DummySocket", test_fixture::LS_OUTPUT_JSON, 0))
+                .mock_instance("gone.sock", expect_ls("gone.sock", "", 1 << 8))
+                .mock_instance(
+                    "other.sock",
+                    expect_ls("other.sock", test_fixture::LS_OUTPUT_JSON, 0),
+                );
+
         let result = terminal.ls().await.expect("ls() returned an error");
 
+        let mut expected = test_fixture::LS_OUTPUT.clone();
+        expected.tag(Some("DummySocket"));
+        let mut other = test_fixture::LS_OUTPUT.clone();
+        other.tag(Some("other.sock"));
+        expected.0.extend(other);
+        assert_eq!(result, expected);
+        assert_eq!(
+            terminal
+                .instance(Some("other.sock"))
+                .kitty_socket
+                .as_deref(),
+            Some("other.sock")
+        );
+        assert_eq!(
+            terminal.instance(None).kitty_socket.as_deref(),
+            Some("DummySocket")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ls_snapshot_is_shared_until_the_windows_change() {
+        let ls_output = || {
+            Ok(Output {
+                status: ExitStatus::default(),
+                stdout: test_fixture::LS_OUTPUT_JSON.as_bytes().to_vec(),
+                stderr: Vec::new(),
+            })
+        };
+        let mut executor = MockExecutor::new();
+        // Listed once, then again after each command changing the windows
+        executor
+            .expect_ls()
+            .times(3)
+            .returning(move |_| ls_output());
+        executor.expect_launch().times(1).returning(|_| {
+            Ok(Output {
+                status: ExitStatus::default(),
+                stdout: b"9".to_vec(),
+                stderr: Vec::new(),
+            })
+        });
+        let mut other = MockExecutor::new();
+        other.expect_ls().times(3).returning(move |_| ls_output());
+        other
+            .expect_focus_window()
+            .times(1)
+            .returning(|_| Ok(success()));
+        let terminal = KittyTerminal::mock(executor).mock_instance("other.sock", other);
+
+        let windows = terminal.ls().await.unwrap();
+        assert_eq!(terminal.ls().await.unwrap(), windows);
+        assert_eq!(
+            terminal
+                .ls_matching(Matcher::Id(WindowId(1)))
+                .await
+                .unwrap(),
+            windows
+        );
+
+        terminal
+            .launch(Launch::new(vec!["hx".to_string()]))
+            .await
+            .unwrap();
+        terminal.ls().await.unwrap();
+
+        // A command sent to the other instance invalidates the snapshot too
+        terminal
+            .instance(Some("other.sock"))
+            .focus_window(Matcher::Id(WindowId(1)))
+            .await
+            .unwrap();
+        terminal.ls().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ls_matching_narrows_the_listing() {
+        let mut executor = MockExecutor::new();
+        executor
+            .expect_ls()
+            .with(eq(Ls::new()
+                .matcher(Matcher::Id(WindowId(3)))
+                .to("DummySocket".to_string())))
+            .times(1)
+            .returning(|_| {
+                Ok(Output {
+                    status: ExitStatus::default(),
+                    stdout: test_fixture::LS_OUTPUT_JSON.as_bytes().to_vec(),
+                    stderr: Vec::new(),
+                })
+            });
+        let terminal = KittyTerminal::mock(executor);
+
+        let result = terminal
+            .ls_matching(Matcher::Id(WindowId(3)))
+            .await
+            .expect("ls_matching() returned an error");
+
         assert_eq!(result, *test_fixture::LS_OUTPUT);
     }
 
@@ -177,28 +819,141 @@ mod tests {
                 })
             });
 
-        let terminal = KittyTerminal {
-            kitty_socket: "dummy.sock".to_string(),
-            executor: Box::new(executor),
-        };
+        let terminal =
+            KittyTerminal::with_executor(Some("dummy.sock".to_string()), Box::new(executor));
 
         terminal
             .send_text(matcher, "text message")
             .await
             .expect("ls() returned an error");
     }
+
+    #[tokio::test]
+    async fn test_get_text_returns_the_text_of_the_window() {
+        let mut executor = MockExecutor::new();
+        executor
+            .expect_get_text()
+            .with(eq(GetText::new()
+                .matcher(Matcher::Id(WindowId(4)))
+                .extent(Extent::Screen)
+                .to("DummySocket".to_string())))
+            .times(1)
+            .returning(|_| {
+                Ok(Output {
+                    status: ExitStatus::default(),
+                    stdout: b"src/lib.rs:12:5: error".to_vec(),
+                    stderr: Vec::new(),
+                })
+            });
+        let terminal = KittyTerminal::mock(executor);
+
+        let result = terminal
+            .get_text(
+                GetText::new()
+                    .matcher(Matcher::Id(WindowId(4)))
+                    .extent(Extent::Screen),
+            )
+            .await
+            .expect("get_text() returned an error");
+
+        assert_eq!(result, "src/lib.rs:12:5: error");
+    }
+
+    #[tokio::test]
+    async fn test_tab_commands_match_tabs() {
+        let mut executor = MockExecutor::new();
+        executor
+            .expect_set_tab_title()
+            .with(eq(SetTabTitle::new("api".to_string())
+                .matcher(TabMatcher::WindowId(WindowId(4)))
+                .to("DummySocket".to_string())))
+            .times(1)
+            .returning(|_| Ok(success()));
+        executor
+            .expect_focus_tab()
+            .with(eq(FocusTab::new()
+                .matcher(TabMatcher::Id(TabId(2)))
+                .to("DummySocket".to_string())))
+            .times(1)
+            .returning(|_| Ok(success()));
+        let terminal = KittyTerminal::mock(executor);
+
+        terminal
+            .set_tab_title(TabMatcher::WindowId(WindowId(4)), "api")
+            .await
+            .expect("set_tab_title() returned an error");
+        terminal
+            .focus_tab(TabMatcher::Id(TabId(2)))
+            .await
+            .expect("focus_tab() returned an error");
+    }
+
+    #[tokio::test]
+    async fn test_refused_password_is_a_distinct_error() {
+        const REJECTED: &str = "Error: The user rejected this password or it is disallowed by \
+                                remote_control_password in kitty.conf";
+        let mut executor = MockExecutor::new();
+        let mut stderrs = [
+            REJECTED,
+            // Another failure, even when it mentions a password
+            "Error: No matching windows for expression: title:password",
+        ]
+        .into_iter();
+        executor.expect_focus_window().times(2).returning(move |_| {
+            Ok(Output {
+                status: ExitStatus::from_raw(1 << 8),
+                stdout: Vec::new(),
+                stderr: format!("{}\n", stderrs.next().unwrap()).into_bytes(),
+            })
+        });
+        let terminal = KittyTerminal::mock(executor);
+
+        match terminal.focus_window(Matcher::Id(WindowId(1))).await {
+            Err(FelisError::PasswordRejected { message }) => assert_eq!(message, REJECTED),
+            result => panic!("Unexpected result: {result:?}"),
+        }
+        let result = terminal.focus_window(Matcher::Id(WindowId(1))).await;
+        assert!(
+            !matches!(result, Err(FelisError::PasswordRejected { .. })),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn test_password_is_passed_to_kitten_in_its_environment() {
+        let executor = TokioExecutor {
+            password: Some("secret".to_string()),
+        };
+
+        let command = executor.command((&Ls::new().to("unix:kitty.sock".to_string())).into());
+
+        let command = command.as_std();
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [
+                "@",
+                "--password-env",
+                "KITTY_RC_PASSWORD",
+                "--to",
+                "unix:kitty.sock",
+                "ls"
+            ]
+        );
+        assert_eq!(
+            command.get_envs().collect::<Vec<_>>(),
+            [(OsStr::new("KITTY_RC_PASSWORD"), Some(OsStr::new("secret")))]
+        );
+    }
 }
 
 #[cfg(test)]
 pub mod test_fixture {
 
-    use std::path::PathBuf;
+    use std::{collections::HashMap, path::PathBuf};
 
     use lazy_static::lazy_static;
 
-    use kitty_remote_bindings::model::{
-        OsWindow, OsWindowId, OsWindows, Process, Tab, TabId, Window, WindowId,
-    };
+    use crate::model::{OsWindow, OsWindowId, OsWindows, Process, Tab, TabId, Window, WindowId};
 
     lazy_static! {
     pub static ref LS_OUTPUT: OsWindows = OsWindows(
@@ -212,11 +967,19 @@ pub mod test_fixture {
                         id: TabId(1u32),
                         is_active: true,
                         is_focused: true,
+                        title: "kitty @ ls".to_string(),
+                        active_window_history: vec![WindowId(3u32), WindowId(2u32), WindowId(1u32)],
                         windows: vec![
                             Window {
                                 id: WindowId(1u32),
                                 is_active: false,
                                 is_focused: false,
+                                title: "hx".to_string(),
+                                pid: Some(863),
+                                cwd: Some(PathBuf::from("/path/to/felis")),
+                                cmdline: vec!["-zsh".to_string()],
+                                env: HashMap::new(),
+                                is_self: false,
                                 foreground_processes: vec![
                                 Process {
                                     cmdline: vec![
@@ -239,11 +1002,19 @@ pub mod test_fixture {
                                   cwd: PathBuf::from("/path/to/felis"),
                                   pid: 38411
                               }],
+                                user_vars: HashMap::new(),
+                                socket: None,
                             },
                             Window {
                                 id: WindowId(2u32),
                                 is_active: true,
                                 is_focused: true,
+                                title: "kitty @ ls".to_string(),
+                                pid: Some(972),
+                                cwd: Some(PathBuf::from("/path/to/felis")),
+                                cmdline: vec!["-zsh".to_string()],
+                                env: HashMap::new(),
+                                is_self: true,
                                 foreground_processes: vec![
                                     Process {
                                         pid: 49915,
@@ -254,12 +1025,20 @@ pub mod test_fixture {
                                             "ls".to_string(),
                                         ],
                                     },
-                                ]
+                                ],
+                                user_vars: HashMap::new(),
+                                socket: None,
                             },
                             Window {
                                 id: WindowId(3u32),
                                 is_active: false,
                                 is_focused: false,
+                                title: "/path/to/felis".to_string(),
+                                pid: Some(983),
+                                cwd: Some(PathBuf::from("/path/to/felis")),
+                                cmdline: vec!["-zsh".to_string()],
+                                env: HashMap::new(),
+                                is_self: false,
                                 foreground_processes: vec![
                                     Process {
                                         pid: 983,
@@ -269,10 +1048,13 @@ pub mod test_fixture {
                                         ],
                                     },
                                 ],
+                                user_vars: HashMap::new(),
+                                socket: None,
                             }
                         ],
                     }
                 ],
+                socket: None,
             }
         ]
     );
@@ -348,7 +1130,8 @@ pub mod test_fixture {
             "is_self": false,
             "lines": 47,
             "pid": 863,
-            "title": "hx"
+            "title": "hx",
+            "user_vars": {}
           },
           {
             "cmdline": [
@@ -0,0 +1,1207 @@
+//! Remote control commands that `kitty_remote_bindings` doesn't cover (or doesn't cover with all
+//! the options felis needs). They follow the same conventions as the bindings: a builder API, a
+//! conversion into a `std::process::Command` running `kitten @`, and result parsing through
+//! [`CommandOutput`].
+
+use std::{path::PathBuf, process::Output};
+
+use kitty_remote_bindings::{
+    command::{
+        options::{Cwd, LaunchType, Matcher},
+        CommandOutput,
+    },
+    model::{TabId, WindowId},
+};
+use kitty_remote_bindings_core::ToArg;
+
+fn kitten(to: Option<&String>, command: &str) -> std::process::Command {
+    let mut cmd = std::process::Command::new("kitten");
+    cmd.arg("@");
+    if let Some(to) = to {
+        cmd.arg("--to").arg(to);
+    }
+    cmd.arg(command);
+    cmd
+}
+
+fn option<T: ToArg>(cmd: &mut std::process::Command, name: &str, value: Option<&T>) {
+    if let Some(value) = value {
+        cmd.arg(name).args(value.to_arg());
+    }
+}
+
+fn flag(cmd: &mut std::process::Command, name: &str, value: bool) {
+    if value {
+        cmd.arg(name);
+    }
+}
+
+/// The result of the commands that don't print anything
+fn unit_result(command: &str, output: &Output) -> kitty_remote_bindings::Result<()> {
+    text_result(command, output).map(|_| ())
+}
+
+/// The result of the commands that print text, e.g. the content of a window
+fn text_result(command: &str, output: &Output) -> kitty_remote_bindings::Result<String> {
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(kitty_remote_bindings::Error::ErrorExit(format!(
+            "kitty @ {command}: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )))
+    }
+}
+
+/// The result of the commands that print the id of a new window
+fn window_id_result(command: &str, output: &Output) -> kitty_remote_bindings::Result<WindowId> {
+    let stdout = text_result(command, output)?;
+    Ok(WindowId(serde_json::from_str(&stdout)?))
+}
+
+/// Represents the `--match` option of the commands acting on tabs
+#[derive(Debug, Clone, PartialEq)]
+pub enum TabMatcher {
+    /// Match by tab id `--match id:tab_id`
+    Id(TabId),
+    /// Match the tab containing the window `--match window_id:window_id`
+    WindowId(WindowId),
+}
+
+impl ToArg for TabMatcher {
+    fn to_arg(&self) -> Vec<String> {
+        match self {
+            TabMatcher::Id(id) => vec![format!("id:{}", id.0)],
+            TabMatcher::WindowId(id) => vec![format!("window_id:{}", id.0)],
+        }
+    }
+}
+
+/// Represents the possible values of the get-text command's `--extent` option
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Extent {
+    Screen,
+    All,
+    Selection,
+    FirstCmdOutputOnScreen,
+    LastCmdOutput,
+    LastVisitedCmdOutput,
+    LastNonEmptyOutput,
+}
+
+impl ToArg for Extent {
+    fn to_arg(&self) -> Vec<String> {
+        let extent = match self {
+            Extent::Screen => "screen",
+            Extent::All => "all",
+            Extent::Selection => "selection",
+            Extent::FirstCmdOutputOnScreen => "first_cmd_output_on_screen",
+            Extent::LastCmdOutput => "last_cmd_output",
+            Extent::LastVisitedCmdOutput => "last_visited_cmd_output",
+            Extent::LastNonEmptyOutput => "last_non_empty_output",
+        };
+        vec![extent.to_string()]
+    }
+}
+
+/// Represents the possible values of the resize-window command's `--axis` option
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Axis {
+    Horizontal,
+    Vertical,
+    /// Resets the layout to its default sizes
+    Reset,
+}
+
+impl ToArg for Axis {
+    fn to_arg(&self) -> Vec<String> {
+        let axis = match self {
+            Axis::Horizontal => "horizontal",
+            Axis::Vertical => "vertical",
+            Axis::Reset => "reset",
+        };
+        vec![axis.to_string()]
+    }
+}
+
+/// Represents the possible values of the new-window command's `--window-type` option
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WindowType {
+    /// A kitty window, in the layout of a tab
+    Kitty,
+    /// A new OS window
+    Os,
+}
+
+impl ToArg for WindowType {
+    fn to_arg(&self) -> Vec<String> {
+        let window_type = match self {
+            WindowType::Kitty => "kitty",
+            WindowType::Os => "os",
+        };
+        vec![window_type.to_string()]
+    }
+}
+
+/// How far to scroll a window, see [`ScrollWindow`]. A negative number of lines or pages scrolls
+/// up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScrollAmount {
+    Start,
+    End,
+    Lines(i32),
+    Pages(i32),
+}
+
+impl ToArg for ScrollAmount {
+    fn to_arg(&self) -> Vec<String> {
+        let direction = |amount: i32| if amount < 0 { "-" } else { "" };
+        let amount = match self {
+            ScrollAmount::Start => "start".to_string(),
+            ScrollAmount::End => "end".to_string(),
+            ScrollAmount::Lines(lines) => format!("{}{}", lines.unsigned_abs(), direction(*lines)),
+            ScrollAmount::Pages(pages) => {
+                format!("{}p{}", pages.unsigned_abs(), direction(*pages))
+            }
+        };
+        vec![amount]
+    }
+}
+
+/// Represents the "launch" remote command: kitty @ launch
+#[allow(clippy::struct_field_names)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Launch {
+    to: Option<String>,
+    matcher: Option<Matcher>,
+    launch_type: Option<LaunchType>,
+    cwd: Option<Cwd>,
+    title: Option<String>,
+    tab_title: Option<String>,
+    copy_env: bool,
+    keep_focus: bool,
+    wait_for_child_to_exit: bool,
+    args: Vec<String>,
+}
+
+impl Launch {
+    #[must_use]
+    pub fn new(args: Vec<String>) -> Self {
+        Self {
+            to: None,
+            matcher: None,
+            launch_type: None,
+            cwd: None,
+            title: None,
+            tab_title: None,
+            copy_env: false,
+            keep_focus: false,
+            wait_for_child_to_exit: false,
+            args,
+        }
+    }
+
+    /// Sets the `--to` top level option
+    #[must_use]
+    pub fn to(mut self, to: String) -> Self {
+        self.to = Some(to);
+        self
+    }
+
+    /// Sets the `--match` option, the tab to open the new window in
+    #[must_use]
+    pub fn matcher(mut self, matcher: Matcher) -> Self {
+        self.matcher = Some(matcher);
+        self
+    }
+
+    /// Sets the `--type` option
+    #[must_use]
+    pub fn launch_type(mut self, launch_type: LaunchType) -> Self {
+        self.launch_type = Some(launch_type);
+        self
+    }
+
+    /// Sets the `--cwd` option
+    #[must_use]
+    pub fn cwd(mut self, cwd: Cwd) -> Self {
+        self.cwd = Some(cwd);
+        self
+    }
+
+    /// Sets the `--title` option, the title of the new window
+    #[must_use]
+    pub fn title(mut self, title: String) -> Self {
+        self.title = Some(title);
+        self
+    }
+
+    /// Sets the `--tab-title` option, the title of the new tab
+    #[must_use]
+    pub fn tab_title(mut self, tab_title: String) -> Self {
+        self.tab_title = Some(tab_title);
+        self
+    }
+
+    /// Sets the `--copy-env` flag: the environment of the active window is copied to the new
+    /// window. Useful when the launched program is not on kitty's own `PATH`.
+    #[must_use]
+    pub fn copy_env(mut self, copy_env: bool) -> Self {
+        self.copy_env = copy_env;
+        self
+    }
+
+    /// Sets the `--keep-focus` flag: the new window doesn't take the focus
+    #[must_use]
+    pub fn keep_focus(mut self, keep_focus: bool) -> Self {
+        self.keep_focus = keep_focus;
+        self
+    }
+
+    /// Sets the `--wait-for-child-to-exit` flag: the command only returns once the launched
+    /// program exits, and it prints the program's exit code instead of the id of the new window
+    #[must_use]
+    pub fn wait_for_child_to_exit(mut self, wait_for_child_to_exit: bool) -> Self {
+        self.wait_for_child_to_exit = wait_for_child_to_exit;
+        self
+    }
+}
+
+impl From<&Launch> for std::process::Command {
+    fn from(value: &Launch) -> Self {
+        let mut cmd = kitten(value.to.as_ref(), "launch");
+        option(&mut cmd, "--match", value.matcher.as_ref());
+        option(&mut cmd, "--type", value.launch_type.as_ref());
+        option(&mut cmd, "--cwd", value.cwd.as_ref());
+        option(&mut cmd, "--title", value.title.as_ref());
+        option(&mut cmd, "--tab-title", value.tab_title.as_ref());
+        flag(&mut cmd, "--copy-env", value.copy_env);
+        flag(&mut cmd, "--keep-focus", value.keep_focus);
+        flag(
+            &mut cmd,
+            "--wait-for-child-to-exit",
+            value.wait_for_child_to_exit,
+        );
+        cmd.args(&value.args);
+        cmd
+    }
+}
+
+impl CommandOutput for Launch {
+    /// The id of the new window, or the exit code of the launched program when
+    /// `--wait-for-child-to-exit` is set
+    type R = WindowId;
+
+    fn result(output: &Output) -> kitty_remote_bindings::Result<Self::R> {
+        if output.status.success() {
+            Ok(WindowId(serde_json::from_slice(&output.stdout)?))
+        } else {
+            Err(kitty_remote_bindings::Error::ErrorExit(format!(
+                "kitty @ launch: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )))
+        }
+    }
+}
+
+/// Represents the "set-user-vars" remote command: kitty @ set-user-vars
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetUserVars {
+    to: Option<String>,
+    matcher: Option<Matcher>,
+    vars: Vec<String>,
+}
+
+impl SetUserVars {
+    /// Sets the given variables, in the `NAME=VALUE` format. A variable without a value, i.e.
+    /// `NAME`, is removed.
+    #[must_use]
+    pub fn new(vars: Vec<String>) -> Self {
+        Self {
+            to: None,
+            matcher: None,
+            vars,
+        }
+    }
+
+    /// Sets the `--to` top level option
+    #[must_use]
+    pub fn to(mut self, to: String) -> Self {
+        self.to = Some(to);
+        self
+    }
+
+    /// Sets the `--match` option, the window to set the variables on
+    #[must_use]
+    pub fn matcher(mut self, matcher: Matcher) -> Self {
+        self.matcher = Some(matcher);
+        self
+    }
+}
+
+impl From<&SetUserVars> for std::process::Command {
+    fn from(value: &SetUserVars) -> Self {
+        let mut cmd = kitten(value.to.as_ref(), "set-user-vars");
+        option(&mut cmd, "--match", value.matcher.as_ref());
+        cmd.args(&value.vars);
+        cmd
+    }
+}
+
+impl CommandOutput for SetUserVars {
+    type R = ();
+
+    fn result(output: &Output) -> kitty_remote_bindings::Result<Self::R> {
+        unit_result("set-user-vars", output)
+    }
+}
+
+/// Represents the "get-text" remote command: kitty @ get-text
+#[derive(Debug, Clone, PartialEq)]
+pub struct GetText {
+    to: Option<String>,
+    matcher: Option<Matcher>,
+    extent: Option<Extent>,
+    ansi: bool,
+    add_cursor: bool,
+    add_wrap_markers: bool,
+}
+
+impl GetText {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            to: None,
+            matcher: None,
+            extent: None,
+            ansi: false,
+            add_cursor: false,
+            add_wrap_markers: false,
+        }
+    }
+
+    /// Sets the `--to` top level option
+    #[must_use]
+    pub fn to(mut self, to: String) -> Self {
+        self.to = Some(to);
+        self
+    }
+
+    /// Sets the `--match` option, the window to get the text of
+    #[must_use]
+    pub fn matcher(mut self, matcher: Matcher) -> Self {
+        self.matcher = Some(matcher);
+        self
+    }
+
+    /// Sets the `--extent` option, kitty gets the text on the screen by default
+    #[must_use]
+    pub fn extent(mut self, extent: Extent) -> Self {
+        self.extent = Some(extent);
+        self
+    }
+
+    /// Sets the `--ansi` flag: the text keeps its formatting escape codes
+    #[must_use]
+    pub fn ansi(mut self, ansi: bool) -> Self {
+        self.ansi = ansi;
+        self
+    }
+
+    /// Sets the `--add-cursor` flag: escape codes for the position and the shape of the cursor
+    /// are appended to the text
+    #[must_use]
+    pub fn add_cursor(mut self, add_cursor: bool) -> Self {
+        self.add_cursor = add_cursor;
+        self
+    }
+
+    /// Sets the `--add-wrap-markers` flag: a carriage return marks where the lines are wrapped
+    #[must_use]
+    pub fn add_wrap_markers(mut self, add_wrap_markers: bool) -> Self {
+        self.add_wrap_markers = add_wrap_markers;
+        self
+    }
+}
+
+impl Default for GetText {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<&GetText> for std::process::Command {
+    fn from(value: &GetText) -> Self {
+        let mut cmd = kitten(value.to.as_ref(), "get-text");
+        option(&mut cmd, "--match", value.matcher.as_ref());
+        option(&mut cmd, "--extent", value.extent.as_ref());
+        flag(&mut cmd, "--ansi", value.ansi);
+        flag(&mut cmd, "--add-cursor", value.add_cursor);
+        flag(&mut cmd, "--add-wrap-markers", value.add_wrap_markers);
+        cmd
+    }
+}
+
+impl CommandOutput for GetText {
+    /// The text of the window
+    type R = String;
+
+    fn result(output: &Output) -> kitty_remote_bindings::Result<Self::R> {
+        text_result("get-text", output)
+    }
+}
+
+/// Represents the "set-tab-title" remote command: kitty @ set-tab-title
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetTabTitle {
+    to: Option<String>,
+    matcher: Option<TabMatcher>,
+    title: String,
+}
+
+impl SetTabTitle {
+    /// Sets the title of the tab, an empty title resets it to the title of the active window
+    #[must_use]
+    pub fn new(title: String) -> Self {
+        Self {
+            to: None,
+            matcher: None,
+            title,
+        }
+    }
+
+    /// Sets the `--to` top level option
+    #[must_use]
+    pub fn to(mut self, to: String) -> Self {
+        self.to = Some(to);
+        self
+    }
+
+    /// Sets the `--match` option, the tab to set the title of
+    #[must_use]
+    pub fn matcher(mut self, matcher: TabMatcher) -> Self {
+        self.matcher = Some(matcher);
+        self
+    }
+}
+
+impl From<&SetTabTitle> for std::process::Command {
+    fn from(value: &SetTabTitle) -> Self {
+        let mut cmd = kitten(value.to.as_ref(), "set-tab-title");
+        option(&mut cmd, "--match", value.matcher.as_ref());
+        cmd.arg(&value.title);
+        cmd
+    }
+}
+
+impl CommandOutput for SetTabTitle {
+    type R = ();
+
+    fn result(output: &Output) -> kitty_remote_bindings::Result<Self::R> {
+        unit_result("set-tab-title", output)
+    }
+}
+
+/// Represents the "set-window-title" remote command: kitty @ set-window-title
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetWindowTitle {
+    to: Option<String>,
+    matcher: Option<Matcher>,
+    temporary: bool,
+    title: String,
+}
+
+impl SetWindowTitle {
+    /// Sets the title of the window, an empty title resets it to the title set by the program
+    /// running in the window
+    #[must_use]
+    pub fn new(title: String) -> Self {
+        Self {
+            to: None,
+            matcher: None,
+            temporary: false,
+            title,
+        }
+    }
+
+    /// Sets the `--to` top level option
+    #[must_use]
+    pub fn to(mut self, to: String) -> Self {
+        self.to = Some(to);
+        self
+    }
+
+    /// Sets the `--match` option, the window to set the title of
+    #[must_use]
+    pub fn matcher(mut self, matcher: Matcher) -> Self {
+        self.matcher = Some(matcher);
+        self
+    }
+
+    /// Sets the `--temporary` flag: the program running in the window can change the title again
+    #[must_use]
+    pub fn temporary(mut self, temporary: bool) -> Self {
+        self.temporary = temporary;
+        self
+    }
+}
+
+impl From<&SetWindowTitle> for std::process::Command {
+    fn from(value: &SetWindowTitle) -> Self {
+        let mut cmd = kitten(value.to.as_ref(), "set-window-title");
+        option(&mut cmd, "--match", value.matcher.as_ref());
+        flag(&mut cmd, "--temporary", value.temporary);
+        cmd.arg(&value.title);
+        cmd
+    }
+}
+
+impl CommandOutput for SetWindowTitle {
+    type R = ();
+
+    fn result(output: &Output) -> kitty_remote_bindings::Result<Self::R> {
+        unit_result("set-window-title", output)
+    }
+}
+
+/// Represents the "close-window" remote command: kitty @ close-window
+#[derive(Debug, Clone, PartialEq)]
+pub struct CloseWindow {
+    to: Option<String>,
+    matcher: Option<Matcher>,
+    ignore_no_match: bool,
+}
+
+impl CloseWindow {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            to: None,
+            matcher: None,
+            ignore_no_match: false,
+        }
+    }
+
+    /// Sets the `--to` top level option
+    #[must_use]
+    pub fn to(mut self, to: String) -> Self {
+        self.to = Some(to);
+        self
+    }
+
+    /// Sets the `--match` option, the windows to close
+    #[must_use]
+    pub fn matcher(mut self, matcher: Matcher) -> Self {
+        self.matcher = Some(matcher);
+        self
+    }
+
+    /// Sets the `--ignore-no-match` flag: no window matching is not an error
+    #[must_use]
+    pub fn ignore_no_match(mut self, ignore_no_match: bool) -> Self {
+        self.ignore_no_match = ignore_no_match;
+        self
+    }
+}
+
+impl Default for CloseWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<&CloseWindow> for std::process::Command {
+    fn from(value: &CloseWindow) -> Self {
+        let mut cmd = kitten(value.to.as_ref(), "close-window");
+        option(&mut cmd, "--match", value.matcher.as_ref());
+        flag(&mut cmd, "--ignore-no-match", value.ignore_no_match);
+        cmd
+    }
+}
+
+impl CommandOutput for CloseWindow {
+    type R = ();
+
+    fn result(output: &Output) -> kitty_remote_bindings::Result<Self::R> {
+        unit_result("close-window", output)
+    }
+}
+
+/// Represents the "focus-tab" remote command: kitty @ focus-tab
+#[derive(Debug, Clone, PartialEq)]
+pub struct FocusTab {
+    to: Option<String>,
+    matcher: Option<TabMatcher>,
+}
+
+impl FocusTab {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            to: None,
+            matcher: None,
+        }
+    }
+
+    /// Sets the `--to` top level option
+    #[must_use]
+    pub fn to(mut self, to: String) -> Self {
+        self.to = Some(to);
+        self
+    }
+
+    /// Sets the `--match` option, the tab to focus
+    #[must_use]
+    pub fn matcher(mut self, matcher: TabMatcher) -> Self {
+        self.matcher = Some(matcher);
+        self
+    }
+}
+
+impl Default for FocusTab {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<&FocusTab> for std::process::Command {
+    fn from(value: &FocusTab) -> Self {
+        let mut cmd = kitten(value.to.as_ref(), "focus-tab");
+        option(&mut cmd, "--match", value.matcher.as_ref());
+        cmd
+    }
+}
+
+impl CommandOutput for FocusTab {
+    type R = ();
+
+    fn result(output: &Output) -> kitty_remote_bindings::Result<Self::R> {
+        unit_result("focus-tab", output)
+    }
+}
+
+/// Represents the "new-window" remote command: kitty @ new-window. kitty recommends [`Launch`]
+/// instead, new-window is kept for the kitty versions and the scripts that rely on it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewWindow {
+    to: Option<String>,
+    matcher: Option<TabMatcher>,
+    title: Option<String>,
+    cwd: Option<PathBuf>,
+    window_type: Option<WindowType>,
+    tab_title: Option<String>,
+    new_tab: bool,
+    keep_focus: bool,
+    args: Vec<String>,
+}
+
+impl NewWindow {
+    /// Runs the given program in the new window, or the default shell when there's none
+    #[must_use]
+    pub fn new(args: Vec<String>) -> Self {
+        Self {
+            to: None,
+            matcher: None,
+            title: None,
+            cwd: None,
+            window_type: None,
+            tab_title: None,
+            new_tab: false,
+            keep_focus: false,
+            args,
+        }
+    }
+
+    /// Sets the `--to` top level option
+    #[must_use]
+    pub fn to(mut self, to: String) -> Self {
+        self.to = Some(to);
+        self
+    }
+
+    /// Sets the `--match` option, the tab to open the new window in
+    #[must_use]
+    pub fn matcher(mut self, matcher: TabMatcher) -> Self {
+        self.matcher = Some(matcher);
+        self
+    }
+
+    /// Sets the `--title` option, the title of the new window
+    #[must_use]
+    pub fn title(mut self, title: String) -> Self {
+        self.title = Some(title);
+        self
+    }
+
+    /// Sets the `--cwd` option
+    #[must_use]
+    pub fn cwd(mut self, cwd: PathBuf) -> Self {
+        self.cwd = Some(cwd);
+        self
+    }
+
+    /// Sets the `--window-type` option
+    #[must_use]
+    pub fn window_type(mut self, window_type: WindowType) -> Self {
+        self.window_type = Some(window_type);
+        self
+    }
+
+    /// Sets the `--tab-title` option, the title of the new tab when `new_tab` is set
+    #[must_use]
+    pub fn tab_title(mut self, tab_title: String) -> Self {
+        self.tab_title = Some(tab_title);
+        self
+    }
+
+    /// Sets the `--new-tab` flag: the window is opened in a new tab
+    #[must_use]
+    pub fn new_tab(mut self, new_tab: bool) -> Self {
+        self.new_tab = new_tab;
+        self
+    }
+
+    /// Sets the `--keep-focus` flag: the new window doesn't take the focus
+    #[must_use]
+    pub fn keep_focus(mut self, keep_focus: bool) -> Self {
+        self.keep_focus = keep_focus;
+        self
+    }
+}
+
+impl From<&NewWindow> for std::process::Command {
+    fn from(value: &NewWindow) -> Self {
+        let mut cmd = kitten(value.to.as_ref(), "new-window");
+        option(&mut cmd, "--match", value.matcher.as_ref());
+        option(&mut cmd, "--title", value.title.as_ref());
+        option(&mut cmd, "--cwd", value.cwd.as_ref());
+        option(&mut cmd, "--window-type", value.window_type.as_ref());
+        option(&mut cmd, "--tab-title", value.tab_title.as_ref());
+        flag(&mut cmd, "--new-tab", value.new_tab);
+        flag(&mut cmd, "--keep-focus", value.keep_focus);
+        cmd.args(&value.args);
+        cmd
+    }
+}
+
+impl CommandOutput for NewWindow {
+    /// The id of the new window
+    type R = WindowId;
+
+    fn result(output: &Output) -> kitty_remote_bindings::Result<Self::R> {
+        window_id_result("new-window", output)
+    }
+}
+
+/// Represents the "resize-window" remote command: kitty @ resize-window
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResizeWindow {
+    to: Option<String>,
+    matcher: Option<Matcher>,
+    increment: Option<i32>,
+    axis: Option<Axis>,
+}
+
+impl ResizeWindow {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            to: None,
+            matcher: None,
+            increment: None,
+            axis: None,
+        }
+    }
+
+    /// Sets the `--to` top level option
+    #[must_use]
+    pub fn to(mut self, to: String) -> Self {
+        self.to = Some(to);
+        self
+    }
+
+    /// Sets the `--match` option, the window to resize
+    #[must_use]
+    pub fn matcher(mut self, matcher: Matcher) -> Self {
+        self.matcher = Some(matcher);
+        self
+    }
+
+    /// Sets the `--increment` option, in cells, a negative increment shrinks the window. kitty
+    /// uses 2 by default.
+    #[must_use]
+    pub fn increment(mut self, increment: i32) -> Self {
+        self.increment = Some(increment);
+        self
+    }
+
+    /// Sets the `--axis` option, kitty resizes horizontally by default
+    #[must_use]
+    pub fn axis(mut self, axis: Axis) -> Self {
+        self.axis = Some(axis);
+        self
+    }
+}
+
+impl Default for ResizeWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<&ResizeWindow> for std::process::Command {
+    fn from(value: &ResizeWindow) -> Self {
+        let mut cmd = kitten(value.to.as_ref(), "resize-window");
+        option(&mut cmd, "--match", value.matcher.as_ref());
+        option(
+            &mut cmd,
+            "--increment",
+            value
+                .increment
+                .map(|increment| increment.to_string())
+                .as_ref(),
+        );
+        option(&mut cmd, "--axis", value.axis.as_ref());
+        cmd
+    }
+}
+
+impl CommandOutput for ResizeWindow {
+    type R = ();
+
+    fn result(output: &Output) -> kitty_remote_bindings::Result<Self::R> {
+        unit_result("resize-window", output)
+    }
+}
+
+/// Represents the "signal-child" remote command: kitty @ signal-child
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignalChild {
+    to: Option<String>,
+    matcher: Option<Matcher>,
+    signals: Vec<String>,
+}
+
+impl SignalChild {
+    /// Sends the given signals, e.g. `SIGINT`, to the foreground process of the window. kitty sends
+    /// `SIGINT` when there's none.
+    #[must_use]
+    pub fn new(signals: Vec<String>) -> Self {
+        Self {
+            to: None,
+            matcher: None,
+            signals,
+        }
+    }
+
+    /// Sets the `--to` top level option
+    #[must_use]
+    pub fn to(mut self, to: String) -> Self {
+        self.to = Some(to);
+        self
+    }
+
+    /// Sets the `--match` option, the window to signal the foreground process of
+    #[must_use]
+    pub fn matcher(mut self, matcher: Matcher) -> Self {
+        self.matcher = Some(matcher);
+        self
+    }
+}
+
+impl From<&SignalChild> for std::process::Command {
+    fn from(value: &SignalChild) -> Self {
+        let mut cmd = kitten(value.to.as_ref(), "signal-child");
+        option(&mut cmd, "--match", value.matcher.as_ref());
+        cmd.args(&value.signals);
+        cmd
+    }
+}
+
+impl CommandOutput for SignalChild {
+    type R = ();
+
+    fn result(output: &Output) -> kitty_remote_bindings::Result<Self::R> {
+        unit_result("signal-child", output)
+    }
+}
+
+/// Represents the "scroll-window" remote command: kitty @ scroll-window
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScrollWindow {
+    to: Option<String>,
+    matcher: Option<Matcher>,
+    amount: ScrollAmount,
+}
+
+impl ScrollWindow {
+    #[must_use]
+    pub fn new(amount: ScrollAmount) -> Self {
+        Self {
+            to: None,
+            matcher: None,
+            amount,
+        }
+    }
+
+    /// Sets the `--to` top level option
+    #[must_use]
+    pub fn to(mut self, to: String) -> Self {
+        self.to = Some(to);
+        self
+    }
+
+    /// Sets the `--match` option, the window to scroll
+    #[must_use]
+    pub fn matcher(mut self, matcher: Matcher) -> Self {
+        self.matcher = Some(matcher);
+        self
+    }
+}
+
+impl From<&ScrollWindow> for std::process::Command {
+    fn from(value: &ScrollWindow) -> Self {
+        let mut cmd = kitten(value.to.as_ref(), "scroll-window");
+        option(&mut cmd, "--match", value.matcher.as_ref());
+        cmd.args(value.amount.to_arg());
+        cmd
+    }
+}
+
+impl CommandOutput for ScrollWindow {
+    type R = ();
+
+    fn result(output: &Output) -> kitty_remote_bindings::Result<Self::R> {
+        unit_result("scroll-window", output)
+    }
+}
+
+/// Represents the "kitten" remote command: kitty @ kitten, it runs a kitten in kitty itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kitten {
+    to: Option<String>,
+    matcher: Option<Matcher>,
+    name: String,
+    args: Vec<String>,
+}
+
+impl Kitten {
+    /// Runs the given kitten, with its arguments
+    #[must_use]
+    pub fn new(name: String, args: Vec<String>) -> Self {
+        Self {
+            to: None,
+            matcher: None,
+            name,
+            args,
+        }
+    }
+
+    /// Sets the `--to` top level option
+    #[must_use]
+    pub fn to(mut self, to: String) -> Self {
+        self.to = Some(to);
+        self
+    }
+
+    /// Sets the `--match` option, the window to run the kitten in
+    #[must_use]
+    pub fn matcher(mut self, matcher: Matcher) -> Self {
+        self.matcher = Some(matcher);
+        self
+    }
+}
+
+impl From<&Kitten> for std::process::Command {
+    fn from(value: &Kitten) -> Self {
+        let mut cmd = kitten(value.to.as_ref(), "kitten");
+        option(&mut cmd, "--match", value.matcher.as_ref());
+        cmd.arg(&value.name).args(&value.args);
+        cmd
+    }
+}
+
+impl CommandOutput for Kitten {
+    /// What the kitten returned, if anything
+    type R = String;
+
+    fn result(output: &Output) -> kitty_remote_bindings::Result<Self::R> {
+        text_result("kitten", output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        os::unix::process::ExitStatusExt,
+        path::PathBuf,
+        process::{Command, ExitStatus, Output},
+    };
+
+    use kitty_remote_bindings::{
+        command::{
+            options::{Cwd, LaunchType, Matcher},
+            CommandOutput,
+        },
+        model::{TabId, WindowId},
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::{
+        CloseWindow, GetText, Kitten, Launch, NewWindow, ResizeWindow, ScrollAmount, ScrollWindow,
+        SetUserVars, SetWindowTitle, TabMatcher, WindowType,
+    };
+
+    #[test]
+    fn test_launch_command() {
+        let cmd = Command::from(
+            &Launch::new(vec!["hx".to_string(), "src/lib.rs".to_string()])
+                .to("unix:/tmp/kitty.sock".to_string())
+                .launch_type(LaunchType::Tab)
+                .cwd(Cwd::Path(PathBuf::from("/path/to/felis")))
+                .copy_env(true),
+        );
+
+        assert_eq!(cmd.get_program(), "kitten");
+        assert_eq!(
+            cmd.get_args().collect::<Vec<_>>(),
+            vec![
+                "@",
+                "--to",
+                "unix:/tmp/kitty.sock",
+                "launch",
+                "--type",
+                "tab",
+                "--cwd",
+                "/path/to/felis",
+                "--copy-env",
+                "hx",
+                "src/lib.rs"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_launch_output_is_the_new_window_id() {
+        let output = Output {
+            status: ExitStatus::from_raw(0),
+            stdout: b"42\n".to_vec(),
+            stderr: Vec::new(),
+        };
+
+        assert_eq!(Launch::result(&output).unwrap(), WindowId(42));
+    }
+
+    #[test]
+    fn test_set_user_vars_command() {
+        let cmd = Command::from(
+            &SetUserVars::new(vec![
+                "felis_role=editor".to_string(),
+                "felis_project".to_string(),
+            ])
+            .matcher(Matcher::Id(WindowId(3))),
+        );
+
+        assert_eq!(
+            cmd.get_args().collect::<Vec<_>>(),
+            vec![
+                "@",
+                "set-user-vars",
+                "--match",
+                "id:3",
+                "felis_role=editor",
+                "felis_project"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_window_commands() {
+        let args = |cmd: Command| {
+            cmd.get_args()
+                .map(|arg| arg.to_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            args(Command::from(
+                &SetWindowTitle::new("helix".to_string())
+                    .matcher(Matcher::Id(WindowId(3)))
+                    .temporary(true)
+            )),
+            [
+                "@",
+                "set-window-title",
+                "--match",
+                "id:3",
+                "--temporary",
+                "helix"
+            ]
+        );
+        assert_eq!(
+            args(Command::from(&CloseWindow::new().ignore_no_match(true))),
+            ["@", "close-window", "--ignore-no-match"]
+        );
+        assert_eq!(
+            args(Command::from(&ResizeWindow::new().increment(-2))),
+            ["@", "resize-window", "--increment", "-2"]
+        );
+        assert_eq!(
+            args(Command::from(&ScrollWindow::new(ScrollAmount::Lines(-5)))),
+            ["@", "scroll-window", "5-"]
+        );
+        assert_eq!(
+            args(Command::from(
+                &NewWindow::new(vec!["hx".to_string()])
+                    .matcher(TabMatcher::Id(TabId(2)))
+                    .cwd(PathBuf::from("/path/to/felis"))
+                    .window_type(WindowType::Kitty)
+            )),
+            [
+                "@",
+                "new-window",
+                "--match",
+                "id:2",
+                "--cwd",
+                "/path/to/felis",
+                "--window-type",
+                "kitty",
+                "hx"
+            ]
+        );
+        assert_eq!(
+            args(Command::from(&Kitten::new(
+                "hints".to_string(),
+                vec!["--type".to_string(), "path".to_string()]
+            ))),
+            ["@", "kitten", "hints", "--type", "path"]
+        );
+    }
+
+    #[test]
+    fn test_command_results() {
+        let success = |stdout: &[u8]| Output {
+            status: ExitStatus::from_raw(0),
+            stdout: stdout.to_vec(),
+            stderr: Vec::new(),
+        };
+
+        assert_eq!(
+            GetText::result(&success(b"$ hx src/lib.rs\n")).unwrap(),
+            "$ hx src/lib.rs\n"
+        );
+        assert_eq!(NewWindow::result(&success(b"12")).unwrap(), WindowId(12));
+
+        let failure = Output {
+            status: ExitStatus::from_raw(1 << 8),
+            stdout: Vec::new(),
+            stderr: b"No matching windows".to_vec(),
+        };
+        match CloseWindow::result(&failure) {
+            Err(kitty_remote_bindings::Error::ErrorExit(message)) => {
+                assert_eq!(message, "kitty @ close-window: No matching windows");
+            }
+            result => panic!("Unexpected result: {result:?}"),
+        }
+    }
+}
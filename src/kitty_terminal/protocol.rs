@@ -0,0 +1,484 @@
+//! kitty's remote control protocol: the messages `kitten @` exchanges with kitty, see
+//! <https://sw.kovidgoyal.net/kitty/rc_protocol/>.
+//!
+//! A request is a JSON object wrapped in `ESC P @kitty-cmd` and `ESC \`, and kitty answers with a
+//! JSON object in the same envelope. The commands are not modeled a second time here: the request
+//! is built from the `kitten @` command line of the command (see the `From<&X> for
+//! std::process::Command` conversions), and the response is turned into the [`Output`] `kitten`
+//! would produce, so the result parsing of the commands works with both.
+
+use std::{
+    ffi::OsStr,
+    io,
+    os::unix::process::ExitStatusExt,
+    process::{ExitStatus, Output},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+/// The start of a remote control message
+pub(crate) const PREFIX: &[u8] = b"\x1bP@kitty-cmd";
+/// The end of a remote control message
+pub(crate) const SUFFIX: &[u8] = b"\x1b\\";
+
+/// The protocol version felis speaks, kitty only uses it to reject too old clients
+const VERSION: [u32; 3] = [0, 26, 0];
+
+/// The value an option takes
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Flag,
+    Number,
+    Text,
+}
+
+/// The options of the supported commands, with their key in the payload. The keys are the names
+/// kitty gives to the options, they are not always the option's name, e.g. `--add-cursor`.
+const OPTIONS: [(&str, &str, Kind); 18] = [
+    ("--match", "match", Kind::Text),
+    ("--type", "type", Kind::Text),
+    ("--cwd", "cwd", Kind::Text),
+    ("--title", "title", Kind::Text),
+    ("--tab-title", "tab_title", Kind::Text),
+    ("--window-type", "window_type", Kind::Text),
+    ("--extent", "extent", Kind::Text),
+    ("--axis", "axis", Kind::Text),
+    ("--increment", "increment", Kind::Number),
+    ("--copy-env", "copy_env", Kind::Flag),
+    ("--keep-focus", "keep_focus", Kind::Flag),
+    (
+        "--wait-for-child-to-exit",
+        "wait_for_child_to_exit",
+        Kind::Flag,
+    ),
+    ("--ansi", "ansi", Kind::Flag),
+    ("--add-cursor", "cursor", Kind::Flag),
+    ("--add-wrap-markers", "wrap_markers", Kind::Flag),
+    ("--temporary", "temporary", Kind::Flag),
+    ("--ignore-no-match", "ignore_no_match", Kind::Flag),
+    ("--new-tab", "new_tab", Kind::Flag),
+];
+
+#[derive(Debug, Serialize)]
+struct Request {
+    cmd: String,
+    version: [u32; 3],
+    no_response: bool,
+    #[serde(rename = "async", skip_serializing_if = "Option::is_none")]
+    async_id: Option<String>,
+    payload: Map<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Response {
+    ok: bool,
+    #[serde(default)]
+    data: Value,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Encodes a `kitten @ [--to <socket>] <command> [options] [arguments]` command line as a remote
+/// control request
+pub(crate) fn request(command: &std::process::Command) -> io::Result<Vec<u8>> {
+    let mut args = command
+        .get_args()
+        .map(OsStr::to_str)
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| invalid_input("the command line is not valid UTF-8".to_string()))?
+        .into_iter()
+        .skip_while(|arg| *arg == "@");
+    let mut cmd = args
+        .next()
+        .ok_or_else(|| invalid_input("missing remote control command".to_string()))?;
+    if cmd == "--to" {
+        // The socket is already known by the executor
+        args.next();
+        cmd = args
+            .next()
+            .ok_or_else(|| invalid_input("missing remote control command".to_string()))?;
+    }
+
+    let mut payload = Map::new();
+    let mut arguments = Vec::new();
+    while let Some(arg) = args.next() {
+        // Like for `kitten`, the options end at the first argument, e.g. the program to launch
+        // can have options of its own
+        if arg.starts_with("--") && arguments.is_empty() {
+            let (_, key, kind) = OPTIONS
+                .iter()
+                .find(|(option, _, _)| *option == arg)
+                .ok_or_else(|| invalid_input(format!("unsupported option of {cmd}: {arg}")))?;
+            let value = if *kind == Kind::Flag {
+                Value::Bool(true)
+            } else {
+                let value = args
+                    .next()
+                    .ok_or_else(|| invalid_input(format!("missing value of {arg}")))?;
+                if *kind == Kind::Number {
+                    let number = value
+                        .parse::<i64>()
+                        .map_err(|_| invalid_input(format!("{arg} is not a number: {value}")))?;
+                    Value::from(number)
+                } else {
+                    Value::String(value.to_string())
+                }
+            };
+            payload.insert((*key).to_string(), value);
+        } else {
+            arguments.push(arg.to_string());
+        }
+    }
+
+    match cmd {
+        "launch" | "new-window" => {
+            payload.insert("args".to_string(), arguments.into());
+        }
+        // `kitten` interprets the escapes of the text before sending it, e.g. `\r` or `\x1b`
+        "send-text" => {
+            payload.insert(
+                "data".to_string(),
+                format!("text:{}", unescape(&arguments.join(" "))).into(),
+            );
+        }
+        "set-user-vars" => {
+            payload.insert("var".to_string(), arguments.into());
+        }
+        "set-tab-title" | "set-window-title" => {
+            payload.insert("title".to_string(), arguments.join(" ").into());
+        }
+        "signal-child" if arguments.is_empty() => {
+            payload.insert("signals".to_string(), vec!["SIGINT"].into());
+        }
+        "signal-child" => {
+            let signals = arguments.iter().map(|signal| signal.to_uppercase());
+            payload.insert("signals".to_string(), signals.collect::<Vec<_>>().into());
+        }
+        "scroll-window" => {
+            let [amount] = arguments.as_slice() else {
+                return Err(invalid_input(format!(
+                    "expected one amount to scroll: {arguments:?}"
+                )));
+            };
+            payload.insert("amount".to_string(), scroll_amount(amount)?);
+        }
+        "kitten" if !arguments.is_empty() => {
+            payload.insert("kitten".to_string(), arguments.remove(0).into());
+            payload.insert("args".to_string(), arguments.into());
+        }
+        _ if arguments.is_empty() => {}
+        _ => return Err(invalid_input(format!("unexpected arguments of {cmd}"))),
+    }
+
+    // kitty only answers once the launched program exits when the request is asynchronous
+    let async_id = (payload.get("wait_for_child_to_exit") == Some(&Value::Bool(true)))
+        .then(|| format!("felis-{}", std::process::id()));
+
+    let request = Request {
+        cmd: cmd.to_string(),
+        version: VERSION,
+        no_response: false,
+        async_id,
+        payload,
+    };
+
+    let mut message = PREFIX.to_vec();
+    serde_json::to_writer(&mut message, &request)?;
+    message.extend_from_slice(SUFFIX);
+
+    Ok(message)
+}
+
+/// The length of the first complete message in the buffer, or `None` when more bytes are needed
+pub(crate) fn message_len(buffer: &[u8]) -> Option<usize> {
+    // The JSON in between can't contain an escape character, it would be encoded as `\u001b`
+    buffer
+        .windows(SUFFIX.len())
+        .position(|window| window == SUFFIX)
+        .map(|position| position + SUFFIX.len())
+}
+
+/// Decodes a remote control response into the output `kitten` would produce: the data on the
+/// standard output, or the error on the standard error with a non-zero exit status
+pub(crate) fn output(message: &[u8]) -> io::Result<Output> {
+    let json = message
+        .strip_prefix(PREFIX)
+        .and_then(|message| message.strip_suffix(SUFFIX))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "unexpected remote control response: {}",
+                    String::from_utf8_lossy(message)
+                ),
+            )
+        })?;
+    let response = serde_json::from_slice::<Response>(json)?;
+
+    let output = if response.ok {
+        let stdout = match response.data {
+            Value::Null => Vec::new(),
+            Value::String(data) => data.into_bytes(),
+            data => data.to_string().into_bytes(),
+        };
+        Output {
+            status: ExitStatus::from_raw(0),
+            stdout,
+            stderr: Vec::new(),
+        }
+    } else {
+        Output {
+            status: ExitStatus::from_raw(1 << 8),
+            stdout: Vec::new(),
+            stderr: response.error.unwrap_or_default().into_bytes(),
+        }
+    };
+
+    Ok(output)
+}
+
+/// Decodes the amount of scroll-window the way `kitten` does: `start`, `end`, or a number of lines
+/// (or pages with a `p` suffix) scrolling down, up with a `-` suffix. It's sent as the amount and
+/// its unit.
+fn scroll_amount(amount: &str) -> io::Result<Value> {
+    if amount == "start" || amount == "end" {
+        return Ok(json!([amount, null]));
+    }
+
+    let pages = amount.contains('p');
+    let unscroll = amount.contains('u');
+    let sign = if amount.ends_with('-') && !unscroll {
+        -1.0
+    } else {
+        1.0
+    };
+    let number = amount
+        .trim_end_matches(['+', '-', 'p', 'l', 'u'])
+        .parse::<f64>()
+        .map_err(|_| invalid_input(format!("invalid amount to scroll: {amount}")))?;
+    let unit = if pages {
+        "p"
+    } else if unscroll {
+        "u"
+    } else {
+        "l"
+    };
+
+    Ok(json!([number * sign, unit]))
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Interprets the Python escape sequences in the text the way `kitten @ send-text` does, unknown
+/// escapes are kept as they are
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        let escaped = match chars.peek() {
+            Some('\\') => Some('\\'),
+            Some('\'') => Some('\''),
+            Some('"') => Some('"'),
+            Some('a') => Some('\x07'),
+            Some('b') => Some('\x08'),
+            Some('f') => Some('\x0c'),
+            Some('n') => Some('\n'),
+            Some('r') => Some('\r'),
+            Some('t') => Some('\t'),
+            Some('v') => Some('\x0b'),
+            _ => None,
+        };
+        if let Some(escaped) = escaped {
+            chars.next();
+            result.push(escaped);
+            continue;
+        }
+
+        let digits = match chars.peek() {
+            Some('x') => 2,
+            Some('u') => 4,
+            Some('U') => 8,
+            _ => 0,
+        };
+        let code = (digits > 0)
+            .then(|| {
+                let hex = chars.clone().skip(1).take(digits).collect::<String>();
+                (hex.len() == digits)
+                    .then(|| u32::from_str_radix(&hex, 16).ok())
+                    .flatten()
+                    .and_then(char::from_u32)
+            })
+            .flatten();
+        if let Some(code) = code {
+            chars.nth(digits);
+            result.push(code);
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use kitty_remote_bindings::{
+        command::{
+            options::{Cwd, LaunchType, Matcher},
+            SendText,
+        },
+        model::WindowId,
+    };
+    use pretty_assertions::assert_eq;
+    use serde_json::{json, Value};
+
+    use crate::kitty_terminal::command::{
+        Axis, Extent, GetText, Kitten, Launch, ResizeWindow, ScrollAmount, ScrollWindow,
+        SetTabTitle, SignalChild, TabMatcher,
+    };
+
+    use super::{message_len, output, request, unescape};
+
+    fn decode(message: &[u8]) -> Value {
+        let json = message
+            .strip_prefix(b"\x1bP@kitty-cmd")
+            .and_then(|message| message.strip_suffix(b"\x1b\\"))
+            .expect("the message should be enveloped");
+        serde_json::from_slice(json).unwrap()
+    }
+
+    #[test]
+    fn test_request_is_built_from_the_command_line() {
+        let launch = Launch::new(vec!["hx".to_string(), "src/lib.rs".to_string()])
+            .to("unix:/tmp/kitty.sock".to_string())
+            .matcher(Matcher::Id(WindowId(3)))
+            .launch_type(LaunchType::Overlay)
+            .cwd(Cwd::Current)
+            .keep_focus(true);
+
+        assert_eq!(
+            decode(&request(&(&launch).into()).unwrap()),
+            json!({
+                "cmd": "launch",
+                "version": [0, 26, 0],
+                "no_response": false,
+                "payload": {
+                    "match": "id:3",
+                    "type": "overlay",
+                    "cwd": "current",
+                    "keep_focus": true,
+                    "args": ["hx", "src/lib.rs"],
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_send_text_request_interprets_escapes() {
+        let send_text = SendText::new(r"\x1b[27u:open \x1b[200~src/\\x.rs\x1b[201~\r".to_string())
+            .matcher(Matcher::Id(WindowId(1)));
+
+        assert_eq!(
+            decode(&request(&(&send_text).into()).unwrap())["payload"],
+            json!({
+                "match": "id:1",
+                "data": "text:\x1b[27u:open \x1b[200~src/\\x.rs\x1b[201~\r",
+            })
+        );
+        assert_eq!(unescape(r"é\q\x1\"), "é\\q\\x1\\");
+    }
+
+    #[test]
+    fn test_request_payloads_of_the_other_commands() {
+        let payload = |command: &dyn Fn() -> std::process::Command| {
+            decode(&request(&command()).unwrap())["payload"].clone()
+        };
+
+        assert_eq!(
+            payload(&|| (&ResizeWindow::new().increment(-3).axis(Axis::Vertical)).into()),
+            json!({"increment": -3, "axis": "vertical"})
+        );
+        assert_eq!(
+            payload(&|| (&SetTabTitle::new("felis api".to_string())
+                .matcher(TabMatcher::WindowId(WindowId(2))))
+                .into()),
+            json!({"match": "window_id:2", "title": "felis api"})
+        );
+        assert_eq!(
+            payload(&|| (&SignalChild::new(vec!["sigterm".to_string()])).into()),
+            json!({"signals": ["SIGTERM"]})
+        );
+        assert_eq!(
+            payload(&|| (&ScrollWindow::new(ScrollAmount::Pages(-2))).into()),
+            json!({"amount": [-2.0, "p"]})
+        );
+        assert_eq!(
+            payload(&|| (&ScrollWindow::new(ScrollAmount::End)).into()),
+            json!({"amount": ["end", null]})
+        );
+        assert_eq!(
+            payload(
+                &|| (&Kitten::new("hints".to_string(), vec!["--type=path".to_string()])
+                    .matcher(Matcher::Id(WindowId(1))))
+                    .into()
+            ),
+            json!({"match": "id:1", "kitten": "hints", "args": ["--type=path"]})
+        );
+        assert_eq!(
+            payload(&|| (&GetText::new().extent(Extent::LastCmdOutput).ansi(true)).into()),
+            json!({"extent": "last_cmd_output", "ansi": true})
+        );
+    }
+
+    #[test]
+    fn test_options_are_named_like_kitty_names_them() {
+        let payload =
+            |command: &std::process::Command| decode(&request(command).unwrap())["payload"].clone();
+
+        assert_eq!(
+            payload(&(&GetText::new().add_cursor(true).add_wrap_markers(true)).into()),
+            json!({"cursor": true, "wrap_markers": true})
+        );
+        assert_eq!(
+            payload(&(&Launch::new(vec!["hx".to_string()]).tab_title("api".to_string())).into()),
+            json!({"tab_title": "api", "args": ["hx"]})
+        );
+
+        let mut unknown = std::process::Command::new("kitten");
+        unknown.args(["@", "ls", "--self"]);
+        assert!(request(&unknown).is_err());
+    }
+
+    #[test]
+    fn test_response_is_decoded_as_the_output_of_kitten() {
+        let message = b"\x1bP@kitty-cmd{\"ok\": true, \"data\": \"[]\"}\x1b\\\x1bP@kitty-cmd";
+        // The buffer already contains the start of the next message
+        let len = message.len() - b"\x1bP@kitty-cmd".len();
+        assert_eq!(message_len(message), Some(len));
+
+        let success = output(&message[..len]).unwrap();
+        assert!(success.status.success());
+        assert_eq!(success.stdout, b"[]");
+
+        let launched = output(b"\x1bP@kitty-cmd{\"ok\": true, \"data\": 7}\x1b\\").unwrap();
+        assert_eq!(launched.stdout, b"7");
+
+        let failure =
+            output(b"\x1bP@kitty-cmd{\"ok\": false, \"error\": \"No matching windows\"}\x1b\\")
+                .unwrap();
+        assert!(!failure.status.success());
+        assert_eq!(failure.stderr, b"No matching windows");
+
+        assert_eq!(message_len(b"\x1bP@kitty-cmd{\"ok\": true"), None);
+        assert!(output(b"garbage").is_err());
+    }
+}
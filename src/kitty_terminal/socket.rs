@@ -0,0 +1,379 @@
+//! An [`Executor`] talking to kitty directly over its remote control socket, instead of spawning a
+//! `kitten @` process for every command. The connection is opened once, and it is used for all
+//! the commands of a felis invocation.
+
+use std::{io, process::Output};
+
+use async_trait::async_trait;
+use kitty_remote_bindings::command::{FocusWindow, Ls, SendText};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+    sync::Mutex,
+};
+
+use super::{
+    command::{
+        CloseWindow, FocusTab, GetText, Kitten, Launch, NewWindow, ResizeWindow, ScrollWindow,
+        SetTabTitle, SetUserVars, SetWindowTitle, SignalChild,
+    },
+    protocol, Executor,
+};
+
+pub(crate) struct SocketExecutor {
+    address: String,
+    stream: Mutex<Option<UnixStream>>,
+}
+
+impl SocketExecutor {
+    /// Connects to the socket kitty listens on, in kitty's format: `unix:/path/to/socket`, or
+    /// `unix:@name` for an abstract socket
+    pub(crate) async fn connect(address: &str) -> io::Result<Self> {
+        let stream = connect(address).await?;
+
+        Ok(Self {
+            address: address.to_string(),
+            stream: Mutex::new(Some(stream)),
+        })
+    }
+
+    async fn execute(&self, command: std::process::Command) -> io::Result<Output> {
+        let request = protocol::request(&command)?;
+        let mut stream = self.stream.lock().await;
+
+        // kitty may close the connection after a response, then the request is sent again on a
+        // new connection. The closed connection is only noticed when the next request is sent, so
+        // kitty hasn't seen that request yet.
+        let mut reconnected = false;
+        loop {
+            let connection = match stream.as_mut() {
+                Some(connection) => connection,
+                None => stream.insert(connect(&self.address).await?),
+            };
+            match exchange(connection, &request).await {
+                Ok(response) => return protocol::output(&response),
+                Err(error) if !reconnected && is_closed(&error) => {
+                    *stream = None;
+                    reconnected = true;
+                }
+                Err(error) => {
+                    *stream = None;
+                    return Err(error);
+                }
+            }
+        }
+    }
+}
+
+async fn connect(address: &str) -> io::Result<UnixStream> {
+    let path = address.strip_prefix("unix:").ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("not a unix socket: {address}"),
+        )
+    })?;
+
+    if let Some(name) = path.strip_prefix('@') {
+        connect_abstract(name)
+    } else {
+        UnixStream::connect(path).await
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn connect_abstract(name: &str) -> io::Result<UnixStream> {
+    use std::os::linux::net::SocketAddrExt;
+
+    let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+    let stream = std::os::unix::net::UnixStream::connect_addr(&address)?;
+    stream.set_nonblocking(true)?;
+    UnixStream::from_std(stream)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn connect_abstract(name: &str) -> io::Result<UnixStream> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("abstract sockets are only supported on Linux: @{name}"),
+    ))
+}
+
+/// Sends the request, and reads the response
+async fn exchange(stream: &mut UnixStream, request: &[u8]) -> io::Result<Vec<u8>> {
+    stream.write_all(request).await?;
+
+    let mut response = Vec::new();
+    let mut buffer = [0; 4096];
+    loop {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        response.extend_from_slice(&buffer[..read]);
+        if let Some(len) = protocol::message_len(&response) {
+            response.truncate(len);
+            return Ok(response);
+        }
+    }
+}
+
+fn is_closed(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::UnexpectedEof | io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset
+    )
+}
+
+#[async_trait]
+impl Executor for SocketExecutor {
+    async fn launch(&self, launch: &Launch) -> io::Result<Output> {
+        self.execute(launch.into()).await
+    }
+
+    async fn ls(&self, ls: &Ls) -> io::Result<Output> {
+        self.execute(ls.into()).await
+    }
+
+    async fn send_text(&self, send_text: &SendText) -> io::Result<Output> {
+        self.execute(send_text.into()).await
+    }
+
+    async fn focus_window(&self, focus_window: &FocusWindow) -> io::Result<Output> {
+        self.execute(focus_window.into()).await
+    }
+
+    async fn set_user_vars(&self, set_user_vars: &SetUserVars) -> io::Result<Output> {
+        self.execute(set_user_vars.into()).await
+    }
+
+    async fn get_text(&self, get_text: &GetText) -> io::Result<Output> {
+        self.execute(get_text.into()).await
+    }
+
+    async fn set_tab_title(&self, set_tab_title: &SetTabTitle) -> io::Result<Output> {
+        self.execute(set_tab_title.into()).await
+    }
+
+    async fn set_window_title(&self, set_window_title: &SetWindowTitle) -> io::Result<Output> {
+        self.execute(set_window_title.into()).await
+    }
+
+    async fn close_window(&self, close_window: &CloseWindow) -> io::Result<Output> {
+        self.execute(close_window.into()).await
+    }
+
+    async fn focus_tab(&self, focus_tab: &FocusTab) -> io::Result<Output> {
+        self.execute(focus_tab.into()).await
+    }
+
+    async fn new_window(&self, new_window: &NewWindow) -> io::Result<Output> {
+        self.execute(new_window.into()).await
+    }
+
+    async fn resize_window(&self, resize_window: &ResizeWindow) -> io::Result<Output> {
+        self.execute(resize_window.into()).await
+    }
+
+    async fn signal_child(&self, signal_child: &SignalChild) -> io::Result<Output> {
+        self.execute(signal_child.into()).await
+    }
+
+    async fn scroll_window(&self, scroll_window: &ScrollWindow) -> io::Result<Output> {
+        self.execute(scroll_window.into()).await
+    }
+
+    async fn kitten(&self, kitten: &Kitten) -> io::Result<Output> {
+        self.execute(kitten.into()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use kitty_remote_bindings::{command::options::Matcher, model::WindowId};
+    use pretty_assertions::assert_eq;
+    use serde_json::{json, Value};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{UnixListener, UnixStream},
+        task::JoinHandle,
+    };
+
+    use crate::{
+        kitty_terminal::{protocol, test_fixture, KittyTerminal},
+        FelisError,
+    };
+
+    use super::SocketExecutor;
+
+    /// A fake kitty listening on a socket, it replays the recorded responses in order, and returns
+    /// the requests it received, per connection
+    struct FakeKitty {
+        path: PathBuf,
+        server: JoinHandle<Vec<Vec<Value>>>,
+    }
+
+    impl FakeKitty {
+        /// With `close`, the connection is closed after each response
+        fn serve(dir: &Path, responses: Vec<Value>, close: bool) -> Self {
+            let path = dir.join("kitty.sock");
+            let listener = UnixListener::bind(&path).unwrap();
+
+            let server = tokio::spawn(async move {
+                let mut responses = responses.into_iter().peekable();
+                let mut connections = Vec::new();
+                while responses.peek().is_some() {
+                    let (mut stream, _) = listener.accept().await.unwrap();
+                    let mut requests = Vec::new();
+                    while let Some(request) = read_message(&mut stream).await {
+                        requests.push(request);
+                        let response = responses.next().expect("unexpected request");
+                        write_message(&mut stream, &response).await;
+                        if close || responses.peek().is_none() {
+                            break;
+                        }
+                    }
+                    connections.push(requests);
+                }
+                connections
+            });
+
+            Self { path, server }
+        }
+
+        fn address(&self) -> String {
+            format!("unix:{}", self.path.display())
+        }
+
+        async fn requests(self) -> Vec<Vec<Value>> {
+            self.server.await.unwrap()
+        }
+    }
+
+    async fn read_message(stream: &mut UnixStream) -> Option<Value> {
+        let mut message = Vec::new();
+        let mut buffer = [0; 1024];
+        while protocol::message_len(&message).is_none() {
+            let read = stream.read(&mut buffer).await.unwrap();
+            if read == 0 {
+                return None;
+            }
+            message.extend_from_slice(&buffer[..read]);
+        }
+        let json = &message[protocol::PREFIX.len()..message.len() - protocol::SUFFIX.len()];
+        Some(serde_json::from_slice(json).unwrap())
+    }
+
+    async fn write_message(stream: &mut UnixStream, response: &Value) {
+        let mut message = protocol::PREFIX.to_vec();
+        message.extend_from_slice(response.to_string().as_bytes());
+        message.extend_from_slice(protocol::SUFFIX);
+        stream.write_all(&message).await.unwrap();
+    }
+
+    async fn terminal(kitty: &FakeKitty) -> KittyTerminal {
+        KittyTerminal::with_executor(
+            Some(kitty.address()),
+            Box::new(SocketExecutor::connect(&kitty.address()).await.unwrap()),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_commands_share_one_connection() {
+        let dir = tempfile::tempdir().unwrap();
+        let kitty = FakeKitty::serve(
+            dir.path(),
+            vec![
+                json!({"ok": true, "data": test_fixture::LS_OUTPUT_JSON}),
+                json!({"ok": true}),
+                json!({"ok": true}),
+            ],
+            false,
+        );
+        let terminal = terminal(&kitty).await;
+
+        let windows = terminal.ls().await.unwrap();
+        terminal
+            .send_text(Matcher::Id(WindowId(1)), r"\x1b[27u:open a.rs\r")
+            .await
+            .unwrap();
+        terminal
+            .focus_window(Matcher::Id(WindowId(1)))
+            .await
+            .unwrap();
+
+        assert_eq!(windows, *test_fixture::LS_OUTPUT);
+        let connections = kitty.requests().await;
+        assert_eq!(connections.len(), 1);
+        assert_eq!(
+            connections[0]
+                .iter()
+                .map(|request| (request["cmd"].clone(), request["payload"].clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (json!("ls"), json!({})),
+                (
+                    json!("send-text"),
+                    json!({"match": "id:1", "data": "text:\x1b[27u:open a.rs\r"})
+                ),
+                (json!("focus-window"), json!({"match": "id:1"})),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reconnects_when_kitty_closes_the_connection() {
+        let dir = tempfile::tempdir().unwrap();
+        let kitty = FakeKitty::serve(
+            dir.path(),
+            vec![json!({"ok": true, "data": "5"}), json!({"ok": true})],
+            true,
+        );
+        let terminal = terminal(&kitty).await;
+
+        let launched = terminal
+            .launch(crate::kitty_terminal::command::Launch::new(vec![
+                "hx".to_string()
+            ]))
+            .await
+            .unwrap();
+        terminal.focus_window(Matcher::Id(launched)).await.unwrap();
+
+        assert_eq!(launched, WindowId(5));
+        let connections = kitty.requests().await;
+        assert_eq!(connections.len(), 2);
+        assert_eq!(connections[1][0]["payload"], json!({"match": "id:5"}));
+    }
+
+    #[tokio::test]
+    async fn test_error_responses_are_reported_as_failed_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let kitty = FakeKitty::serve(
+            dir.path(),
+            vec![json!({"ok": false, "error": "No matching windows for expression: id:9"})],
+            false,
+        );
+        let terminal = terminal(&kitty).await;
+
+        let result = terminal.ls().await;
+
+        match result {
+            Err(FelisError::KittyError(kitty_remote_bindings::Error::ErrorExit(message))) => {
+                assert_eq!(
+                    message,
+                    "kitty @ ls: No matching windows for expression: id:9"
+                );
+            }
+            result => panic!("Unexpected result: {result:?}"),
+        }
+        kitty.requests().await;
+    }
+
+    #[tokio::test]
+    async fn test_only_unix_sockets_are_supported() {
+        assert!(SocketExecutor::connect("tcp:localhost:12345")
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,300 @@
+//! An [`Executor`] sending the remote control commands over the controlling terminal, for when
+//! kitty doesn't listen on a socket. This only works when felis runs in a kitty window: kitty reads
+//! the commands from the window's output, and writes the responses to its input. The terminal is
+//! put in raw mode while waiting for a response, so the response is not echoed.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    path::Path,
+    process::Output,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use kitty_remote_bindings::command::{FocusWindow, Ls, SendText};
+use rustix::termios::{tcgetattr, tcsetattr, OptionalActions, Termios};
+use tokio::{io::unix::AsyncFd, sync::Mutex};
+
+use super::{
+    command::{
+        CloseWindow, FocusTab, GetText, Kitten, Launch, NewWindow, ResizeWindow, ScrollWindow,
+        SetTabTitle, SetUserVars, SetWindowTitle, SignalChild,
+    },
+    protocol, Executor,
+};
+
+/// How long to wait for kitty's response. Without `allow_remote_control` kitty ignores the
+/// commands, so there's no response at all.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub(crate) struct TtyExecutor {
+    tty: Mutex<AsyncFd<File>>,
+}
+
+impl TtyExecutor {
+    /// Opens the terminal, usually `/dev/tty`
+    pub(crate) fn open(path: &Path) -> io::Result<Self> {
+        let tty = OpenOptions::new().read(true).write(true).open(path)?;
+        rustix::io::ioctl_fionbio(&tty, true)?;
+
+        Ok(Self {
+            tty: Mutex::new(AsyncFd::new(tty)?),
+        })
+    }
+
+    async fn execute(&self, command: std::process::Command) -> io::Result<Output> {
+        let request = protocol::request(&command)?;
+        // kitty only answers when the launched program exits, it can take any time
+        let waits = command
+            .get_args()
+            .any(|arg| arg == "--wait-for-child-to-exit");
+        let tty = self.tty.lock().await;
+
+        let _raw = RawMode::enable(tty.get_ref())?;
+        write(&tty, &request).await?;
+        let response = if waits {
+            read(&tty).await?
+        } else {
+            tokio::time::timeout(TIMEOUT, read(&tty))
+                .await
+                .map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "kitty didn't respond, is allow_remote_control enabled?",
+                    )
+                })??
+        };
+
+        protocol::output(&response)
+    }
+}
+
+/// Puts the terminal in raw mode, the original mode is restored when dropped
+struct RawMode<'a> {
+    tty: &'a File,
+    original: Termios,
+}
+
+impl<'a> RawMode<'a> {
+    fn enable(tty: &'a File) -> io::Result<Self> {
+        let original = tcgetattr(tty)?;
+        let mut raw = original.clone();
+        raw.make_raw();
+        tcsetattr(tty, OptionalActions::Now, &raw)?;
+
+        Ok(Self { tty, original })
+    }
+}
+
+impl Drop for RawMode<'_> {
+    fn drop(&mut self) {
+        let _ = tcsetattr(self.tty, OptionalActions::Now, &self.original);
+    }
+}
+
+async fn write(tty: &AsyncFd<File>, mut request: &[u8]) -> io::Result<()> {
+    while !request.is_empty() {
+        let mut guard = tty.writable().await?;
+        if let Ok(written) = guard.try_io(|tty| tty.get_ref().write(request)) {
+            request = &request[written?..];
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the response, anything the user typed in the meantime is dropped
+async fn read(tty: &AsyncFd<File>) -> io::Result<Vec<u8>> {
+    let mut response = Vec::new();
+    let mut buffer = [0; 4096];
+    loop {
+        let mut guard = tty.readable().await?;
+        let Ok(read) = guard.try_io(|tty| tty.get_ref().read(&mut buffer)) else {
+            continue;
+        };
+        let read = read?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        response.extend_from_slice(&buffer[..read]);
+
+        if let Some(start) = response
+            .windows(protocol::PREFIX.len())
+            .position(|window| window == protocol::PREFIX)
+        {
+            response.drain(..start);
+            if let Some(len) = protocol::message_len(&response) {
+                response.truncate(len);
+                return Ok(response);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Executor for TtyExecutor {
+    async fn launch(&self, launch: &Launch) -> io::Result<Output> {
+        self.execute(launch.into()).await
+    }
+
+    async fn ls(&self, ls: &Ls) -> io::Result<Output> {
+        self.execute(ls.into()).await
+    }
+
+    async fn send_text(&self, send_text: &SendText) -> io::Result<Output> {
+        self.execute(send_text.into()).await
+    }
+
+    async fn focus_window(&self, focus_window: &FocusWindow) -> io::Result<Output> {
+        self.execute(focus_window.into()).await
+    }
+
+    async fn set_user_vars(&self, set_user_vars: &SetUserVars) -> io::Result<Output> {
+        self.execute(set_user_vars.into()).await
+    }
+
+    async fn get_text(&self, get_text: &GetText) -> io::Result<Output> {
+        self.execute(get_text.into()).await
+    }
+
+    async fn set_tab_title(&self, set_tab_title: &SetTabTitle) -> io::Result<Output> {
+        self.execute(set_tab_title.into()).await
+    }
+
+    async fn set_window_title(&self, set_window_title: &SetWindowTitle) -> io::Result<Output> {
+        self.execute(set_window_title.into()).await
+    }
+
+    async fn close_window(&self, close_window: &CloseWindow) -> io::Result<Output> {
+        self.execute(close_window.into()).await
+    }
+
+    async fn focus_tab(&self, focus_tab: &FocusTab) -> io::Result<Output> {
+        self.execute(focus_tab.into()).await
+    }
+
+    async fn new_window(&self, new_window: &NewWindow) -> io::Result<Output> {
+        self.execute(new_window.into()).await
+    }
+
+    async fn resize_window(&self, resize_window: &ResizeWindow) -> io::Result<Output> {
+        self.execute(resize_window.into()).await
+    }
+
+    async fn signal_child(&self, signal_child: &SignalChild) -> io::Result<Output> {
+        self.execute(signal_child.into()).await
+    }
+
+    async fn scroll_window(&self, scroll_window: &ScrollWindow) -> io::Result<Output> {
+        self.execute(scroll_window.into()).await
+    }
+
+    async fn kitten(&self, kitten: &Kitten) -> io::Result<Output> {
+        self.execute(kitten.into()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        io::{Read, Write},
+        os::fd::OwnedFd,
+        path::PathBuf,
+    };
+
+    use kitty_remote_bindings::{command::options::Matcher, model::WindowId};
+    use pretty_assertions::assert_eq;
+    use rustix::{
+        pty::{grantpt, openpt, ptsname, unlockpt, OpenptFlags},
+        termios::{tcgetattr, LocalModes},
+    };
+    use serde_json::{json, Value};
+
+    use crate::kitty_terminal::{protocol, test_fixture, KittyTerminal};
+
+    use super::TtyExecutor;
+
+    /// A pseudo terminal: felis uses the terminal, while the fake kitty is on the other side
+    fn pty() -> (OwnedFd, PathBuf) {
+        let controller = openpt(OpenptFlags::RDWR | OpenptFlags::NOCTTY).unwrap();
+        grantpt(&controller).unwrap();
+        unlockpt(&controller).unwrap();
+        let path = ptsname(&controller, Vec::new()).unwrap();
+
+        (controller, PathBuf::from(path.to_str().unwrap()))
+    }
+
+    /// Reads the requests written to the terminal, and answers with the recorded responses. Some
+    /// noise, e.g. keys typed by the user, precedes every response. The terminal is kept open until
+    /// the requests are collected, closing it would hang up felis' side.
+    fn fake_kitty(
+        controller: OwnedFd,
+        responses: Vec<Value>,
+    ) -> std::thread::JoinHandle<(File, Vec<Value>)> {
+        std::thread::spawn(move || {
+            let mut kitty = File::from(controller);
+            let mut requests = Vec::new();
+            for response in responses {
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while protocol::message_len(&request).is_none() {
+                    let read = kitty.read(&mut buffer).unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                let json = &request[protocol::PREFIX.len()..request.len() - protocol::SUFFIX.len()];
+                requests.push(serde_json::from_slice(json).unwrap());
+
+                let mut message = b"jk".to_vec();
+                message.extend_from_slice(protocol::PREFIX);
+                message.extend_from_slice(response.to_string().as_bytes());
+                message.extend_from_slice(protocol::SUFFIX);
+                kitty.write_all(&message).unwrap();
+            }
+            (kitty, requests)
+        })
+    }
+
+    #[tokio::test]
+    async fn test_commands_are_sent_over_the_terminal() {
+        let (controller, path) = pty();
+        let kitty = fake_kitty(
+            controller,
+            vec![
+                json!({"ok": true, "data": test_fixture::LS_OUTPUT_JSON}),
+                json!({"ok": true}),
+            ],
+        );
+        let executor = TtyExecutor::open(&path).unwrap();
+        let tty = File::open(&path).unwrap();
+        let terminal = KittyTerminal::with_executor(None, Box::new(executor));
+
+        let windows = terminal.ls().await.unwrap();
+        terminal
+            .send_text(Matcher::Id(WindowId(1)), r"\x1b[27u:open a.rs\r")
+            .await
+            .unwrap();
+
+        assert_eq!(windows, *test_fixture::LS_OUTPUT);
+        let (_kitty, requests) = kitty.join().unwrap();
+        assert_eq!(
+            requests
+                .iter()
+                .map(|request| (request["cmd"].clone(), request["payload"].clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (json!("ls"), json!({})),
+                (
+                    json!("send-text"),
+                    json!({"match": "id:1", "data": "text:\x1b[27u:open a.rs\r"})
+                ),
+            ]
+        );
+        // The terminal is back in its original mode
+        assert!(tcgetattr(&tty)
+            .unwrap()
+            .local_modes
+            .contains(LocalModes::ECHO));
+    }
+}
@@ -1,9 +1,21 @@
 pub mod command;
+pub mod config;
+pub mod discovery;
+pub mod editor;
 pub mod fs;
+pub mod handoff;
 pub mod kitty_terminal;
+pub mod matcher;
+pub mod model;
+pub mod picker;
+pub mod plugin;
+pub mod screen;
+pub mod strategy;
+pub mod tag;
+pub mod workspace;
 
 use clap::ValueEnum;
-use kitty_remote_bindings::model::OsWindows;
+use model::OsWindows;
 use std::{
     io::Error,
     num::TryFromIntError,
@@ -28,6 +40,32 @@ pub enum FelisError {
     KittyError(#[from] kitty_remote_bindings::Error),
     #[error("strip prefix error")]
     StripPrefixError(#[from] StripPrefixError),
+    #[error("config error")]
+    ConfigError(#[from] toml::de::Error),
+    #[error("regex error")]
+    RegexError(#[from] regex::Error),
+    #[error("invalid match expression {expression:?}: {message}")]
+    MatchExpressionError { expression: String, message: String },
+    #[error("refusing to send {text:?} to helix: {reason}")]
+    UnsafeText { text: String, reason: String },
+    #[error("couldn't find kitty's socket, tried:\n  {}", .attempts.join("\n  "))]
+    SocketNotFound { attempts: Vec<String> },
+    #[error("helix didn't open {path} in window {window_id}: {message}")]
+    OpenNotVerified {
+        path: String,
+        window_id: u32,
+        message: String,
+    },
+    #[error("refusing to type into window {window_id}, helix' state is unknown: {statusline:?}")]
+    UnknownEditorState { window_id: u32, statusline: String },
+    #[error("kitty refused the remote control password: {message}")]
+    PasswordRejected { message: String },
+    #[error("the steel plugin {path} doesn't speak felis' protocol version {expected}: {reason}")]
+    PluginProtocol {
+        path: String,
+        expected: u32,
+        reason: String,
+    },
 }
 
 impl From<String> for FelisError {
@@ -0,0 +1,408 @@
+//! kitty's match expressions (`kitty @ ... --match`), evaluated by felis against the window tree.
+//!
+//! An expression is made of `field:query` terms combined with `and`, `or`, `not` and parentheses.
+//! Terms next to each other are combined with `and`. Queries containing spaces or parentheses can
+//! be quoted, e.g. `title:"cargo (test)"`. The supported fields are the ones kitty supports for
+//! windows:
+//!
+//! - `id`: the id of the window
+//! - `title`: a regex matched against the title of the window
+//! - `pid`: the pid of the process kitty started in the window, or of a foreground process
+//! - `cwd`: a regex matched against the working directory of the window, or of a foreground process
+//! - `cmdline`: a regex matched against the command line of the window, or of a foreground process,
+//!   with the arguments joined by spaces
+//! - `num`: the position of the window in its tab, starting from 0
+//! - `env`: `NAME=VALUE`, where `VALUE` is a regex matched against the environment variable
+//! - `var`: `NAME=VALUE`, where `VALUE` is a regex matched against the user variable
+//! - `state`: one of `active`, `focused`, `needs_attention`, `parent_active`, `parent_focused`,
+//!   `self`, `overlay_parent` and `focused_os_window`
+//! - `recent`: the n-th most recently active window of the active tab, 0 being the active window
+//!
+//! Regexes are not anchored, `title:hx` matches any title containing `hx`.
+
+use std::{collections::HashMap, fmt::Display, iter::Peekable, str::FromStr, vec::IntoIter};
+
+use regex::Regex;
+
+use crate::{
+    model::{OsWindow, OsWindows, Tab, Window},
+    FelisError, Result,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Active,
+    Focused,
+    NeedsAttention,
+    ParentActive,
+    ParentFocused,
+    SelfWindow,
+    OverlayParent,
+    FocusedOsWindow,
+}
+
+/// A single `field:query` term
+#[derive(Debug, Clone)]
+pub enum Term {
+    Id(u32),
+    Title(Regex),
+    Pid(u32),
+    Cwd(Regex),
+    Cmdline(Regex),
+    Num(usize),
+    Env(String, Regex),
+    Var(String, Regex),
+    State(State),
+    Recent(usize),
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Term(Term),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+/// A parsed match expression
+#[derive(Debug, Clone)]
+pub struct MatchExpr {
+    source: String,
+    expr: Expr,
+}
+
+impl MatchExpr {
+    /// Whether the window, in the given tab and OS window, matches the expression
+    #[must_use]
+    pub fn matches(&self, os_window: &OsWindow, tab: &Tab, window: &Window) -> bool {
+        self.expr.matches(os_window, tab, window)
+    }
+
+    /// Returns all the matching windows, in the order of `kitty @ ls`
+    #[must_use]
+    pub fn select<'a>(&self, windows: &'a OsWindows) -> Vec<&'a Window> {
+        windows
+            .0
+            .iter()
+            .flat_map(|os_window| {
+                os_window.tabs.iter().flat_map(move |tab| {
+                    tab.windows
+                        .iter()
+                        .filter(move |window| self.matches(os_window, tab, window))
+                })
+            })
+            .collect()
+    }
+}
+
+impl PartialEq for MatchExpr {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Display for MatchExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl FromStr for MatchExpr {
+    type Err = FelisError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let error = |message: String| FelisError::MatchExpressionError {
+            expression: s.to_string(),
+            message,
+        };
+
+        let mut tokens = tokenize(s).map_err(error)?.into_iter().peekable();
+        let expr = parse_or(&mut tokens).map_err(error)?;
+        if let Some(token) = tokens.next() {
+            return Err(error(format!("unexpected {token}")));
+        }
+
+        Ok(Self {
+            source: s.to_string(),
+            expr,
+        })
+    }
+}
+
+impl Expr {
+    fn matches(&self, os_window: &OsWindow, tab: &Tab, window: &Window) -> bool {
+        match self {
+            Expr::Term(term) => term.matches(os_window, tab, window),
+            Expr::Not(expr) => !expr.matches(os_window, tab, window),
+            Expr::And(left, right) => {
+                left.matches(os_window, tab, window) && right.matches(os_window, tab, window)
+            }
+            Expr::Or(left, right) => {
+                left.matches(os_window, tab, window) || right.matches(os_window, tab, window)
+            }
+        }
+    }
+}
+
+impl Term {
+    fn matches(&self, os_window: &OsWindow, tab: &Tab, window: &Window) -> bool {
+        let processes = &window.foreground_processes;
+
+        match self {
+            Term::Id(id) => window.id.0 == *id,
+            Term::Title(re) => re.is_match(&window.title),
+            Term::Pid(pid) => {
+                window.pid == Some(*pid) || processes.iter().any(|process| process.pid == *pid)
+            }
+            Term::Cwd(re) => window
+                .cwd
+                .iter()
+                .chain(processes.iter().map(|process| &process.cwd))
+                .any(|cwd| re.is_match(&cwd.to_string_lossy())),
+            Term::Cmdline(re) => std::iter::once(&window.cmdline)
+                .chain(processes.iter().map(|process| &process.cmdline))
+                .filter(|cmdline| !cmdline.is_empty())
+                .any(|cmdline| re.is_match(&cmdline.join(" "))),
+            Term::Num(num) => tab.windows.get(*num).is_some_and(|w| w.id == window.id),
+            Term::Env(name, re) => matches_var(&window.env, name, re),
+            Term::Var(name, re) => matches_var(&window.user_vars, name, re),
+            Term::State(state) => match state {
+                State::Active => window.is_active,
+                State::Focused => window.is_focused,
+                // Not part of `kitty @ ls`
+                State::NeedsAttention | State::OverlayParent => false,
+                State::ParentActive => tab.is_active,
+                State::ParentFocused => tab.is_focused,
+                State::SelfWindow => window.is_self,
+                State::FocusedOsWindow => os_window.is_focused,
+            },
+            Term::Recent(n) => {
+                tab.is_active && os_window.is_focused && recent(tab).get(*n) == Some(&window.id.0)
+            }
+        }
+    }
+}
+
+fn matches_var(vars: &HashMap<String, String>, name: &str, re: &Regex) -> bool {
+    vars.get(name).is_some_and(|value| re.is_match(value))
+}
+
+/// Ids of the windows of the tab, from the most recently active one
+fn recent(tab: &Tab) -> Vec<u32> {
+    let mut ids = Vec::new();
+    if let Some(active) = tab.windows.iter().find(|window| window.is_active) {
+        ids.push(active.id.0);
+    }
+    for id in tab.active_window_history.iter().rev() {
+        if !ids.contains(&id.0) {
+            ids.push(id.0);
+        }
+    }
+    ids
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Word(String),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+            Token::Word(word) => write!(f, "{word}"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> std::result::Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            c => {
+                let mut word = String::new();
+                let mut next = Some(c);
+                while let Some(c) = next {
+                    match c {
+                        '"' => loop {
+                            match chars.next() {
+                                Some('"') => break,
+                                Some(c) => word.push(c),
+                                None => return Err("unterminated quote".to_string()),
+                            }
+                        },
+                        c => word.push(c),
+                    }
+                    next = chars.next_if(|c| !c.is_whitespace() && *c != '(' && *c != ')');
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+type Tokens = Peekable<IntoIter<Token>>;
+
+fn parse_or(tokens: &mut Tokens) -> std::result::Result<Expr, String> {
+    let mut expr = parse_and(tokens)?;
+    while tokens.next_if_eq(&Token::Word("or".to_string())).is_some() {
+        expr = Expr::Or(Box::new(expr), Box::new(parse_and(tokens)?));
+    }
+    Ok(expr)
+}
+
+fn parse_and(tokens: &mut Tokens) -> std::result::Result<Expr, String> {
+    let mut expr = parse_not(tokens)?;
+    loop {
+        match tokens.peek() {
+            None | Some(Token::Close) => break,
+            Some(Token::Word(word)) if word == "or" => break,
+            Some(Token::Word(word)) if word == "and" => {
+                tokens.next();
+            }
+            // Terms next to each other are combined with `and`
+            Some(_) => {}
+        }
+        expr = Expr::And(Box::new(expr), Box::new(parse_not(tokens)?));
+    }
+    Ok(expr)
+}
+
+fn parse_not(tokens: &mut Tokens) -> std::result::Result<Expr, String> {
+    match tokens.next() {
+        Some(Token::Word(word)) if word == "not" => Ok(Expr::Not(Box::new(parse_not(tokens)?))),
+        Some(Token::Open) => {
+            let expr = parse_or(tokens)?;
+            match tokens.next() {
+                Some(Token::Close) => Ok(expr),
+                _ => Err("missing )".to_string()),
+            }
+        }
+        Some(Token::Word(word)) => parse_term(&word).map(Expr::Term),
+        Some(Token::Close) => Err("unexpected )".to_string()),
+        None => Err("unexpected end of expression".to_string()),
+    }
+}
+
+fn parse_term(term: &str) -> std::result::Result<Term, String> {
+    let (field, query) = term
+        .split_once(':')
+        .ok_or_else(|| format!("{term} is not a field:query term"))?;
+    let number = || {
+        query
+            .parse::<u32>()
+            .map_err(|_| format!("{field} expects a number, got {query}"))
+    };
+    let regex = |query: &str| Regex::new(query).map_err(|err| err.to_string());
+    let var = || {
+        let (name, value) = query.split_once('=').unwrap_or((query, ""));
+        Ok::<_, String>((name.to_string(), regex(value)?))
+    };
+
+    match field {
+        "id" => number().map(Term::Id),
+        "title" => regex(query).map(Term::Title),
+        "pid" => number().map(Term::Pid),
+        "cwd" => regex(query).map(Term::Cwd),
+        "cmdline" => regex(query).map(Term::Cmdline),
+        "num" => number().map(|num| Term::Num(num as usize)),
+        "env" => var().map(|(name, re)| Term::Env(name, re)),
+        "var" => var().map(|(name, re)| Term::Var(name, re)),
+        "recent" => number().map(|n| Term::Recent(n as usize)),
+        "state" => {
+            let state = match query {
+                "active" => State::Active,
+                "focused" => State::Focused,
+                "needs_attention" => State::NeedsAttention,
+                "parent_active" => State::ParentActive,
+                "parent_focused" => State::ParentFocused,
+                "self" => State::SelfWindow,
+                "overlay_parent" => State::OverlayParent,
+                "focused_os_window" => State::FocusedOsWindow,
+                _ => return Err(format!("unknown state {query}")),
+            };
+            Ok(Term::State(state))
+        }
+        _ => Err(format!("unknown field {field}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::kitty_terminal::test_fixture;
+
+    use super::MatchExpr;
+
+    fn select(expr: &str) -> Vec<u32> {
+        expr.parse::<MatchExpr>()
+            .unwrap()
+            .select(&test_fixture::LS_OUTPUT)
+            .iter()
+            .map(|window| window.id.0)
+            .collect()
+    }
+
+    #[test]
+    fn test_fields_are_matched_against_the_fixture() {
+        assert_eq!(select("id:3"), vec![3]);
+        assert_eq!(select("title:^hx$"), vec![1]);
+        assert_eq!(select("title:ls"), vec![2]);
+        assert_eq!(select("pid:49915"), vec![2]);
+        assert_eq!(select("pid:983"), vec![3]);
+        assert_eq!(select("cwd:other-project$"), vec![3]);
+        assert_eq!(select("cwd:/path/to/felis"), vec![1, 2, 3]);
+        assert_eq!(select("cmdline:/hx$"), vec![1]);
+        assert_eq!(select("cmdline:rust-analyzer"), vec![1]);
+        assert_eq!(select("num:1"), vec![2]);
+        assert_eq!(select("state:focused"), vec![2]);
+        assert_eq!(select("state:self"), vec![2]);
+        assert_eq!(select("state:parent_active"), vec![1, 2, 3]);
+        assert_eq!(select("recent:0"), vec![2]);
+        assert_eq!(select("recent:1"), vec![1]);
+        assert_eq!(select("recent:2"), vec![3]);
+        assert_eq!(select("var:felis_role=editor"), Vec::<u32>::new());
+        assert_eq!(select("env:HOME"), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn test_terms_are_combined_with_boolean_operators() {
+        assert_eq!(select("cwd:felis and not state:active"), vec![1, 3]);
+        assert_eq!(select("cwd:felis not state:active"), vec![1, 3]);
+        assert_eq!(select("id:1 or id:3"), vec![1, 3]);
+        assert_eq!(select("not (id:1 or id:3)"), vec![2]);
+        assert_eq!(select("id:1 or id:2 and state:focused"), vec![1, 2]);
+        assert_eq!(select("(id:1 or id:3) and title:felis"), vec![3]);
+        assert_eq!(select(r#"title:"kitty @ ls""#), vec![2]);
+        assert_eq!(select(r#"title:"(hx|zsh)""#), vec![1]);
+    }
+
+    #[test]
+    fn test_invalid_expressions_are_rejected() {
+        for expr in [
+            "",
+            "hx",
+            "id:x",
+            "foo:bar",
+            "state:sleeping",
+            "title:(",
+            "(id:1",
+            "id:1)",
+            "id:1 or",
+            r#"title:"hx"#,
+        ] {
+            assert!(expr.parse::<MatchExpr>().is_err(), "{expr}");
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! The window tree returned by `kitty @ ls`.
+//!
+//! This mirrors [`kitty_remote_bindings::model`], but it also decodes the fields felis needs and
+//! the bindings don't expose (e.g. titles and the focus history of tabs). Ids and processes are
+//! reused from the bindings, so they can be passed to the bindings' commands directly.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+pub use kitty_remote_bindings::model::{OsWindowId, Process, TabId, WindowId};
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct OsWindows(pub Vec<OsWindow>);
+
+impl IntoIterator for OsWindows {
+    type Item = OsWindow;
+
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl OsWindows {
+    /// Tags the windows with the socket of the kitty instance they belong to
+    pub fn tag(&mut self, socket: Option<&str>) {
+        for os_window in &mut self.0 {
+            os_window.socket = socket.map(String::from);
+            for window in os_window.tabs.iter_mut().flat_map(|tab| &mut tab.windows) {
+                window.socket = socket.map(String::from);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct OsWindow {
+    pub id: OsWindowId,
+    pub is_active: bool,
+    pub is_focused: bool,
+    pub tabs: Vec<Tab>,
+    /// The socket of the kitty instance the window belongs to, only set when the windows of
+    /// several instances are listed, see [`crate::kitty_terminal::KittyTerminal::connect_all`]
+    #[serde(default)]
+    pub socket: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Tab {
+    pub id: TabId,
+    pub is_active: bool,
+    pub is_focused: bool,
+    #[serde(default)]
+    pub title: String,
+    /// Ids of the previously active windows of the tab, the most recent one is the last
+    #[serde(default)]
+    pub active_window_history: Vec<WindowId>,
+    pub windows: Vec<Window>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Window {
+    pub id: WindowId,
+    pub is_active: bool,
+    pub is_focused: bool,
+    #[serde(default)]
+    pub title: String,
+    /// The process kitty started in the window, usually a shell
+    #[serde(default)]
+    pub pid: Option<u32>,
+    #[serde(default)]
+    pub cwd: Option<PathBuf>,
+    #[serde(default)]
+    pub cmdline: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Whether this is the window the remote command was sent from
+    #[serde(default)]
+    pub is_self: bool,
+    pub foreground_processes: Vec<Process>,
+    /// Variables set with `kitty @ set-user-vars`, see [`crate::tag`]
+    #[serde(default)]
+    pub user_vars: HashMap<String, String>,
+    /// The socket of the kitty instance the window belongs to, see [`OsWindow::socket`]
+    #[serde(default)]
+    pub socket: Option<String>,
+}
+
+impl Window {
+    /// The key of the window among the windows of every kitty instance
+    #[must_use]
+    pub fn key(&self) -> WindowKey {
+        WindowKey {
+            socket: self.socket.clone(),
+            id: self.id.0,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Window {
+    /// An unfocused window without processes, for tests
+    pub(crate) fn new(id: u32) -> Self {
+        Self {
+            id: WindowId(id),
+            is_active: false,
+            is_focused: false,
+            title: String::new(),
+            pid: None,
+            cwd: None,
+            cmdline: Vec::new(),
+            env: HashMap::new(),
+            is_self: false,
+            foreground_processes: Vec::new(),
+            user_vars: HashMap::new(),
+            socket: None,
+        }
+    }
+
+    /// Sets the given user vars on the window, e.g. the tags of [`crate::tag`]
+    pub(crate) fn tagged(mut self, vars: &[(&str, &str)]) -> Self {
+        for (name, value) in vars {
+            self.user_vars
+                .insert((*name).to_string(), (*value).to_string());
+        }
+        self
+    }
+}
+
+/// Identifies a window when the windows of several kitty instances are listed: window ids are
+/// only unique within an instance
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct WindowKey {
+    /// See [`Window::socket`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub socket: Option<String>,
+    pub id: u32,
+}
+
+impl WindowKey {
+    #[must_use]
+    pub fn window_id(&self) -> WindowId {
+        WindowId(self.id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use crate::kitty_terminal::test_fixture::{LS_OUTPUT, LS_OUTPUT_JSON};
+
+    use super::OsWindows;
+
+    #[test]
+    fn test_kitty_ls_output_can_be_deserialized() {
+        let output: OsWindows = serde_json::from_str(LS_OUTPUT_JSON).unwrap();
+
+        let expected: &OsWindows = &LS_OUTPUT;
+
+        assert_eq!(&output, expected);
+    }
+}
@@ -0,0 +1,330 @@
+//! Asking which helix to open a file in when felis can't tell: when several helix windows match the
+//! file equally well, or when none of them do.
+//!
+//! The picker runs in a kitty overlay on top of the current window: felis launches itself with the
+//! hidden `pick-window` subcommand, which lists the windows to choose from and writes the id of the
+//! chosen one to a file in felis' private runtime directory (see [`handoff::runtime_dir`]). The
+//! overlay is launched with `--wait-for-child-to-exit`, so the file can be read as soon as the
+//! launch command returns.
+//!
+//! Choices are kept per project root, so the same question is only asked once per invocation. They
+//! can also be remembered across invocations, see [`Choices::load`].
+
+use std::{
+    collections::HashMap,
+    fmt::{Display, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use kitty_remote_bindings::command::options::LaunchType;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    command, handoff,
+    kitty_terminal::{command::Launch, KittyTerminal},
+    model::{Tab, Window, WindowKey},
+    FelisError, Result,
+};
+
+/// A window that can be picked
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub window: WindowKey,
+    pub label: String,
+}
+
+impl Item {
+    /// An item labelled by the title of the tab and the working directory of the window
+    #[must_use]
+    pub fn new(tab: &Tab, window: &Window) -> Self {
+        let label = format!("{}  {}", tab.title, command::window_cwd(window).display());
+
+        Self {
+            window: window.key(),
+            // Items are passed to the picker as arguments, one per line
+            label: label.replace(char::is_control, " "),
+        }
+    }
+}
+
+/// Items are formatted as `<window id>\t<socket>\t<label>` to be passed to the picker, the socket
+/// is empty when the windows of a single kitty instance are listed
+impl Display for Item {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let socket = self.window.socket.as_deref().unwrap_or_default();
+        write!(f, "{}\t{socket}\t{}", self.window.id, self.label)
+    }
+}
+
+impl FromStr for Item {
+    type Err = FelisError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || FelisError::UnexpectedError {
+            message: format!("Invalid picker item: {s}"),
+        };
+        let mut fields = s.splitn(3, '\t');
+        let (Some(id), Some(socket), Some(label)) = (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(invalid());
+        };
+
+        Ok(Self {
+            window: WindowKey {
+                socket: (!socket.is_empty()).then(|| socket.to_string()),
+                id: id.parse().map_err(|_| invalid())?,
+            },
+            label: label.to_string(),
+        })
+    }
+}
+
+/// Launches the picker in an overlay and waits for the choice, returns `None` when the picker was
+/// cancelled. `kitty` is the kitty instance of the window the overlay is shown on.
+pub async fn pick(kitty: &KittyTerminal, items: &[Item]) -> Result<Option<WindowKey>> {
+    let output = handoff::runtime_dir()?.join(format!("pick-{}", std::process::id()));
+    // A leftover from an earlier run would be taken as the choice
+    remove_file(&output)?;
+
+    let executable = std::env::current_exe()?;
+    let mut args = vec![
+        executable.to_string_lossy().to_string(),
+        "pick-window".to_string(),
+        "--output".to_string(),
+        output.to_string_lossy().to_string(),
+    ];
+    args.extend(items.iter().map(ToString::to_string));
+
+    kitty
+        .launch_and_wait(
+            Launch::new(args)
+                .launch_type(LaunchType::Overlay)
+                .title("felis: pick a window".to_string()),
+        )
+        .await?;
+
+    let choice = match std::fs::read_to_string(&output) {
+        Ok(content) => content
+            .trim_end_matches('\n')
+            .parse::<Item>()
+            .map(|item| Some(item.window)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    };
+    remove_file(&output)?;
+
+    choice
+}
+
+/// Lists the items and asks for a choice until a valid one is given, this is what runs in the
+/// overlay. Returns `None` when the input is empty or closed.
+pub async fn prompt<R, W>(items: &[Item], mut input: R, mut output: W) -> Result<Option<Item>>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut menu = String::from("Open in:\n\n");
+    for (i, item) in items.iter().enumerate() {
+        let _ = write!(
+            menu,
+            "  {}) {}  (window {}",
+            i + 1,
+            item.label,
+            item.window.id
+        );
+        match &item.window.socket {
+            Some(socket) => {
+                let _ = writeln!(menu, " of {socket})");
+            }
+            None => menu.push_str(")\n"),
+        }
+    }
+    output.write_all(menu.as_bytes()).await?;
+
+    loop {
+        output
+            .write_all(format!("\nChoose 1-{} (empty to cancel): ", items.len()).as_bytes())
+            .await?;
+        output.flush().await?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line).await? == 0 || line.trim().is_empty() {
+            return Ok(None);
+        }
+
+        match line.trim().parse::<usize>() {
+            Ok(choice) if (1..=items.len()).contains(&choice) => {
+                return Ok(Some(items[choice - 1].clone()))
+            }
+            _ => output.write_all(b"Invalid choice\n").await?,
+        }
+    }
+}
+
+/// The windows picked for project roots
+#[derive(Debug, Default)]
+pub struct Choices {
+    path: Option<PathBuf>,
+    choices: HashMap<PathBuf, WindowKey>,
+}
+
+impl Choices {
+    /// Loads the remembered choices from the given file, they are saved back to the same file. A
+    /// missing or unreadable file is the same as no remembered choices: the file is only a cache.
+    #[must_use]
+    pub fn load(path: PathBuf) -> Self {
+        let choices = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        Self {
+            path: Some(path),
+            choices,
+        }
+    }
+
+    #[must_use]
+    pub fn get(&self, root: &Path) -> Option<&WindowKey> {
+        self.choices.get(root)
+    }
+
+    pub fn insert(&mut self, root: PathBuf, window: WindowKey) {
+        self.choices.insert(root, window);
+    }
+
+    /// Saves the choices to the file they were loaded from, if any
+    pub fn save(&self) -> Result<()> {
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            let content = serde_json::to_string_pretty(&self.choices).map_err(|err| {
+                FelisError::UnexpectedError {
+                    message: err.to_string(),
+                }
+            })?;
+            std::fs::write(path, content)?;
+        }
+
+        Ok(())
+    }
+
+    /// `$XDG_STATE_HOME/felis/choices.json`, or `~/.local/state/felis/choices.json`
+    #[must_use]
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("state"))
+            })
+            .map(|dir| dir.join("felis").join("choices.json"))
+    }
+}
+
+fn remove_file(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use pretty_assertions::assert_eq;
+
+    use crate::model::WindowKey;
+
+    use super::{prompt, Choices, Item};
+
+    fn key(socket: Option<&str>, id: u32) -> WindowKey {
+        WindowKey {
+            socket: socket.map(String::from),
+            id,
+        }
+    }
+
+    fn items() -> Vec<Item> {
+        vec![
+            Item {
+                window: key(None, 1),
+                label: "felis  /path/to/felis".to_string(),
+            },
+            Item {
+                window: key(None, 4),
+                label: "docs  /path/to/felis/docs".to_string(),
+            },
+            Item {
+                window: key(Some("unix:/tmp/kitty.sock-2"), 4),
+                label: "docs  /path/to/felis/docs".to_string(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_item_round_trips_through_its_argument_format() {
+        let items = items();
+
+        assert_eq!(items[1].to_string(), "4\t\tdocs  /path/to/felis/docs");
+        assert_eq!(
+            items[2].to_string(),
+            "4\tunix:/tmp/kitty.sock-2\tdocs  /path/to/felis/docs"
+        );
+        for item in &items {
+            assert_eq!(item.to_string().parse::<Item>().unwrap(), *item);
+        }
+        assert!("4\tdocs".parse::<Item>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_prompt_asks_again_after_an_invalid_choice() {
+        let mut output = Vec::new();
+
+        let choice = prompt(&items(), "4\nfoo\n3\n".as_bytes(), &mut output)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            choice.map(|item| item.window),
+            Some(key(Some("unix:/tmp/kitty.sock-2"), 4))
+        );
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("  2) docs  /path/to/felis/docs  (window 4)\n"));
+        assert!(output
+            .contains("  3) docs  /path/to/felis/docs  (window 4 of unix:/tmp/kitty.sock-2)\n"));
+        assert_eq!(output.matches("Invalid choice").count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_prompt_is_cancelled_by_an_empty_line() {
+        for input in ["\n", ""] {
+            let choice = prompt(&items(), input.as_bytes(), Vec::new())
+                .await
+                .unwrap();
+
+            assert_eq!(choice, None);
+        }
+    }
+
+    #[test]
+    fn test_choices_are_saved_and_loaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("felis").join("choices.json");
+
+        let mut choices = Choices::load(path.clone());
+        assert_eq!(choices.get(Path::new("/src/app")), None);
+        choices.insert(
+            PathBuf::from("/src/app"),
+            key(Some("unix:/tmp/kitty.sock-2"), 3),
+        );
+        choices.save().unwrap();
+
+        assert_eq!(
+            Choices::load(path).get(Path::new("/src/app")),
+            Some(&key(Some("unix:/tmp/kitty.sock-2"), 3))
+        );
+    }
+}
@@ -0,0 +1,335 @@
+//! The steel plugin, `felis.scm` in helix' config directory, and the messages felis hands to it
+//! (see [`crate::handoff`]). A message is a JSON object with the version of the protocol and a
+//! batch of requests, run in order:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "requests": [
+//!     { "type": "open", "path": "src/lib.rs", "line": 13, "column": 3 },
+//!     { "type": "open", "path": "src/main.rs", "split": "vertical" },
+//!     { "type": "command", "name": "reload" }
+//!   ]
+//! }
+//! ```
+//!
+//! A range is opened at its first line, the message doesn't carry the rest of it: felis selects it
+//! with keys once the file is shown (see [`crate::strategy`]).
+//!
+//! Commands are deliberately limited to the plugin's `felis-commands` table, the plugin refuses
+//! the others and the names in a message are never evaluated: a message can't run e.g.
+//! `:run-shell-command`. The table is extended by adding entries to it, felis reads the commands
+//! from the plugin's file (see [`commands`]), the same way as its version.
+//!
+//! The plugin declares the version it speaks with `(define felis-protocol-version 1)`, felis reads
+//! it from the plugin's file before handing anything to it.
+//!
+//! `fixtures/felis-message.json` is a message using every kind of request, see the README for
+//! checking the plugin with it.
+
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fs::{FileLocation, Position},
+    FelisError, Result,
+};
+
+/// The version of the protocol felis speaks, a change in the messages bumps it
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Message {
+    pub version: u32,
+    pub requests: Vec<Request>,
+}
+
+impl Message {
+    #[must_use]
+    pub fn new(requests: Vec<Request>) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            requests,
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Will return Err if the message can't be serialized
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string(self).map_err(|err| FelisError::UnexpectedError {
+            message: err.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Request {
+    Open(Open),
+    /// Runs a typed command, e.g. `reload` or `theme` with its arguments, see [`Request::command`]
+    Command {
+        name: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        args: Vec<String>,
+    },
+}
+
+impl Request {
+    /// A typed command, `commands` are the ones the plugin runs, see [`commands`]
+    ///
+    /// # Errors
+    ///
+    /// Will return Err if the plugin doesn't run the command
+    pub fn command(name: &str, args: Vec<String>, commands: &[String]) -> Result<Self> {
+        if !commands.iter().any(|command| command == name) {
+            return Err(FelisError::UnexpectedError {
+                message: format!("The steel plugin doesn't run the {name} command"),
+            });
+        }
+
+        Ok(Self::Command {
+            name: name.to_string(),
+            args,
+        })
+    }
+}
+
+/// Opens a file, optionally in a new split
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Open {
+    /// The path, relative to helix' working directory or absolute
+    pub path: String,
+    /// The line the cursor is placed on, from 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    /// The column the cursor is placed on, from 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub split: Option<Split>,
+}
+
+impl Open {
+    /// Opens the path at the position of the location. Only the first line of a range is carried,
+    /// felis selects the range once the file is open.
+    #[must_use]
+    pub fn new<P>(path: String, location: &FileLocation<P>) -> Self {
+        let (line, column) = match location.position {
+            Some(Position::Line { line, column }) => (Some(line), column),
+            Some(Position::Range { start, .. }) => (Some(start), None),
+            None => (None, None),
+        };
+
+        Self {
+            path,
+            line,
+            column,
+            split: None,
+        }
+    }
+}
+
+/// The split a file is opened in, see [`crate::config::OpenConfig::split`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Split {
+    Horizontal,
+    Vertical,
+}
+
+/// The plugin's file in helix' config directory, `$XDG_CONFIG_HOME/helix/felis.scm` (or
+/// `~/.config/helix/felis.scm`)
+#[must_use]
+pub fn plugin_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .map(|dir| dir.join("helix").join("felis.scm"))
+}
+
+/// Checks that the plugin speaks felis' protocol version
+///
+/// # Errors
+///
+/// Will return Err if the plugin can't be read, or it declares another version or none
+pub fn check_version(path: &Path) -> Result<()> {
+    let error = |reason: String| FelisError::PluginProtocol {
+        path: path.display().to_string(),
+        expected: PROTOCOL_VERSION,
+        reason,
+    };
+    let source =
+        std::fs::read_to_string(path).map_err(|err| error(format!("it can't be read: {err}")))?;
+
+    match declared_version(&source) {
+        Some(PROTOCOL_VERSION) => Ok(()),
+        Some(version) => Err(error(format!("it speaks version {version}"))),
+        None => Err(error("it doesn't declare its version".to_string())),
+    }
+}
+
+/// The typed commands the plugin runs, the names of its `felis-commands` table
+///
+/// # Errors
+///
+/// Will return Err if the plugin can't be read
+pub fn commands(path: &Path) -> Result<Vec<String>> {
+    let source = std::fs::read_to_string(path)?;
+
+    Ok(declared_commands(&source))
+}
+
+/// The names of the `felis-commands` table in the plugin's source, an entry is a name followed by
+/// the procedure running it, e.g. `"reload" helix.reload`
+fn declared_commands(source: &str) -> Vec<String> {
+    let Some(start) = source.find("(define felis-commands") else {
+        return Vec::new();
+    };
+    let table = &source[start..];
+    let table = &table[..table.find("))").map_or(table.len(), |end| end + 2)];
+    let entry = Regex::new(r#""([^"\s]+)"\s+[^\s()]+"#).expect("valid regex");
+
+    entry
+        .captures_iter(table)
+        .map(|captures| captures[1].to_string())
+        .collect()
+}
+
+/// The version declared by the plugin's source
+fn declared_version(source: &str) -> Option<u32> {
+    let declaration =
+        Regex::new(r"\(define\s+felis-protocol-version\s+(\d+)\s*\)").expect("valid regex");
+
+    declaration.captures(source)?[1].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use regex::Regex;
+
+    use super::{
+        check_version, commands, declared_commands, declared_version, Message, Open, Request, Split,
+    };
+    use crate::{fs::FileLocation, FelisError};
+
+    fn bundled_commands() -> Vec<String> {
+        commands(concat!(env!("CARGO_MANIFEST_DIR"), "/felis.scm").as_ref()).unwrap()
+    }
+
+    #[test]
+    fn test_message_as_json() {
+        let location = FileLocation::from("src/lib.rs:10-20");
+        let message = Message::new(vec![
+            Request::Open(Open::new("src/lib.rs".to_string(), &location)),
+            Request::Open(Open {
+                split: Some(Split::Vertical),
+                ..Open::new(
+                    "src/main.rs".to_string(),
+                    &FileLocation::from("src/main.rs"),
+                )
+            }),
+            Request::command("theme", vec!["onedark".to_string()], &bundled_commands()).unwrap(),
+        ]);
+
+        let json = message.to_json().unwrap();
+        assert_eq!(
+            json,
+            r#"{"version":1,"requests":[{"type":"open","path":"src/lib.rs","line":10},{"type":"open","path":"src/main.rs","split":"vertical"},{"type":"command","name":"theme","args":["onedark"]}]}"#
+        );
+        assert_eq!(serde_json::from_str::<Message>(&json).unwrap(), message);
+    }
+
+    #[test]
+    fn test_the_bundled_plugin_speaks_felis_protocol() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/felis.scm");
+
+        check_version(path.as_ref()).unwrap();
+    }
+
+    #[test]
+    fn test_message_of_the_fixture() {
+        let fixture = include_str!("../fixtures/felis-message.json");
+        let message = Message::new(vec![
+            Request::Open(Open::new(
+                "src/lib.rs".to_string(),
+                &FileLocation::from("src/lib.rs:10:3"),
+            )),
+            Request::Open(Open {
+                split: Some(Split::Vertical),
+                ..Open::new(
+                    "src/plugin.rs".to_string(),
+                    &FileLocation::from("src/plugin.rs:30-42"),
+                )
+            }),
+            Request::command("theme", vec!["default".to_string()], &bundled_commands()).unwrap(),
+        ]);
+
+        assert_eq!(serde_json::from_str::<Message>(fixture).unwrap(), message);
+    }
+
+    #[test]
+    fn test_commands_are_the_ones_of_the_plugin() {
+        let plugin = include_str!("../felis.scm");
+        let table = &plugin[plugin.find("(define felis-commands").unwrap()..];
+        let table = &table[..table.find("))").unwrap()];
+        // The bundled commands run the helix command of the same name
+        let entry = Regex::new(r#""([a-z-]+)"\s+helix\.([a-z-]+)"#).unwrap();
+        for captures in entry.captures_iter(table) {
+            assert_eq!(captures[1], captures[2]);
+        }
+
+        assert_eq!(
+            bundled_commands(),
+            ["reload", "reload-all", "write", "theme", "buffer-close"]
+        );
+        assert_eq!(
+            declared_commands(r#"(define felis-commands (hash "format" helix.format "vs" my-vs))"#),
+            ["format", "vs"]
+        );
+        assert!(declared_commands("(define felis-protocol-version 1)").is_empty());
+        assert!(Request::command("sh", vec!["rm -rf ~".to_string()], &bundled_commands()).is_err());
+        assert!(Request::command("format", Vec::new(), &["format".to_string()]).is_ok());
+    }
+
+    #[test]
+    fn test_declared_version_is_read_from_the_source() {
+        assert_eq!(
+            declared_version("(define  felis-protocol-version 2)"),
+            Some(2)
+        );
+        assert_eq!(declared_version("(define (felis-open) #t)"), None);
+    }
+
+    #[test]
+    fn test_missing_plugin_is_a_protocol_error() {
+        let result = check_version("/nonexistent/felis.scm".as_ref());
+
+        assert!(
+            matches!(result, Err(FelisError::PluginProtocol { .. })),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn test_plugin_with_another_version_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("felis.scm");
+        std::fs::write(&path, "(define felis-protocol-version 2)\n").unwrap();
+
+        match check_version(&path) {
+            Err(FelisError::PluginProtocol {
+                expected, reason, ..
+            }) => {
+                assert_eq!(expected, 1);
+                assert_eq!(reason, "it speaks version 2");
+            }
+            result => panic!("Unexpected result: {result:?}"),
+        }
+    }
+}
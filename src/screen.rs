@@ -0,0 +1,267 @@
+//! helix' screen, as read from its kitty window with `kitty @ get-text`. felis only looks at the
+//! bottom of the screen: helix draws the statusline, with the name of the current buffer, right
+//! above the command line, where it shows its messages (e.g. errors). kitty ends every line of the
+//! screen with a new line, so an empty command line is still a line.
+
+use std::path::Path;
+
+use regex::Regex;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Screen {
+    lines: Vec<String>,
+    cursor: Option<Cursor>,
+}
+
+/// The terminal cursor, helix only shows it in prompts, as it draws its own cursors in the buffers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cursor {
+    pub visible: bool,
+    /// The line of the cursor, from 1
+    pub row: usize,
+    /// The column of the cursor, from 1
+    pub column: usize,
+}
+
+impl Screen {
+    /// Parses the text of the screen, with the escape codes of the cursor when they were asked for
+    #[must_use]
+    pub fn new(text: &str) -> Self {
+        // The escape codes `kitty @ get-text --add-cursor` appends to the text: whether the cursor
+        // is visible, and its position
+        let codes = Regex::new(r"\x1b\[\?25([hl])\x1b\[(\d+);(\d+)H").expect("valid regex");
+        let (text, cursor) = match codes.captures(text) {
+            Some(captures) => {
+                let cursor = Cursor {
+                    visible: &captures[1] == "h",
+                    row: captures[2].parse().unwrap_or_default(),
+                    column: captures[3].parse().unwrap_or_default(),
+                };
+                (
+                    &text[..captures.get(0).map_or(0, |m| m.start())],
+                    Some(cursor),
+                )
+            }
+            None => (text, None),
+        };
+
+        Self {
+            lines: text.lines().map(str::to_string).collect(),
+            cursor,
+        }
+    }
+
+    /// The number of lines of the screen
+    #[must_use]
+    pub fn height(&self) -> usize {
+        self.lines.len()
+    }
+
+    #[must_use]
+    pub fn cursor(&self) -> Option<Cursor> {
+        self.cursor
+    }
+
+    /// The mode shown at the start of the statusline, e.g. `NOR`
+    #[must_use]
+    pub fn mode(&self) -> Option<&str> {
+        self.statusline()?.split_whitespace().next()
+    }
+
+    /// The keys of a pending keymap, e.g. `g` or `mi`. helix shows them at the right end of the
+    /// command line, while its messages start on the left: a short word on the right half of the
+    /// command line is taken for the pending keys.
+    #[must_use]
+    pub fn pending_keys(&self) -> Option<&str> {
+        let width = self.statusline()?.chars().count();
+        let line = self.lines.last()?.trim_end();
+        let (before, keys) = line.rsplit_once(char::is_whitespace)?;
+
+        (!keys.is_empty()
+            && keys.chars().count() <= 5
+            && before.ends_with("  ")
+            && line.chars().count() > width / 2)
+            .then_some(keys)
+    }
+
+    /// The statusline, the line above the command line
+    #[must_use]
+    pub fn statusline(&self) -> Option<&str> {
+        self.lines
+            .len()
+            .checked_sub(2)
+            .map(|index| self.lines[index].as_str())
+    }
+
+    /// The message on the command line, `None` when there's none
+    #[must_use]
+    pub fn message(&self) -> Option<&str> {
+        self.lines
+            .last()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+    }
+
+    /// Whether the statusline shows the buffer of the path. helix shows the path relative to its
+    /// working directory, or the absolute path with the home directory folded to `~`.
+    #[must_use]
+    pub fn shows_buffer(&self, path: &str) -> bool {
+        let Some(statusline) = self.statusline() else {
+            return false;
+        };
+        let folded = std::env::var_os("HOME")
+            .and_then(|home| Path::new(path).strip_prefix(home).ok().map(Path::to_owned))
+            .map(|path| format!("~/{}", path.display()));
+
+        contains_word(statusline, path)
+            || folded.is_some_and(|folded| contains_word(statusline, &folded))
+    }
+}
+
+/// Whether the line contains the word, surrounded by spaces or at the start or the end of the line
+fn contains_word(line: &str, word: &str) -> bool {
+    line.match_indices(word).any(|(index, _)| {
+        let before = line[..index].chars().next_back();
+        let after = line[index + word.len()..].chars().next();
+        before.is_none_or(char::is_whitespace) && after.is_none_or(char::is_whitespace)
+    })
+}
+
+#[cfg(test)]
+pub mod test_fixture {
+    /// helix showing `src/lib.rs`
+    pub const OPENED: &str = "  1 pub mod command;
+  2 pub mod config;
+  3 pub mod discovery;
+~
+~
+ NOR   src/lib.rs                                              1 sel  1:1
+
+";
+
+    /// helix with the file picker open, the keys were typed into the picker
+    pub const PICKER: &str = "  1 fn main() {
+ ┌──────────────────────────────────────┐┌──────────────────────────────────┐
+ │> :open src/lib.rs                0/42││                                  │
+ │                                      ││                                  │
+ └──────────────────────────────────────┘└──────────────────────────────────┘
+ NOR   src/main.rs                                             1 sel  1:1
+
+";
+
+    /// The text read with `--add-cursor`: helix in normal mode, with a hidden cursor
+    pub const NORMAL: &str = "  1 fn main() {
+~
+ NOR   src/main.rs                                             1 sel  1:1
+
+\x1b[?25l\x1b[1;5H\x1b[2 q";
+
+    /// helix in insert mode, with a completion popup
+    pub const INSERT: &str = "  1 fn main() {
+  2     pri
+        println!
+        print!
+ INS   src/main.rs [+]                                         1 sel  2:8
+
+\x1b[?25l\x1b[2;8H\x1b[2 q";
+
+    /// helix in select mode
+    pub const SELECT: &str = "  1 fn main() {
+~
+ SEL   src/main.rs                                             1 sel  1:4
+
+\x1b[?25l\x1b[1;5H\x1b[2 q";
+
+    /// helix waiting for the rest of a `g` key sequence
+    pub const PENDING: &str = "  1 fn main() {
+~                                                  ┌Goto──────────────────┐
+~                                                  │g  Goto line number…  │
+~                                                  │e  Goto last line     │
+ NOR   src/main.rs                                 └──────────────────────┘
+                                                                          g
+\x1b[?25l\x1b[1;5H\x1b[2 q";
+
+    /// helix with the command prompt open
+    pub const PROMPT: &str = "  1 fn main() {
+~
+ NOR   src/main.rs                                             1 sel  1:1
+:wri
+\x1b[?25h\x1b[4;5H\x1b[2 q";
+
+    /// helix with the file picker open, the cursor is in the picker's prompt
+    pub const PICKER_PROMPT: &str = "  1 fn main() {
+ ┌──────────────────────────────────────┐┌──────────────────────────────────┐
+ │> ma                              2/42││                                  │
+ │ src/main.rs                          ││                                  │
+ └──────────────────────────────────────┘└──────────────────────────────────┘
+ NOR   src/main.rs                                             1 sel  1:1
+
+\x1b[?25h\x1b[3;7H\x1b[2 q";
+
+    /// Not helix, a shell
+    pub const SHELL: &str = "$ cargo test
+    Finished `test` profile [unoptimized + debuginfo] target(s) in 0.12s
+$
+\x1b[?25h\x1b[3;3H\x1b[2 q";
+
+    /// helix failing to open the file
+    pub const ERROR: &str = "  1 fn main() {
+~
+ NOR   src/main.rs                                             1 sel  1:1
+unable to open \"src/lib.rs\": Permission denied (os error 13)
+";
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{test_fixture, Cursor, Screen};
+
+    #[test]
+    fn test_statusline_shows_the_buffer() {
+        let screen = Screen::new(test_fixture::OPENED);
+
+        assert!(screen.shows_buffer("src/lib.rs"));
+        assert!(!screen.shows_buffer("lib.rs"));
+        assert_eq!(screen.message(), None);
+    }
+
+    #[test]
+    fn test_statusline_shows_another_buffer() {
+        let picker = Screen::new(test_fixture::PICKER);
+        assert!(!picker.shows_buffer("src/lib.rs"));
+
+        let error = Screen::new(test_fixture::ERROR);
+        assert!(!error.shows_buffer("src/lib.rs"));
+        assert_eq!(
+            error.message(),
+            Some("unable to open \"src/lib.rs\": Permission denied (os error 13)")
+        );
+    }
+
+    #[test]
+    fn test_cursor_and_mode() {
+        let prompt = Screen::new(test_fixture::PROMPT);
+
+        assert_eq!(
+            prompt.cursor(),
+            Some(Cursor {
+                visible: true,
+                row: 4,
+                column: 5
+            })
+        );
+        assert_eq!(prompt.height(), 4);
+        assert_eq!(prompt.mode(), Some("NOR"));
+        assert_eq!(prompt.message(), Some(":wri"));
+        assert_eq!(Screen::new(test_fixture::OPENED).cursor(), None);
+    }
+
+    #[test]
+    fn test_pending_keys() {
+        assert_eq!(Screen::new(test_fixture::PENDING).pending_keys(), Some("g"));
+        assert_eq!(Screen::new(test_fixture::NORMAL).pending_keys(), None);
+        assert_eq!(Screen::new(test_fixture::ERROR).pending_keys(), None);
+    }
+}
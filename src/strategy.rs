@@ -0,0 +1,541 @@
+//! How the files are handed to helix. helix has no remote control of its own, so the paths are
+//! either typed into its command line, or written in a file read by a plugin command. A transport
+//! talking to helix directly (e.g. over a socket, once helix has one) is another implementation of
+//! [`OpenStrategy`].
+//!
+//! The strategy is chosen on the command line, per project or in the config (see
+//! [`crate::config::OpenConfig`]), or it's detected from the helix running in the window.
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use async_trait::async_trait;
+use kitty_remote_bindings::command::options::Matcher;
+use regex::Regex;
+use tokio::io::AsyncReadExt;
+
+use crate::{
+    command::{helix_quote, helix_target, kitty_escape, relative_path, window_cwd},
+    config::{OpenConfig, Strategy},
+    fs::{AbsolutePath, FileLocation, Position},
+    handoff::Handoff,
+    kitty_terminal::KittyTerminal,
+    model::{Process, Window},
+    plugin::{self, Message, Open, Request, Split},
+    FelisError, Result,
+};
+
+/// The files to open in a helix window
+#[derive(Debug)]
+pub struct OpenRequest<'a> {
+    pub window: &'a Window,
+    /// The helix process of the window, as recognised by [`crate::editor::EditorDetector`]
+    pub helix: Option<&'a Process>,
+    pub locations: &'a [&'a FileLocation<AbsolutePath>],
+    /// The keys going back to normal mode, typed before anything else
+    pub reset: &'a str,
+    /// Open the files in new splits, see [`crate::config::OpenConfig::split`]
+    pub split: Option<Split>,
+}
+
+impl OpenRequest<'_> {
+    /// The paths relative to helix' working directory, followed by their positions
+    ///
+    /// # Errors
+    ///
+    /// Will return Err if a path contains control characters
+    pub fn targets(&self) -> Result<Vec<String>> {
+        self.locations
+            .iter()
+            .map(|location| helix_target(self.window, location))
+            .collect()
+    }
+
+    /// The typed command opening the files: `open`, or `vsplit` / `hsplit` for a split
+    #[must_use]
+    pub fn command(&self) -> &'static str {
+        match self.split {
+            None => "open",
+            Some(Split::Vertical) => "vsplit",
+            Some(Split::Horizontal) => "hsplit",
+        }
+    }
+
+    /// The position of the last location, its range is selected once it's open
+    #[must_use]
+    pub fn position(&self) -> Option<Position> {
+        self.locations.last().and_then(|location| location.position)
+    }
+}
+
+#[async_trait]
+pub trait OpenStrategy: Send + Sync {
+    /// Opens the files in helix. `kitty` is the kitty instance of the window.
+    async fn open(&self, kitty: &KittyTerminal, request: &OpenRequest<'_>) -> Result<()>;
+}
+
+/// Types the `:open` command without a bracketed paste, for helix versions that don't handle them.
+/// The command is sent at once, but helix takes every character as a key press and completes the
+/// command line after each of them, so this is the slowest one.
+pub struct PlainKeys;
+
+#[async_trait]
+impl OpenStrategy for PlainKeys {
+    async fn open(&self, kitty: &KittyTerminal, request: &OpenRequest<'_>) -> Result<()> {
+        let command = format!(
+            r"{} {}",
+            request.command(),
+            kitty_escape(&arguments(&request.targets()?)?)
+        );
+        type_command(kitty, request, &command).await
+    }
+}
+
+/// Types the `:open` command with the paths in a bracketed paste, so the completion is not
+/// triggered after each character
+pub struct BracketedPaste;
+
+const PASTE_START: &str = r"\x1b[200~";
+const PASTE_END: &str = r"\x1b[201~";
+
+#[async_trait]
+impl OpenStrategy for BracketedPaste {
+    async fn open(&self, kitty: &KittyTerminal, request: &OpenRequest<'_>) -> Result<()> {
+        let command = format!(
+            r"{} {PASTE_START}{}{PASTE_END}",
+            request.command(),
+            kitty_escape(&arguments(&request.targets()?)?)
+        );
+        type_command(kitty, request, &command).await
+    }
+}
+
+/// Writes the files in a handoff as a [`Message`], then runs the `felis-open` command of the steel
+/// plugin (see `felis.scm`) with the path of the handoff, see [`Handoff`]. The plugin has to speak
+/// felis' protocol version.
+pub struct SteelHandoff;
+
+#[async_trait]
+impl OpenStrategy for SteelHandoff {
+    async fn open(&self, kitty: &KittyTerminal, request: &OpenRequest<'_>) -> Result<()> {
+        let plugin = plugin::plugin_path().ok_or_else(|| FelisError::UnexpectedError {
+            message: "Couldn't find helix' config directory".to_string(),
+        })?;
+        plugin::check_version(&plugin)?;
+
+        let helix = request.helix.ok_or_else(|| FelisError::UnexpectedError {
+            message: format!("Couldn't find helix in window {}", request.window.id.0),
+        })?;
+        let handoff = Handoff::new(helix.pid)?;
+        handoff.write(Self::message(request)?.to_json()?.as_bytes())?;
+
+        let path = helix_quote(&handoff.path().to_string_lossy())?;
+        type_command(
+            kitty,
+            request,
+            &format!("felis-open {}", kitty_escape(&path)),
+        )
+        .await
+    }
+}
+
+impl SteelHandoff {
+    /// The message opening the files of the request, each of them in a split of its own when the
+    /// request has a split
+    fn message(request: &OpenRequest<'_>) -> Result<Message> {
+        let requests = request
+            .locations
+            .iter()
+            .map(|location| {
+                let path = relative_path(window_cwd(request.window), location)?;
+                Ok(Request::Open(Open {
+                    split: request.split,
+                    ..Open::new(path, location)
+                }))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Message::new(requests))
+    }
+}
+
+/// The targets quoted for helix' command line
+fn arguments(targets: &[String]) -> Result<String> {
+    Ok(targets
+        .iter()
+        .map(|target| helix_quote(target))
+        .collect::<Result<Vec<_>>>()?
+        .join(" "))
+}
+
+/// Types the command into helix' command line, in a single `kitty @ send-text` call
+async fn type_command(
+    kitty: &KittyTerminal,
+    request: &OpenRequest<'_>,
+    command: &str,
+) -> Result<()> {
+    kitty
+        .send_text(
+            Matcher::Id(request.window.id),
+            &keystrokes(request.reset, command, request.position()),
+        )
+        .await
+}
+
+/// Everything that needs to be typed into helix to run the command, encoded for kitty: the reset
+/// going back to normal mode, the command, then the selection of the range of the last file
+fn keystrokes(reset: &str, command: &str, position: Option<Position>) -> String {
+    let mut keystrokes = format!(r"{reset}:{command}\r");
+
+    // Only the last opened file is visible, so only its range can be selected
+    if let Some(Position::Range { start, end }) = position {
+        // The cursor is already on the first line of the range, extend the selection to the last
+        // line of the range (`x` selects the current line, and with a count the following lines)
+        let _ = write!(keystrokes, "{}x", end - start + 1);
+    }
+
+    keystrokes
+}
+
+/// Selects the strategy: the one given on the command line, or the one configured for the project,
+/// detected from the helix process of the window when it's `auto`
+pub async fn select(
+    choice: Option<Strategy>,
+    config: &OpenConfig,
+    project: &Path,
+    helix: Option<&Process>,
+) -> Box<dyn OpenStrategy> {
+    let strategy = match choice.unwrap_or_else(|| config.strategy_for(project)) {
+        Strategy::Auto => detect(helix).await,
+        strategy => strategy,
+    };
+
+    match strategy {
+        Strategy::Plain => Box::new(PlainKeys),
+        Strategy::Auto | Strategy::BracketedPaste => Box::new(BracketedPaste),
+        Strategy::Steel => Box::new(SteelHandoff),
+    }
+}
+
+/// The first helix release handling bracketed pastes
+const BRACKETED_PASTE_SINCE: (u32, u32) = (22, 12);
+
+/// Detects the strategy: the steel plugin when it's installed, it speaks felis' protocol version
+/// and helix is a steel build, otherwise the keys helix understands, according to its version. A
+/// helix whose version can't be told is expected to be a recent one.
+pub async fn detect(helix: Option<&Process>) -> Strategy {
+    if let Some(helix) = helix {
+        // A plugin speaking another version is left alone, the keys are typed instead
+        let plugin = plugin::plugin_path().filter(|path| plugin::check_version(path).is_ok());
+        if plugin.is_some() && is_steel_build(helix).await {
+            return Strategy::Steel;
+        }
+    }
+
+    let output = tokio::process::Command::new(helix_program(helix))
+        .arg("--version")
+        .output()
+        .await;
+    match output
+        .ok()
+        .and_then(|output| helix_version(&String::from_utf8_lossy(&output.stdout)))
+    {
+        Some(version) if version < BRACKETED_PASTE_SINCE => Strategy::Plain,
+        _ => Strategy::BracketedPaste,
+    }
+}
+
+/// The program of the helix process, or `hx` when there's none
+fn helix_program(helix: Option<&Process>) -> &str {
+    helix
+        .and_then(|process| process.cmdline.first())
+        .map_or("hx", String::as_str)
+}
+
+/// The paths of steel's sources end up in the binary of a steel build, e.g. in its panic messages
+const STEEL_MARKER: &[u8] = b"steel-core";
+
+/// The size of the chunks the binaries are searched in
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Whether the binaries are steel builds, by their path and modification time
+type SteelBuilds = HashMap<(PathBuf, Option<SystemTime>), bool>;
+
+static STEEL_BUILDS: OnceLock<Mutex<SteelBuilds>> = OnceLock::new();
+
+/// Whether helix is a steel build, i.e. its binary embeds steel. The binary is the one of the
+/// running process (`/proc/<pid>/exe`), or its program when there's no `/proc`. A binary that
+/// can't be read is not taken as a steel build.
+///
+/// The answer is remembered until the binary changes, helix binaries are large to search.
+async fn is_steel_build(helix: &Process) -> bool {
+    let exe = Path::new("/proc").join(helix.pid.to_string()).join("exe");
+    let binary = if exe.exists() {
+        exe
+    } else {
+        PathBuf::from(helix_program(Some(helix)))
+    };
+    let Ok(metadata) = tokio::fs::metadata(&binary).await else {
+        return false;
+    };
+    // `/proc/<pid>/exe` is a link to the binary, shared by every process running it
+    let path = tokio::fs::canonicalize(&binary).await.unwrap_or(binary);
+    let key = (path, metadata.modified().ok());
+
+    let builds = STEEL_BUILDS.get_or_init(Mutex::default);
+    if let Some(steel) = builds.lock().expect("not poisoned").get(&key) {
+        return *steel;
+    }
+    let steel = embeds_steel(&key.0).await.unwrap_or(false);
+    builds.lock().expect("not poisoned").insert(key, steel);
+
+    steel
+}
+
+/// Searches the binary for [`STEEL_MARKER`] chunk by chunk, without reading all of it
+async fn embeds_steel(binary: &Path) -> std::io::Result<bool> {
+    let mut file = tokio::fs::File::open(binary).await?;
+    let mut buffer = vec![0; CHUNK_SIZE];
+    // The end of the previous chunk is kept, the marker might span two chunks
+    let mut kept = 0;
+
+    loop {
+        let read = file.read(&mut buffer[kept..]).await?;
+        if read == 0 {
+            return Ok(false);
+        }
+        let len = kept + read;
+        if buffer[..len]
+            .windows(STEEL_MARKER.len())
+            .any(|window| window == STEEL_MARKER)
+        {
+            return Ok(true);
+        }
+        kept = len.min(STEEL_MARKER.len() - 1);
+        buffer.copy_within(len - kept..len, 0);
+    }
+}
+
+/// The version printed by `hx --version`, e.g. `helix 24.7 (079f5442)`
+fn helix_version(text: &str) -> Option<(u32, u32)> {
+    let version = Regex::new(r"^helix (\d+)\.(\d+)").expect("valid regex");
+    let captures = version.captures(text.trim_start())?;
+
+    Some((captures[1].parse().ok()?, captures[2].parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        os::unix::process::ExitStatusExt,
+        process::{ExitStatus, Output},
+        time::SystemTime,
+    };
+
+    use kitty_remote_bindings::{
+        command::{options::Matcher, SendText},
+        model::WindowId,
+    };
+    use mockall::predicate::eq;
+    use pretty_assertions::assert_eq;
+
+    use super::{
+        helix_program, helix_version, is_steel_build, keystrokes, BracketedPaste, OpenRequest,
+        OpenStrategy, PlainKeys, SteelHandoff, CHUNK_SIZE, STEEL_MARKER,
+    };
+    use crate::{
+        editor::EditorDetector,
+        fs::{AbsolutePath, FileLocation, Position},
+        kitty_terminal::{test_fixture, KittyTerminal, MockExecutor},
+        model::{OsWindows, Process},
+        plugin::Split,
+    };
+
+    fn windows() -> OsWindows {
+        serde_json::from_str(test_fixture::LS_OUTPUT_JSON).unwrap()
+    }
+
+    fn expect_send_text(executor: &mut MockExecutor, text: &str) {
+        let cmd = SendText::new(text.to_string())
+            .matcher(Matcher::Id(WindowId(1)))
+            .to("DummySocket".to_string());
+        executor
+            .expect_send_text()
+            .times(1)
+            .with(eq(cmd))
+            .returning(|_| {
+                Ok(Output {
+                    status: ExitStatus::from_raw(0),
+                    stdout: Vec::new(),
+                    stderr: Vec::new(),
+                })
+            });
+    }
+
+    #[test]
+    fn test_keystrokes_are_planned_as_one_text() {
+        assert_eq!(
+            keystrokes(r"\x1b[27u", "open src/lib.rs:3", None),
+            r"\x1b[27u:open src/lib.rs:3\r"
+        );
+        assert_eq!(
+            keystrokes(
+                r"\x1b[27u",
+                "felis-open",
+                Some(Position::Range { start: 3, end: 5 })
+            ),
+            r"\x1b[27u:felis-open\r3x"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_keys_of_the_strategies() {
+        let windows = windows();
+        let locations: Vec<FileLocation<AbsolutePath>> = [
+            "/path/to/felis/src/lib.rs:3",
+            "/path/to/felis/src/my file.rs",
+        ]
+        .map(|path| FileLocation::from(path).try_into().unwrap())
+        .into();
+        let window = &windows.0[0].tabs[0].windows[0];
+        let request = OpenRequest {
+            window,
+            helix: None,
+            locations: &locations.iter().collect::<Vec<_>>(),
+            reset: r"\x1b[27u",
+            split: None,
+        };
+
+        let mut executor = MockExecutor::new();
+        expect_send_text(
+            &mut executor,
+            r"\x1b[27u:open \x1b[200~src/lib.rs:3 'src/my file.rs'\x1b[201~\r",
+        );
+        expect_send_text(
+            &mut executor,
+            r"\x1b[27u:open src/lib.rs:3 'src/my file.rs'\r",
+        );
+        let kitty = KittyTerminal::mock(executor);
+
+        BracketedPaste.open(&kitty, &request).await.unwrap();
+        PlainKeys.open(&kitty, &request).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_files_are_opened_in_splits() {
+        let windows = windows();
+        let locations: Vec<FileLocation<AbsolutePath>> =
+            ["/path/to/felis/src/lib.rs:3", "/path/to/felis/src/main.rs"]
+                .map(|path| FileLocation::from(path).try_into().unwrap())
+                .into();
+        let window = &windows.0[0].tabs[0].windows[0];
+        let request = OpenRequest {
+            window,
+            helix: None,
+            locations: &locations.iter().collect::<Vec<_>>(),
+            reset: r"\x1b[27u",
+            split: Some(Split::Vertical),
+        };
+
+        let mut executor = MockExecutor::new();
+        expect_send_text(
+            &mut executor,
+            r"\x1b[27u:vsplit \x1b[200~src/lib.rs:3 src/main.rs\x1b[201~\r",
+        );
+        BracketedPaste
+            .open(&KittyTerminal::mock(executor), &request)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            SteelHandoff::message(&request).unwrap().to_json().unwrap(),
+            concat!(
+                r#"{"version":1,"requests":["#,
+                r#"{"type":"open","path":"src/lib.rs","line":3,"split":"vertical"},"#,
+                r#"{"type":"open","path":"src/main.rs","split":"vertical"}]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_helix_version() {
+        assert_eq!(helix_version("helix 24.7 (079f5442)\n"), Some((24, 7)));
+        assert_eq!(helix_version("helix 22.08.1 (66276ce6)"), Some((22, 8)));
+        assert_eq!(helix_version("hx: command not found"), None);
+    }
+
+    #[test]
+    fn test_helix_program() {
+        let windows = windows();
+        let window = &windows.0[0].tabs[0].windows[0];
+
+        let detector = EditorDetector::default();
+        let helix = window
+            .foreground_processes
+            .iter()
+            .find(|process| detector.is_editor(process));
+
+        assert_eq!(
+            helix_program(helix),
+            "/etc/profiles/per-user/gaborpihaj/bin/hx"
+        );
+        assert_eq!(helix_program(None), "hx");
+    }
+
+    #[tokio::test]
+    async fn test_steel_build_is_told_by_its_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let helix = |binary: &str, contents: &[u8]| {
+            let path = dir.path().join(binary);
+            std::fs::write(&path, contents).unwrap();
+            Process {
+                // No such process, the binary is the program
+                pid: 0,
+                cwd: dir.path().to_path_buf(),
+                cmdline: vec![path.to_string_lossy().to_string()],
+            }
+        };
+
+        assert!(is_steel_build(&helix("hx-steel", b"\0/src/steel-core-0.6.0/src/\0")).await);
+        assert!(!is_steel_build(&helix("hx", b"\0/src/helix-term/src/\0")).await);
+        assert!(
+            !is_steel_build(&Process {
+                pid: 0,
+                cwd: dir.path().to_path_buf(),
+                cmdline: vec!["/nonexistent/hx".to_string()],
+            })
+            .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_steel_build_is_found_across_chunks_and_rechecked_when_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hx");
+        let helix = Process {
+            pid: 0,
+            cwd: dir.path().to_path_buf(),
+            cmdline: vec![path.to_string_lossy().to_string()],
+        };
+        let mut contents = vec![0; CHUNK_SIZE - 4];
+        contents.extend_from_slice(STEEL_MARKER);
+        std::fs::write(&path, &contents).unwrap();
+
+        assert!(is_steel_build(&helix).await);
+
+        std::fs::write(&path, vec![0; CHUNK_SIZE]).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH)
+            .unwrap();
+
+        assert!(!is_steel_build(&helix).await);
+    }
+}
@@ -0,0 +1,175 @@
+//! Explicit tags on kitty windows, stored as kitty user variables (`kitty @ set-user-vars`).
+//!
+//! A window can be tagged with a role, e.g. `editor`, and with a project. Tags take precedence over
+//! the heuristics based on the working directories of the foreground processes, but helix is still
+//! recognised by its process (see [`crate::editor::EditorDetector`]):
+//!
+//! - a window with any other role than `editor` is never used as helix, windows can be selected by
+//!   their role (see [`crate::workspace::WindowTarget`])
+//! - a window tagged with a project is the best candidate for the files of that project, and it is
+//!   not a candidate for the files of other projects. The project is either the name of the
+//!   project's root directory (see [`crate::workspace::project_root`]), or a path.
+
+use std::{fmt::Display, path::Path, str::FromStr};
+
+use crate::{model::Window, FelisError, Result};
+
+/// The user variable holding the role of a window
+pub const ROLE_VAR: &str = "felis_role";
+/// The user variable holding the project of a window
+pub const PROJECT_VAR: &str = "felis_project";
+/// The role of windows running helix
+pub const EDITOR_ROLE: &str = "editor";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tags {
+    pub role: Option<String>,
+    pub project: Option<String>,
+}
+
+impl Tags {
+    /// The tags of the window
+    #[must_use]
+    pub fn of(window: &Window) -> Self {
+        Self {
+            role: window.user_vars.get(ROLE_VAR).cloned(),
+            project: window.user_vars.get(PROJECT_VAR).cloned(),
+        }
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.role.is_none() && self.project.is_none()
+    }
+
+    /// Whether the window has all of these tags
+    #[must_use]
+    pub fn matches(&self, window: &Window) -> bool {
+        let tags = Self::of(window);
+
+        self.role
+            .iter()
+            .all(|role| tags.role.as_ref() == Some(role))
+            && self
+                .project
+                .iter()
+                .all(|project| tags.project.as_ref() == Some(project))
+    }
+
+    /// Whether the project tag is the given project: the name of the project root, or a path
+    /// containing it
+    #[must_use]
+    pub fn is_project(&self, root: &Path) -> bool {
+        self.project.as_ref().is_some_and(|project| {
+            if project.contains('/') {
+                root.starts_with(project)
+            } else {
+                root.file_name()
+                    .is_some_and(|name| name == project.as_str())
+            }
+        })
+    }
+
+    /// The user variables to set, in the `NAME=VALUE` format of `kitty @ set-user-vars`
+    #[must_use]
+    pub fn to_user_vars(&self) -> Vec<String> {
+        [(ROLE_VAR, &self.role), (PROJECT_VAR, &self.project)]
+            .into_iter()
+            .filter_map(|(name, value)| value.as_ref().map(|value| format!("{name}={value}")))
+            .collect()
+    }
+}
+
+/// Tags are formatted as a comma separated list of `role=<role>` and `project=<project>`
+impl Display for Tags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tags = [("role", &self.role), ("project", &self.project)]
+            .into_iter()
+            .filter_map(|(name, value)| value.as_ref().map(|value| format!("{name}={value}")))
+            .collect::<Vec<_>>();
+
+        write!(f, "{}", tags.join(","))
+    }
+}
+
+impl FromStr for Tags {
+    type Err = FelisError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut tags = Self::default();
+        for tag in s.split(',') {
+            match tag.split_once('=') {
+                Some(("role", role)) if !role.is_empty() => tags.role = Some(role.to_string()),
+                Some(("project", project)) if !project.is_empty() => {
+                    tags.project = Some(project.to_string());
+                }
+                _ => {
+                    return Err(FelisError::UnexpectedError {
+                        message: format!(
+                            "Invalid tag: {tag}, expected role=<role> or project=<project>"
+                        ),
+                    })
+                }
+            }
+        }
+
+        Ok(tags)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::model::Window;
+
+    use super::Tags;
+
+    #[test]
+    fn test_tags_are_read_from_user_vars() {
+        let window = Window::new(1).tagged(&[("felis_role", "editor"), ("felis_project", "api")]);
+
+        assert_eq!(
+            Tags::of(&window),
+            Tags {
+                role: Some("editor".to_string()),
+                project: Some("api".to_string()),
+            }
+        );
+        assert!("role=editor".parse::<Tags>().unwrap().matches(&window));
+        assert!("project=api,role=editor"
+            .parse::<Tags>()
+            .unwrap()
+            .matches(&window));
+        assert!(!"project=web".parse::<Tags>().unwrap().matches(&window));
+        assert!(!"role=editor"
+            .parse::<Tags>()
+            .unwrap()
+            .matches(&Window::new(1).tagged(&[])));
+    }
+
+    #[test]
+    fn test_tags_round_trip_through_their_string_format() {
+        let tags = "role=editor,project=api".parse::<Tags>().unwrap();
+
+        assert_eq!(tags.to_string(), "role=editor,project=api");
+        assert_eq!(
+            tags.to_user_vars(),
+            vec!["felis_role=editor", "felis_project=api"]
+        );
+        assert!("role=".parse::<Tags>().is_err());
+        assert!("editor".parse::<Tags>().is_err());
+    }
+
+    #[test]
+    fn test_project_is_matched_by_name_or_path() {
+        let root = Path::new("/src/api");
+
+        assert!("project=api".parse::<Tags>().unwrap().is_project(root));
+        assert!("project=/src".parse::<Tags>().unwrap().is_project(root));
+        assert!(!"project=web".parse::<Tags>().unwrap().is_project(root));
+        assert!(!Tags::default().is_project(root));
+    }
+}
@@ -0,0 +1,928 @@
+//! Finding the helix instance a file should be opened in.
+//!
+//! Every helix process whose working directory contains the file is a candidate, and so is every
+//! helix running inside the file's project (see [`project_root`]). Windows tagged with `felis tag`
+//! take precedence over these heuristics, see [`crate::tag`]. Candidates are ranked by:
+//!
+//! 1. whether the window is tagged with the file's project, helix' working directory contains the
+//!    file, or it is only in the same project
+//! 2. the depth of helix' working directory, the deepest one wins, e.g. for
+//!    `~/src/monorepo/crates/foo/src/lib.rs` a helix running in `~/src/monorepo/crates/foo` is
+//!    preferred over one running in `~/src/monorepo`
+//! 3. the proximity to the window where felis was invoked from: same tab, then same OS window
+//! 4. recency: the active OS window, then the active tab of its OS window, then the tab's window
+//!    focus history
+
+use std::{
+    cmp::Reverse,
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{
+    command,
+    config::WorkspaceConfig,
+    editor::EditorDetector,
+    fs::AbsolutePath,
+    matcher::MatchExpr,
+    model::{OsWindow, OsWindows, Process, Tab, Window, WindowId},
+    tag::{Tags, EDITOR_ROLE},
+    FelisError, Result,
+};
+
+/// How close a candidate is to the window felis was invoked from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Proximity {
+    Elsewhere,
+    SameOsWindow,
+    SameTab,
+}
+
+/// How helix' working directory relates to the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Match {
+    /// The working directory is in the same project as the file, e.g. a sibling crate
+    ProjectRoot,
+    /// The working directory contains the file
+    WorkingDirectory,
+    /// The window is tagged with the file's project
+    Tagged,
+}
+
+/// The score of a candidate, a greater score is a better match
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Score {
+    pub kind: Match,
+    /// Number of path components in helix' working directory
+    pub depth: usize,
+    pub proximity: Proximity,
+    pub recency: Recency,
+}
+
+impl Score {
+    /// How well the window fits the file, regardless of how recently it was used
+    #[must_use]
+    pub fn fit(&self) -> (Match, usize, Proximity) {
+        (self.kind, self.depth, self.proximity)
+    }
+}
+
+/// How recently a window was used, a greater one is more recent. The focus histories of different
+/// tabs are not on a common scale, so the OS window and the tab being active come first, then the
+/// number of windows that were active in the tab since the window was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Recency {
+    pub os_window: bool,
+    pub tab: bool,
+    /// 0 for the active window of the tab, `usize::MAX` when the window never was active
+    pub since: Reverse<usize>,
+}
+
+/// A window running helix in a working directory that contains the file
+#[derive(Debug, Clone, Copy)]
+pub struct Candidate<'a> {
+    pub os_window: &'a OsWindow,
+    pub tab: &'a Tab,
+    pub window: &'a Window,
+    pub score: Score,
+}
+
+/// A window selected explicitly: by its id, by its tags or by a kitty match expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum WindowTarget {
+    /// Window ids are only unique within a kitty instance: without a socket the id is looked up
+    /// in kitty's own instance
+    Id {
+        id: WindowId,
+        socket: Option<String>,
+    },
+    Tags(Tags),
+    Match(MatchExpr),
+}
+
+/// Targets are formatted as the window id, optionally followed by the socket of its kitty instance,
+/// e.g. `3@unix:/tmp/kitty-2`, as tags, e.g. `role=editor,project=api`, or as the match expression.
+/// Only ids and tags can be parsed back, match expressions are parsed by [`MatchExpr`].
+impl Display for WindowTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WindowTarget::Id { id, socket: None } => write!(f, "{}", id.0),
+            WindowTarget::Id {
+                id,
+                socket: Some(socket),
+            } => write!(f, "{}@{socket}", id.0),
+            WindowTarget::Tags(tags) => write!(f, "{tags}"),
+            WindowTarget::Match(expr) => write!(f, "{expr}"),
+        }
+    }
+}
+
+impl FromStr for WindowTarget {
+    type Err = FelisError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (id, socket) = match s.split_once('@') {
+            Some((id, socket)) => (id, Some(socket.to_string())),
+            None => (s, None),
+        };
+        match id.parse() {
+            Ok(id) => Ok(WindowTarget::Id {
+                id: WindowId(id),
+                socket,
+            }),
+            Err(_) => Ok(WindowTarget::Tags(s.parse()?)),
+        }
+    }
+}
+
+pub struct WorkspaceResolver<'a> {
+    windows: &'a OsWindows,
+    origin: Option<&'a Window>,
+    root_markers: Vec<String>,
+    detector: EditorDetector,
+}
+
+impl<'a> WorkspaceResolver<'a> {
+    /// Creates a resolver where the origin is the active focused window, i.e. the window where
+    /// felis was invoked from
+    #[must_use]
+    pub fn new(windows: &'a OsWindows) -> Self {
+        Self {
+            windows,
+            origin: command::focused_active_window(windows),
+            root_markers: WorkspaceConfig::default().root_markers,
+            detector: EditorDetector::default(),
+        }
+    }
+
+    /// Sets how helix processes are recognised
+    #[must_use]
+    pub fn detector(mut self, detector: EditorDetector) -> Self {
+        self.detector = detector;
+        self
+    }
+
+    /// Sets the files or directories that mark the root of a project, see [`project_root`]
+    #[must_use]
+    pub fn root_markers(mut self, root_markers: &[String]) -> Self {
+        self.root_markers = root_markers.to_vec();
+        self
+    }
+
+    /// Sets the window felis was invoked from, it is used to rank candidates by proximity
+    #[must_use]
+    pub fn origin(mut self, origin: Option<WindowId>) -> Self {
+        self.origin = origin.and_then(|id| {
+            self.all_windows()
+                .map(|(_, _, window)| window)
+                .find(|window| window.id == id)
+        });
+        self
+    }
+
+    /// The window felis was invoked from, new windows are launched in its kitty instance
+    #[must_use]
+    pub fn origin_window(&self) -> Option<&'a Window> {
+        self.origin
+    }
+
+    /// Returns all the candidates for the given file, the best match first
+    #[must_use]
+    pub fn candidates(&self, path: &AbsolutePath) -> Vec<Candidate<'a>> {
+        let origin = self.origin.and_then(|window| self.locate(window));
+        let root = project_root(path.as_ref(), &self.root_markers);
+
+        let mut candidates = self
+            .all_windows()
+            .filter_map(|(os_window, tab, window)| {
+                let tags = Tags::of(window);
+                let processes = self.editor_processes(window);
+                let depth = |process: &Process| process.cwd.components().count();
+
+                let (kind, depth) = if tags.project.is_some() {
+                    // A window tagged with a project is only used for the files of that project
+                    if !root.as_ref().is_some_and(|root| tags.is_project(root)) {
+                        return None;
+                    }
+                    (Match::Tagged, processes.iter().map(|p| depth(p)).max()?)
+                } else {
+                    processes
+                        .iter()
+                        .filter_map(|process| {
+                            let kind = if is_in_workspace(process, path) {
+                                Match::WorkingDirectory
+                            } else if root.as_ref().is_some_and(|r| process.cwd.starts_with(r)) {
+                                Match::ProjectRoot
+                            } else {
+                                return None;
+                            };
+                            Some((kind, depth(process)))
+                        })
+                        .max()?
+                };
+
+                Some(Candidate {
+                    os_window,
+                    tab,
+                    window,
+                    score: Score {
+                        kind,
+                        depth,
+                        proximity: proximity(origin, os_window, tab),
+                        recency: recency(os_window, tab, window),
+                    },
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // sort_by_key is stable, so equally good candidates keep the order of `kitty @ ls`
+        candidates.sort_by_key(|candidate| Reverse(candidate.score));
+        candidates
+    }
+
+    /// Returns the window of the best candidate
+    pub fn resolve(&self, path: &AbsolutePath) -> Result<&'a Window> {
+        self.candidates(path)
+            .first()
+            .map(|candidate| candidate.window)
+            .ok_or_else(|| FelisError::UnexpectedError {
+                message: format!(
+                    "Couldn't find workspace for file {}",
+                    path.as_ref().display()
+                ),
+            })
+    }
+
+    /// Returns the best candidates when there are several equally good ones, i.e. when the best
+    /// candidate is ambiguous. Recency doesn't tell candidates apart, only their [`Score::fit`].
+    /// Returns an empty list when the best candidate is unique, or when there are no candidates at
+    /// all.
+    #[must_use]
+    pub fn ambiguous(&self, path: &AbsolutePath) -> Vec<Candidate<'a>> {
+        let candidates = self.candidates(path);
+        let best = candidates
+            .iter()
+            .take_while(|candidate| candidate.score.fit() == candidates[0].score.fit())
+            .count();
+
+        if best > 1 {
+            candidates[..best].to_vec()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Returns all the windows running helix, in the order of `kitty @ ls`
+    #[must_use]
+    pub fn editors(&self) -> Vec<(&'a Tab, &'a Window)> {
+        self.all_windows()
+            .filter(|(_, _, window)| self.is_editor(window))
+            .map(|(_, tab, window)| (tab, window))
+            .collect()
+    }
+
+    /// Returns the most recently active helix window, preferring the ones closer to the origin,
+    /// regardless of its working directory
+    #[must_use]
+    pub fn most_recent(&self) -> Option<&'a Window> {
+        self.closest(|_, _, window| self.is_editor(window))
+    }
+
+    /// Returns the window with the given tags, preferring the ones running helix, then the ones
+    /// closer to the origin
+    #[must_use]
+    pub fn tagged(&self, tags: &Tags) -> Option<&'a Window> {
+        self.closest(|_, _, window| tags.matches(window))
+    }
+
+    /// Returns the window matching the kitty match expression, preferring the ones running helix,
+    /// then the ones closer to the origin
+    #[must_use]
+    pub fn matching(&self, expr: &MatchExpr) -> Option<&'a Window> {
+        self.closest(|os_window, tab, window| expr.matches(os_window, tab, window))
+    }
+
+    /// Returns the window selected by the target. A window id without a socket is looked up in
+    /// kitty's own instance, its windows are listed first, see [`crate::KittyTerminal::ls`].
+    pub fn target(&self, target: &WindowTarget) -> Result<&'a Window> {
+        let window = match target {
+            WindowTarget::Id { id, socket } => {
+                let primary = self.windows.0.first().and_then(|w| w.socket.as_deref());
+                // The windows of a single instance are not tagged with its socket
+                let socket = socket.as_deref().or(primary);
+                self.all_windows()
+                    .map(|(_, _, window)| window)
+                    .find(|window| {
+                        window.id == *id
+                            && (window.socket.is_none() || window.socket.as_deref() == socket)
+                    })
+            }
+            WindowTarget::Tags(tags) => self.tagged(tags),
+            WindowTarget::Match(expr) => self.matching(expr),
+        };
+
+        window.ok_or_else(|| FelisError::UnexpectedError {
+            message: format!("Couldn't find window {target}"),
+        })
+    }
+
+    /// Returns a window where the only foreground process is a shell running in the given
+    /// directory, i.e. a shell that is waiting for a command to run
+    #[must_use]
+    pub fn idle_shell(&self, dir: &Path) -> Option<&'a Window> {
+        self.all_windows()
+            .map(|(_, _, window)| window)
+            .find(|window| match window.foreground_processes.as_slice() {
+                [process] => is_shell(process) && process.cwd == dir,
+                _ => false,
+            })
+    }
+
+    /// The root of the file's project if it has one, otherwise the directory of the file
+    #[must_use]
+    pub fn project_dir(&self, path: &AbsolutePath) -> PathBuf {
+        project_root(path.as_ref(), &self.root_markers)
+            .or_else(|| path.as_ref().parent().map(Path::to_path_buf))
+            .unwrap_or_else(|| path.as_ref().to_path_buf())
+    }
+
+    /// The window accepted by the predicate that runs helix, is the closest to the origin and the
+    /// most recently active, in this order
+    fn closest<F>(&self, predicate: F) -> Option<&'a Window>
+    where
+        F: Fn(&OsWindow, &Tab, &Window) -> bool,
+    {
+        let origin = self.origin.and_then(|window| self.locate(window));
+
+        self.all_windows()
+            .filter(|(os_window, tab, window)| predicate(os_window, tab, window))
+            // max_by_key returns the last maximum, reverse to keep the order of `kitty @ ls`
+            .rev()
+            .max_by_key(|(os_window, tab, window)| {
+                (
+                    self.is_editor(window),
+                    proximity(origin, os_window, tab),
+                    recency(os_window, tab, window),
+                )
+            })
+            .map(|(_, _, window)| window)
+    }
+
+    /// The helix process running in the window, as recognised by the detector regardless of the
+    /// window's tags
+    #[must_use]
+    pub fn helix_process(&self, window: &'a Window) -> Option<&'a Process> {
+        window
+            .foreground_processes
+            .iter()
+            .find(|process| self.detector.is_editor(process))
+    }
+
+    fn is_editor(&self, window: &Window) -> bool {
+        !self.editor_processes(window).is_empty()
+    }
+
+    /// The foreground processes of the window that are helix, none of them when the window is
+    /// tagged with another role than an editor. The editor role doesn't make a process helix, e.g.
+    /// once helix exited and the shell is back in the foreground.
+    fn editor_processes(&self, window: &'a Window) -> Vec<&'a Process> {
+        match Tags::of(window).role {
+            Some(role) if role != EDITOR_ROLE => Vec::new(),
+            _ => window
+                .foreground_processes
+                .iter()
+                .filter(|process| self.detector.is_editor(process))
+                .collect(),
+        }
+    }
+
+    fn all_windows(&self) -> impl DoubleEndedIterator<Item = (&'a OsWindow, &'a Tab, &'a Window)> {
+        self.windows.0.iter().flat_map(|os_window| {
+            os_window.tabs.iter().flat_map(move |tab| {
+                tab.windows
+                    .iter()
+                    .map(move |window| (os_window, tab, window))
+            })
+        })
+    }
+
+    fn locate(&self, window: &Window) -> Option<(&'a OsWindow, &'a Tab)> {
+        self.all_windows()
+            .find(|(_, _, w)| std::ptr::eq(*w, window))
+            .map(|(os_window, tab, _)| (os_window, tab))
+    }
+}
+
+/// Finds the root of the project the given file belongs to: the closest ancestor directory that
+/// contains any of the given markers. `Cargo.toml` is only considered as a marker when it defines a
+/// `[workspace]`, so the members of a cargo workspace belong to the same project.
+#[must_use]
+pub fn project_root(path: &Path, markers: &[String]) -> Option<PathBuf> {
+    path.parent()?
+        .ancestors()
+        .find(|dir| markers.iter().any(|marker| is_root_marker(dir, marker)))
+        .map(Path::to_path_buf)
+}
+
+fn is_root_marker(dir: &Path, marker: &str) -> bool {
+    let path = dir.join(marker);
+    if marker == "Cargo.toml" {
+        std::fs::read_to_string(path)
+            .is_ok_and(|content| content.lines().any(|line| line.trim() == "[workspace]"))
+    } else {
+        path.exists()
+    }
+}
+
+fn proximity(origin: Option<(&OsWindow, &Tab)>, os_window: &OsWindow, tab: &Tab) -> Proximity {
+    match origin {
+        // Ids are only unique within a kitty instance
+        Some((_, origin_tab)) if std::ptr::eq(origin_tab, tab) => Proximity::SameTab,
+        Some((origin_os_window, _)) if std::ptr::eq(origin_os_window, os_window) => {
+            Proximity::SameOsWindow
+        }
+        _ => Proximity::Elsewhere,
+    }
+}
+
+fn recency(os_window: &OsWindow, tab: &Tab, window: &Window) -> Recency {
+    let since = if window.is_active {
+        0
+    } else {
+        tab.active_window_history
+            .iter()
+            .rev()
+            .position(|id| *id == window.id)
+            .map_or(usize::MAX, |position| position + 1)
+    };
+
+    Recency {
+        os_window: os_window.is_active,
+        tab: tab.is_active,
+        since: Reverse(since),
+    }
+}
+
+fn is_shell(process: &Process) -> bool {
+    process.cmdline.first().is_some_and(|argv0| {
+        // login shells are started as e.g. `-zsh`
+        let name = argv0.rsplit('/').next().unwrap_or(argv0);
+        matches!(
+            name.trim_start_matches('-'),
+            "sh" | "bash" | "zsh" | "fish" | "nu" | "dash" | "ksh" | "tcsh" | "elvish" | "xonsh"
+        )
+    })
+}
+
+fn is_in_workspace(process: &Process, path: &AbsolutePath) -> bool {
+    path.as_ref()
+        .parent()
+        .is_some_and(|p| p.starts_with(process.cwd.as_path()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    use crate::{
+        fs::AbsolutePath,
+        kitty_terminal::test_fixture,
+        model::{OsWindow, OsWindowId, OsWindows, Process, Tab, TabId, Window, WindowId},
+    };
+
+    use super::{project_root, Match, Proximity, WindowTarget, WorkspaceResolver};
+
+    fn window(id: u32, cwd: &str, cmd: &str) -> Window {
+        Window {
+            foreground_processes: vec![Process {
+                pid: id,
+                cwd: PathBuf::from(cwd),
+                cmdline: vec![cmd.to_string()],
+            }],
+            ..Window::new(id)
+        }
+    }
+
+    fn tab(id: u32, windows: Vec<Window>, history: &[u32]) -> Tab {
+        Tab {
+            id: TabId(id),
+            is_active: false,
+            is_focused: false,
+            title: String::new(),
+            active_window_history: history.iter().map(|id| WindowId(*id)).collect(),
+            windows,
+        }
+    }
+
+    fn os_window(id: u32, tabs: Vec<Tab>) -> OsWindow {
+        OsWindow {
+            id: OsWindowId(id),
+            is_active: false,
+            is_focused: false,
+            tabs,
+            socket: None,
+        }
+    }
+
+    fn ranked_ids(resolver: &WorkspaceResolver, path: &str) -> Vec<u32> {
+        resolver
+            .candidates(&AbsolutePath::try_from(path).unwrap())
+            .iter()
+            .map(|candidate| candidate.window.id.0)
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_finds_helix_in_the_fixture() {
+        let resolver = WorkspaceResolver::new(&test_fixture::LS_OUTPUT);
+
+        let window = resolver
+            .resolve(&AbsolutePath::try_from("/path/to/felis/src/lib.rs").unwrap())
+            .unwrap();
+
+        assert_eq!(window.id, WindowId(1));
+        assert!(resolver
+            .resolve(&AbsolutePath::try_from("/path/to/other-project/lib.rs").unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_deepest_working_directory_wins() {
+        let windows = OsWindows(vec![os_window(
+            1,
+            vec![
+                tab(1, vec![window(1, "/src/monorepo", "/bin/hx")], &[]),
+                tab(
+                    2,
+                    vec![window(2, "/src/monorepo/crates/foo", "/bin/hx")],
+                    &[],
+                ),
+            ],
+        )]);
+        let resolver = WorkspaceResolver::new(&windows);
+
+        assert_eq!(
+            ranked_ids(&resolver, "/src/monorepo/crates/foo/src/lib.rs"),
+            vec![2, 1]
+        );
+        assert_eq!(
+            ranked_ids(&resolver, "/src/monorepo/crates/bar/src/lib.rs"),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn test_proximity_to_origin_breaks_ties() {
+        let windows = OsWindows(vec![
+            os_window(1, vec![tab(1, vec![window(1, "/src/app", "/bin/hx")], &[])]),
+            os_window(
+                2,
+                vec![
+                    tab(2, vec![window(2, "/src/app", "/bin/hx")], &[]),
+                    tab(
+                        3,
+                        vec![
+                            window(3, "/src/app", "/bin/hx"),
+                            window(4, "/src/app", "-zsh"),
+                        ],
+                        &[],
+                    ),
+                ],
+            ),
+        ]);
+        let resolver = WorkspaceResolver::new(&windows).origin(Some(WindowId(4)));
+
+        assert_eq!(ranked_ids(&resolver, "/src/app/lib.rs"), vec![3, 2, 1]);
+        assert_eq!(
+            resolver
+                .candidates(&AbsolutePath::try_from("/src/app/lib.rs").unwrap())
+                .iter()
+                .map(|candidate| candidate.score.proximity)
+                .collect::<Vec<_>>(),
+            vec![
+                Proximity::SameTab,
+                Proximity::SameOsWindow,
+                Proximity::Elsewhere
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recency_breaks_ties() {
+        let windows = OsWindows(vec![os_window(
+            1,
+            vec![tab(
+                1,
+                vec![
+                    window(1, "/src/app", "/bin/hx"),
+                    window(2, "/src/app", "/bin/hx"),
+                    window(3, "/src/app", "/bin/hx"),
+                ],
+                &[1, 3, 2],
+            )],
+        )]);
+        let resolver = WorkspaceResolver::new(&windows);
+
+        assert_eq!(ranked_ids(&resolver, "/src/app/lib.rs"), vec![2, 3, 1]);
+    }
+
+    #[test]
+    fn test_recency_of_the_active_tab_comes_before_the_focus_history() {
+        let mut active = tab(2, vec![window(3, "/src/app", "/bin/hx")], &[4, 3]);
+        active.is_active = true;
+        let windows = OsWindows(vec![os_window(
+            1,
+            vec![
+                tab(
+                    1,
+                    vec![
+                        window(1, "/src/app", "/bin/hx"),
+                        window(2, "/src/app", "-zsh"),
+                    ],
+                    &[2, 2, 2, 1],
+                ),
+                active,
+            ],
+        )]);
+        let resolver = WorkspaceResolver::new(&windows).origin(None);
+
+        assert_eq!(ranked_ids(&resolver, "/src/app/lib.rs"), vec![3, 1]);
+    }
+
+    fn create_project(files: &[(&str, &str)]) -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (file, content) in files {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_project_root_finds_closest_marker() {
+        let project = create_project(&[(".git/HEAD", ""), ("crates/foo/src/lib.rs", "")]);
+        let markers = vec![".git".to_string()];
+
+        assert_eq!(
+            project_root(&project.path().join("crates/foo/src/lib.rs"), &markers),
+            Some(project.path().to_path_buf())
+        );
+        assert_eq!(project_root(Path::new("/lib.rs"), &markers), None);
+    }
+
+    #[test]
+    fn test_project_root_only_accepts_cargo_workspaces() {
+        let project = create_project(&[
+            ("Cargo.toml", "[workspace]\nmembers = [\"crates/*\"]\n"),
+            ("crates/foo/Cargo.toml", "[package]\nname = \"foo\"\n"),
+        ]);
+        let markers = vec!["Cargo.toml".to_string()];
+
+        assert_eq!(
+            project_root(&project.path().join("crates/foo/src/lib.rs"), &markers),
+            Some(project.path().to_path_buf())
+        );
+    }
+
+    #[test]
+    fn test_helix_in_the_same_project_is_a_candidate() {
+        let project = create_project(&[(".git/HEAD", "")]);
+        let root = project.path().to_string_lossy().to_string();
+        let windows = OsWindows(vec![os_window(
+            1,
+            vec![
+                tab(
+                    1,
+                    vec![window(1, &format!("{root}/crates/foo"), "/bin/hx")],
+                    &[],
+                ),
+                tab(2, vec![window(2, "/src/elsewhere", "/bin/hx")], &[]),
+                tab(
+                    3,
+                    vec![window(3, &format!("{root}/crates/bar"), "/bin/hx")],
+                    &[],
+                ),
+            ],
+        )]);
+        let resolver = WorkspaceResolver::new(&windows).root_markers(&[".git".to_string()]);
+        let path = format!("{root}/crates/bar/src/lib.rs");
+
+        assert_eq!(ranked_ids(&resolver, &path), vec![3, 1]);
+        assert_eq!(
+            resolver
+                .candidates(&AbsolutePath::try_from(path.as_str()).unwrap())
+                .iter()
+                .map(|candidate| candidate.score.kind)
+                .collect::<Vec<_>>(),
+            vec![Match::WorkingDirectory, Match::ProjectRoot]
+        );
+    }
+
+    #[test]
+    fn test_most_recent_ignores_the_working_directory() {
+        let windows = OsWindows(vec![os_window(
+            1,
+            vec![tab(
+                1,
+                vec![
+                    window(1, "/src/app", "/bin/hx"),
+                    window(2, "/src/other", "/bin/hx"),
+                    window(3, "/src/other", "-zsh"),
+                ],
+                &[2, 1, 3],
+            )],
+        )]);
+        let resolver = WorkspaceResolver::new(&windows);
+
+        assert_eq!(resolver.most_recent().map(|w| w.id), Some(WindowId(1)));
+    }
+
+    #[test]
+    fn test_idle_shell_is_found_by_its_working_directory() {
+        let resolver = WorkspaceResolver::new(&test_fixture::LS_OUTPUT);
+
+        assert_eq!(
+            resolver
+                .idle_shell(Path::new("/path/to/other-project"))
+                .map(|w| w.id),
+            Some(WindowId(3))
+        );
+        assert!(resolver.idle_shell(Path::new("/path/to/felis")).is_none());
+    }
+
+    #[test]
+    fn test_ambiguous_returns_the_equally_good_candidates() {
+        let windows = OsWindows(vec![
+            os_window(
+                1,
+                vec![tab(
+                    1,
+                    vec![
+                        window(1, "/src/app", "/bin/hx"),
+                        window(2, "/src/app/crates/foo", "/bin/hx"),
+                    ],
+                    &[],
+                )],
+            ),
+            // Window 3 was used more recently, which doesn't make it a better fit
+            os_window(
+                2,
+                vec![tab(2, vec![window(3, "/src/app", "/bin/hx")], &[3])],
+            ),
+        ]);
+        let resolver = WorkspaceResolver::new(&windows).origin(None);
+        let ids = |path: &str| {
+            resolver
+                .ambiguous(&AbsolutePath::try_from(path).unwrap())
+                .iter()
+                .map(|candidate| candidate.window.id.0)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids("/src/app/lib.rs"), vec![3, 1]);
+        assert_eq!(ids("/src/app/crates/foo/src/lib.rs"), Vec::<u32>::new());
+        assert_eq!(ids("/src/elsewhere/lib.rs"), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn test_editors_lists_every_helix_window() {
+        let resolver = WorkspaceResolver::new(&test_fixture::LS_OUTPUT);
+
+        assert_eq!(
+            resolver
+                .editors()
+                .iter()
+                .map(|(tab, window)| (tab.title.as_str(), window.id))
+                .collect::<Vec<_>>(),
+            vec![("kitty @ ls", WindowId(1))]
+        );
+    }
+
+    #[test]
+    fn test_tagged_project_wins_over_working_directories() {
+        let project = create_project(&[(".git/HEAD", "")]);
+        let root = project.path().to_string_lossy().to_string();
+        let name = project.path().file_name().unwrap().to_string_lossy();
+        let windows = OsWindows(vec![os_window(
+            1,
+            vec![tab(
+                1,
+                vec![
+                    window(1, &format!("{root}/src"), "/bin/hx"),
+                    window(2, "/somewhere/else", "/bin/hx")
+                        .tagged(&[("felis_role", "editor"), ("felis_project", &name)]),
+                    window(3, &format!("{root}/src"), "/bin/hx")
+                        .tagged(&[("felis_project", "other")]),
+                    window(4, &format!("{root}/src"), "/bin/hx").tagged(&[("felis_role", "shell")]),
+                    // The tag alone doesn't make vim helix
+                    window(5, &format!("{root}/src"), "/bin/vim")
+                        .tagged(&[("felis_role", "editor"), ("felis_project", &name)]),
+                ],
+                &[],
+            )],
+        )]);
+        let resolver = WorkspaceResolver::new(&windows).root_markers(&[".git".to_string()]);
+        let path = format!("{root}/src/lib.rs");
+
+        assert_eq!(ranked_ids(&resolver, &path), vec![2, 1]);
+        assert_eq!(
+            resolver.candidates(&AbsolutePath::try_from(path.as_str()).unwrap())[0]
+                .score
+                .kind,
+            Match::Tagged
+        );
+    }
+
+    #[test]
+    fn test_target_selects_a_window_by_id_or_tags() {
+        let windows = OsWindows(vec![
+            os_window(
+                1,
+                vec![tab(
+                    1,
+                    vec![
+                        window(1, "/src/api", "/bin/hx").tagged(&[("felis_project", "api")]),
+                        window(2, "/src/api", "-zsh"),
+                    ],
+                    &[],
+                )],
+            ),
+            os_window(
+                2,
+                vec![tab(
+                    2,
+                    vec![window(3, "/src/api", "/bin/hx").tagged(&[("felis_project", "api")])],
+                    &[],
+                )],
+            ),
+        ]);
+        let resolver = WorkspaceResolver::new(&windows).origin(Some(WindowId(2)));
+        let target = |target: &str| {
+            resolver
+                .target(&target.parse::<WindowTarget>().unwrap())
+                .map(|window| window.id)
+        };
+
+        assert_eq!(target("3").unwrap(), WindowId(3));
+        assert_eq!(target("project=api").unwrap(), WindowId(1));
+        assert!(target("role=editor").is_err());
+        assert!(target("7").is_err());
+        assert!("foo".parse::<WindowTarget>().is_err());
+    }
+
+    #[test]
+    fn test_target_id_is_looked_up_in_the_given_instance() {
+        let instance = |id: u32, socket: &str| {
+            let mut windows = OsWindows(vec![os_window(
+                id,
+                vec![tab(id, vec![window(1, "/src/api", "/bin/hx")], &[])],
+            )]);
+            windows.tag(Some(socket));
+            windows.0
+        };
+        let windows = OsWindows(
+            [
+                instance(1, "unix:/tmp/kitty-1"),
+                instance(2, "unix:/tmp/kitty-2"),
+            ]
+            .concat(),
+        );
+        let resolver = WorkspaceResolver::new(&windows);
+        let target = |target: &str| {
+            resolver
+                .target(&target.parse::<WindowTarget>().unwrap())
+                .map(|window| window.socket.clone().unwrap())
+        };
+
+        assert_eq!(target("1").unwrap(), "unix:/tmp/kitty-1");
+        assert_eq!(target("1@unix:/tmp/kitty-1").unwrap(), "unix:/tmp/kitty-1");
+        assert_eq!(target("1@unix:/tmp/kitty-2").unwrap(), "unix:/tmp/kitty-2");
+        assert!(target("1@unix:/tmp/kitty-3").is_err());
+        assert_eq!(
+            "1@unix:/tmp/kitty-2"
+                .parse::<WindowTarget>()
+                .unwrap()
+                .to_string(),
+            "1@unix:/tmp/kitty-2"
+        );
+    }
+
+    #[test]
+    fn test_matching_prefers_helix_windows() {
+        let resolver = WorkspaceResolver::new(&test_fixture::LS_OUTPUT);
+        let matching = |expr: &str| {
+            resolver
+                .matching(&expr.parse().unwrap())
+                .map(|window| window.id)
+        };
+
+        assert_eq!(matching("cwd:/path/to/felis"), Some(WindowId(1)));
+        assert_eq!(matching("not cmdline:hx"), Some(WindowId(2)));
+        assert_eq!(matching("title:nothing"), None);
+    }
+}